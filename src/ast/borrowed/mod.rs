@@ -0,0 +1,146 @@
+//! AST borrowing the input and the rules
+//!
+//! It's the same as ```ast::spanned::Node```, but the values are slices
+//! of the input and the rule names are the ones on the set of rules.
+//! No allocation for them
+//!
+//! On inputs that are not a ```&str``` (bytes, segments...), the values
+//! are allocated
+//!
+//! Convert it with ```into_spanned``` or ```into_node``` when an owned AST
+//! is needed
+//!
+//! ```
+//! #[macro_use]  extern crate dynparser;
+//! use dynparser::{ast, parse_borrowed};
+//!
+//! fn main() {
+//!     let rules = rules!{
+//!        "main"   =>  and!(lit!("a"), ref_rule!("b")),
+//!        "b"      =>  rep!(dot!(), 1)
+//!     };
+//!
+//!     let text = "abcd".to_string();
+//!     let ast = parse_borrowed(&text, &rules).unwrap();
+//!     match ast {
+//!         ast::borrowed::Node::Rule((name, ref nodes, _)) => {
+//!             assert_eq!(name, "main");
+//!             assert_eq!(nodes.len(), 2);
+//!         }
+//!         _ => panic!("expected rule"),
+//!     }
+//!     assert_eq!(
+//!         ast.into_node(),
+//!         ast::Node::Rule((
+//!             "main".to_string(),
+//!             vec![
+//!                 ast::Node::Val("a".to_string()),
+//!                 ast::Node::Rule((
+//!                     "b".to_string(),
+//!                     vec![
+//!                         ast::Node::Val("b".to_string()),
+//!                         ast::Node::Val("c".to_string()),
+//!                         ast::Node::Val("d".to_string()),
+//!                     ]
+//!                 ))
+//!             ]
+//!         ))
+//!     );
+//! }
+//! ```
+
+use crate::ast;
+use crate::ast::spanned::{self, Span};
+use crate::parser::expression::Rules;
+use std::borrow::Cow;
+
+// -------------------------------------------------------------------------------------
+//  T Y P E S
+
+/// Information of a node, borrowing the input and the rules
+#[derive(Debug, PartialEq, Clone)]
+pub enum Node<'a> {
+    /// The node is terminal (atom) with a value
+    Val((Cow<'a, str>, Span)),
+    /// The node is not terminal (rule)
+    /// with a name and a vec of nodes
+    Rule((&'a str, Vec<Node<'a>>, Span)),
+    /// Reached end of file
+    EOF,
+    /// Input skipped recovering from an error,
+    /// with the error description
+    Error((String, Span)),
+}
+
+impl<'a> From<Node<'a>> for spanned::Node {
+    fn from(node: Node<'a>) -> Self {
+        node.into_spanned()
+    }
+}
+
+impl<'a> From<Node<'a>> for ast::Node {
+    fn from(node: Node<'a>) -> Self {
+        node.into_node()
+    }
+}
+
+impl<'a> Node<'a> {
+    /// Get the span of the node
+    /// Node::EOF has no span
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            Node::Val((_, span)) => Some(span),
+            Node::Rule((_, _, span)) => Some(span),
+            Node::Error((_, span)) => Some(span),
+            Node::EOF => None,
+        }
+    }
+
+    /// Owned AST, with the spans
+    pub fn into_spanned(self) -> spanned::Node {
+        match self {
+            Node::EOF => spanned::Node::EOF,
+            Node::Val((v, span)) => spanned::Node::Val((v.into_owned(), span)),
+            Node::Error((e, span)) => spanned::Node::Error((e, span)),
+            Node::Rule((n, vn, span)) => spanned::Node::Rule((
+                n.to_string(),
+                vn.into_iter().map(Node::into_spanned).collect(),
+                span,
+            )),
+        }
+    }
+
+    /// Owned AST, without the spans
+    pub fn into_node(self) -> ast::Node {
+        match self {
+            Node::EOF => ast::Node::EOF,
+            Node::Val((v, _)) => ast::Node::Val(v.into_owned()),
+            Node::Error((e, _)) => ast::Node::Error(e),
+            Node::Rule((n, vn, _)) => {
+                ast::Node::Rule((n.to_string(), vn.into_iter().map(Node::into_node).collect()))
+            }
+        }
+    }
+
+    //  The rule names are taken from the rules
+    pub(crate) fn from_spanned(node: spanned::Node, rules: Rules<'a>) -> Self {
+        match node {
+            spanned::Node::EOF => Node::EOF,
+            spanned::Node::Val((v, span)) => Node::Val((Cow::Owned(v), span)),
+            spanned::Node::Error((e, span)) => Node::Error((e, span)),
+            spanned::Node::Rule((n, vn, span)) => {
+                let name = rules
+                    .get(&n)
+                    .map(|rule| rule.name)
+                    .expect("rule on AST not on the rules");
+                Node::Rule((
+                    name,
+                    vn.into_iter()
+                        .map(|n| Node::from_spanned(n, rules))
+                        .collect(),
+                    span,
+                ))
+            }
+        }
+    }
+}
@@ -32,6 +32,8 @@ pub enum Node {
     EndRule(String),
     /// Reached end of file
     EOF,
+    /// Input skipped recovering from an error
+    Error(String),
 }
 
 impl ast::Node {
@@ -83,6 +85,7 @@ impl ast::Node {
             match next {
                 ast::Node::EOF => acc,
                 ast::Node::Val(v) => acc.ipush(Node::Val(v.clone())),
+                ast::Node::Error(e) => acc.ipush(Node::Error(e.clone())),
                 ast::Node::Rule((n, vn)) => {
                     let acc = acc.ipush(Node::BeginRule(n.to_string()));
                     let acc = vn.iter().fold(acc, |facc, n| flatten_acc(facc, n));
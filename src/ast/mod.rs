@@ -5,7 +5,9 @@
 use idata::cont::IVec;
 use std::result::Result;
 
+pub mod borrowed;
 pub mod flat;
+pub mod spanned;
 
 // -------------------------------------------------------------------------------------
 //  T Y P E S
@@ -33,7 +35,7 @@ pub fn error(desc: &str, ast_context: Option<&str>) -> Error {
 }
 
 /// Information of a node
-#[derive(Debug, PartialEq)]
+#[derive(Debug, PartialEq, Clone)]
 pub enum Node {
     /// The node is terminal (atom) with a name
     Val(String),
@@ -42,6 +44,9 @@ pub enum Node {
     Rule((String, Vec<Node>)),
     /// Reached end of file
     EOF,
+    /// Input skipped recovering from an error,
+    /// with the error description
+    Error(String),
 }
 
 impl Node {
@@ -87,6 +92,7 @@ impl Node {
         match self {
             Node::EOF => Node::EOF,
             Node::Val(v) => Node::Val(v.clone()),
+            Node::Error(e) => Node::Error(e.clone()),
             Node::Rule((n, vn)) => Node::Rule((n.clone(), prune_vn(vn))),
         }
     }
@@ -140,6 +146,7 @@ impl Node {
         match self {
             Node::EOF => Node::EOF,
             Node::Val(v) => Node::Val(v.clone()),
+            Node::Error(e) => Node::Error(e.clone()),
             Node::Rule((n, vn)) => Node::Rule((n.clone(), pthr_vn(vn, nodes2keep))),
         }
     }
@@ -183,6 +190,7 @@ impl Node {
 
             match (n, get_val(&nodes)) {
                 (Node::EOF, _) => nodes.ipush(Node::EOF),
+                (Node::Error(e), _) => nodes.ipush(Node::Error(e.clone())),
                 (Node::Val(ref v), ref prev_v) => match concat_v(v, prev_v) {
                     Some(c) => {
                         let (_, nodes) = nodes.ipop();
@@ -203,6 +211,7 @@ impl Node {
         match self {
             Node::EOF => Node::EOF,
             Node::Val(v) => Node::Val(v.clone()),
+            Node::Error(e) => Node::Error(e.clone()),
             Node::Rule((n, vn)) => Node::Rule((n.clone(), compact_nodes(vn))),
         }
     }
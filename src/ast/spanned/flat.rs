@@ -0,0 +1,91 @@
+//! Flat AST keeping the spans
+//!
+//! Look into ```ast::flat``` for the details
+
+use crate::ast::{self, spanned::Span};
+use idata::cont::IVec;
+
+// -------------------------------------------------------------------------------------
+//  T Y P E S
+
+/// Information of a node when ast has been flattened, with the span
+#[derive(Debug, PartialEq, Clone)]
+pub enum Node {
+    /// The node is terminal (atom) with a name
+    Val((String, Span)),
+    /// Starts a rule
+    BeginRule((String, Span)),
+    /// Ends a rule
+    EndRule((String, Span)),
+    /// Reached end of file
+    EOF,
+    /// Input skipped recovering from an error
+    Error((String, Span)),
+}
+
+impl Node {
+    /// Get the span of the node
+    /// Node::EOF has no span
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            Node::Val((_, span)) => Some(span),
+            Node::BeginRule((_, span)) => Some(span),
+            Node::EndRule((_, span)) => Some(span),
+            Node::Error((_, span)) => Some(span),
+            Node::EOF => None,
+        }
+    }
+
+    /// Remove the span
+    pub fn into_node(self) -> ast::flat::Node {
+        match self {
+            Node::Val((v, _)) => ast::flat::Node::Val(v),
+            Node::BeginRule((n, _)) => ast::flat::Node::BeginRule(n),
+            Node::EndRule((n, _)) => ast::flat::Node::EndRule(n),
+            Node::EOF => ast::flat::Node::EOF,
+            Node::Error((e, _)) => ast::flat::Node::Error(e),
+        }
+    }
+}
+
+impl ast::spanned::Node {
+    /// Same as ```ast::Node::flatten```, keeping the spans
+    ///
+    /// BeginRule and EndRule will have the span of the full rule
+    ///
+    /// ```
+    ///    use dynparser::ast::spanned::{self, flat};
+    ///
+    ///    let span = spanned::Span::default();
+    ///    let ast = spanned::Node::Rule((
+    ///        "first".to_string(),
+    ///        vec![spanned::Node::Val(("hello".to_string(), span.clone()))],
+    ///        span.clone(),
+    ///    ));
+    ///
+    ///    assert!(
+    ///        ast.flatten()
+    ///            == vec![
+    ///                flat::Node::BeginRule(("first".to_string(), span.clone())),
+    ///                flat::Node::Val(("hello".to_string(), span.clone())),
+    ///                flat::Node::EndRule(("first".to_string(), span.clone())),
+    ///            ]
+    ///    )
+    ///```
+    pub fn flatten(&self) -> Vec<Node> {
+        fn flatten_acc(acc: Vec<Node>, next: &ast::spanned::Node) -> Vec<Node> {
+            match next {
+                ast::spanned::Node::EOF => acc,
+                ast::spanned::Node::Val(v) => acc.ipush(Node::Val(v.clone())),
+                ast::spanned::Node::Error(e) => acc.ipush(Node::Error(e.clone())),
+                ast::spanned::Node::Rule((n, vn, span)) => {
+                    let acc = acc.ipush(Node::BeginRule((n.to_string(), span.clone())));
+                    let acc = vn.iter().fold(acc, flatten_acc);
+                    acc.ipush(Node::EndRule((n.to_string(), span.clone())))
+                }
+            }
+        }
+
+        flatten_acc(vec![], self)
+    }
+}
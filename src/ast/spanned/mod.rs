@@ -0,0 +1,217 @@
+//! AST with the source span of every node
+//!
+//! It's the same as ```ast::Node```, but any node knows from
+//! where to where it was on the input.
+//!
+//! Useful to report errors once the input has been parsed
+//! (i.e. undefined variable at line 12)
+//!
+//! ```
+//! #[macro_use]  extern crate dynparser;
+//! use dynparser::{ast, parse_spanned, parser::Options};
+//!
+//! fn main() {
+//!     let rules = rules!{
+//!        "main"   =>  and!(lit!("a"), ref_rule!("b")),
+//!        "b"      =>  lit!("bcd")
+//!     };
+//!
+//!     let ast = parse_spanned("abcd", &rules, &Options::new()).unwrap();
+//!     match ast {
+//!         ast::spanned::Node::Rule((_, nodes, span)) => {
+//!             assert_eq!(span.start.byte, 0);
+//!             assert_eq!(span.end.byte, 4);
+//!             assert_eq!(nodes[1].span().unwrap().start.col, 1);
+//!         }
+//!         _ => panic!("expected rule"),
+//!     }
+//! }
+//! ```
+
+use crate::ast;
+use crate::parser::Possition;
+use idata::cont::IVec;
+
+pub mod flat;
+
+// -------------------------------------------------------------------------------------
+//  T Y P E S
+
+/// Location of a node on the input
+#[derive(Debug, PartialEq, Clone)]
+pub struct Span {
+    /// Where the node starts
+    pub start: Possition,
+    /// Where the node ends (not included)
+    pub end: Possition,
+}
+
+/// Information of a node with the span on source
+#[derive(Debug, PartialEq, Clone)]
+pub enum Node {
+    /// The node is terminal (atom) with a name
+    Val((String, Span)),
+    /// The node is not terminal (rule)
+    /// with a name and a vec of nodes
+    Rule((String, Vec<Node>, Span)),
+    /// Reached end of file
+    EOF,
+    /// Input skipped recovering from an error,
+    /// with the error description
+    Error((String, Span)),
+}
+
+impl Span {
+    pub(crate) fn new(start: Possition, end: Possition) -> Self {
+        Span { start, end }
+    }
+}
+
+impl Default for Span {
+    fn default() -> Self {
+        Span::new(Possition::init(), Possition::init())
+    }
+}
+
+impl From<Node> for ast::Node {
+    fn from(node: Node) -> Self {
+        node.into_node()
+    }
+}
+
+impl Node {
+    /// Get the span of the node
+    /// Node::EOF has no span
+    pub fn span(&self) -> Option<&Span> {
+        match self {
+            Node::Val((_, span)) => Some(span),
+            Node::Rule((_, _, span)) => Some(span),
+            Node::Error((_, span)) => Some(span),
+            Node::EOF => None,
+        }
+    }
+
+    /// Remove the spans
+    pub fn into_node(self) -> ast::Node {
+        match self {
+            Node::EOF => ast::Node::EOF,
+            Node::Val((v, _)) => ast::Node::Val(v),
+            Node::Error((e, _)) => ast::Node::Error(e),
+            Node::Rule((n, vn, _)) => {
+                ast::Node::Rule((n, vn.into_iter().map(Node::into_node).collect()))
+            }
+        }
+    }
+
+    //  Move the spans (after an edit, or parsing by chunks)
+    pub(crate) fn map_possitions<F>(self, f: &F) -> Self
+    where
+        F: Fn(&Possition) -> Possition,
+    {
+        let span = |span: Span| Span::new(f(&span.start), f(&span.end));
+        match self {
+            Node::EOF => Node::EOF,
+            Node::Val((v, sp)) => Node::Val((v, span(sp))),
+            Node::Error((e, sp)) => Node::Error((e, span(sp))),
+            Node::Rule((n, vn, sp)) => Node::Rule((
+                n,
+                vn.into_iter().map(|n| n.map_possitions(f)).collect(),
+                span(sp),
+            )),
+        }
+    }
+
+    /// Same as ```ast::Node::prune```, keeping the spans
+    /// ```
+    ///    use dynparser::ast::spanned::Node;
+    ///
+    ///    let ast = Node::Val(("a".to_string(), Default::default()));
+    ///
+    ///    assert!(ast.prune(&["_"]) == ast)
+    /// ```
+    pub fn prune(&self, nodes2prune: &[&str]) -> Self {
+        let node2prune = |node: &Node| match node {
+            Node::Rule((nname, _, _)) => nodes2prune.contains(&nname.as_str()),
+            _ => false,
+        };
+        let prune_vn = |vnodes: &[Node]| {
+            vnodes.iter().fold(vec![], |acc, n| {
+                if !node2prune(n) {
+                    acc.ipush(n.prune(nodes2prune))
+                } else {
+                    acc
+                }
+            })
+        };
+        match self {
+            Node::EOF => Node::EOF,
+            Node::Val(v) => Node::Val(v.clone()),
+            Node::Error(e) => Node::Error(e.clone()),
+            Node::Rule((n, vn, span)) => Node::Rule((n.clone(), prune_vn(vn), span.clone())),
+        }
+    }
+
+    /// Same as ```ast::Node::pass_through_except```, keeping the spans
+    pub fn pass_through_except(&self, nodes2keep: &[&str]) -> Self {
+        fn pthr_vn(vnodes: &[Node], nodes2keep: &[&str]) -> Vec<Node> {
+            let node2keep = |node: &Node| match node {
+                Node::Rule((nname, _, _)) => nodes2keep.contains(&nname.as_str()),
+                _ => true,
+            };
+            vnodes.iter().fold(vec![], |acc, n| {
+                if node2keep(n) {
+                    acc.ipush(n.pass_through_except(nodes2keep))
+                } else {
+                    match n {
+                        Node::Rule((_, new_nodes, _)) => {
+                            acc.iappend(pthr_vn(new_nodes, nodes2keep))
+                        }
+                        _ => acc.ipush(n.pass_through_except(nodes2keep)),
+                    }
+                }
+            })
+        }
+        match self {
+            Node::EOF => Node::EOF,
+            Node::Val(v) => Node::Val(v.clone()),
+            Node::Error(e) => Node::Error(e.clone()),
+            Node::Rule((n, vn, span)) => {
+                Node::Rule((n.clone(), pthr_vn(vn, nodes2keep), span.clone()))
+            }
+        }
+    }
+
+    /// Same as ```ast::Node::compact```
+    ///
+    /// The span of the concatenated Val goes from the start
+    /// of the first one to the end of the last one
+    pub fn compact(&self) -> Self {
+        fn concat_nodes(nodes: Vec<Node>, n: &Node) -> Vec<Node> {
+            match (n, nodes.last()) {
+                (Node::Val((v, span)), Some(Node::Val(_))) => {
+                    let (prev, nodes) = nodes.ipop();
+                    match prev {
+                        Some(Node::Val((pv, pspan))) => nodes.ipush(Node::Val((
+                            format!("{}{}", pv, v),
+                            Span::new(pspan.start, span.end.clone()),
+                        ))),
+                        _ => nodes,
+                    }
+                }
+                (Node::Rule((n, vn, span)), _) => {
+                    nodes.ipush(Node::Rule((n.clone(), compact_nodes(vn), span.clone())))
+                }
+                (n, _) => nodes.ipush(n.clone()),
+            }
+        }
+        fn compact_nodes(nodes: &[Node]) -> Vec<Node> {
+            nodes.iter().fold(vec![], concat_nodes)
+        }
+        match self {
+            Node::EOF => Node::EOF,
+            Node::Val(v) => Node::Val(v.clone()),
+            Node::Error(e) => Node::Error(e.clone()),
+            Node::Rule((n, vn, span)) => Node::Rule((n.clone(), compact_nodes(vn), span.clone())),
+        }
+    }
+}
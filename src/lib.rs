@@ -170,6 +170,92 @@ macro_rules! lit {
     }};
 }
 
+/// Create a case insensitive literal
+///
+/// The node value will be the text as it is on the input
+///
+/// example
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  ilit!("select")
+///     };
+///
+///     assert!(parse("select", &rules).is_ok());
+///     assert!(parse("SeLeCt", &rules).is_ok());
+///     assert!(parse("selec", &rules).is_err())
+/// }
+/// ```
+#[macro_export]
+macro_rules! ilit {
+    ($e:expr) => {{
+        $crate::parser::expression::Expression::Simple($crate::parser::atom::Atom::ILiteral(
+            $e.to_string(),
+        ))
+    }};
+}
+
+/// Create a set of literals, matching the longest one
+///
+/// An *or* of literals matches the first one listed
+///
+/// example
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::{ast, parse};
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  longest!("<", "<=", "<<")
+///     };
+///
+///     assert_eq!(
+///         parse("<=", &rules).unwrap(),
+///         ast::Node::Rule(("main".to_string(), vec![ast::Node::Val("<=".to_string())]))
+///     );
+///     let rules = rules!{
+///        "main"   =>  or!(lit!("<"), lit!("<="), lit!("<<"))
+///     };
+///     assert!(parse("<=", &rules).is_err())
+/// }
+/// ```
+#[macro_export]
+macro_rules! longest {
+    ($($e:expr),*) => {{
+        $crate::parser::expression::Expression::Simple($crate::parser::atom::Atom::Trie(
+            $crate::parser::atom::Trie::longest(&[$($e),*]),
+        ))
+    }};
+}
+
+/// Make case insensitive all literals on an expression
+///
+/// Referenced rules are not modified
+///
+/// example
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  nocase!(and!(lit!("select"), lit!(" "), or!(lit!("a"), lit!("b"))))
+///     };
+///
+///     assert!(parse("SELECT B", &rules).is_ok());
+///     assert!(parse("select a", &rules).is_ok())
+/// }
+/// ```
+#[macro_export]
+macro_rules! nocase {
+    ($e:expr) => {{
+        $crate::parser::expression::Expression::nocase($e)
+    }};
+}
+
 /// Generate an error
 ///
 /// example
@@ -237,6 +323,91 @@ macro_rules! dot {
     }};
 }
 
+/// Atom::SkipUntil (any text till the literal, not consumed, or the
+/// end of input)
+///
+/// Same as ```(!'literal' .)*```, scanning ahead with a substring search.
+/// The text skipped is just one value
+///
+/// example
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  and!(lit!("/*"), skip_until!("*/"), lit!("*/"))
+///     };
+///
+///     assert!(parse("/* a * comment */", &rules).is_ok());
+///     assert!(parse("/* unclosed", &rules).is_err());
+/// }
+/// ```
+#[macro_export]
+macro_rules! skip_until {
+    ($e:expr) => {{
+        $crate::parser::expression::Expression::Simple($crate::parser::atom::Atom::SkipUntil(
+            $e.to_string(),
+        ))
+    }};
+}
+
+/// Atom::Native matcher, written in rust (```@name``` on peg)
+///
+/// Look at ```SetOfRules::matcher```
+///
+/// example
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  and!(matcher!("digits"), lit!(";"))
+///     }
+///     .matcher("digits", |rest| rest.find(|c: char| !c.is_ascii_digit()));
+///
+///     assert!(parse("123;", &rules).is_ok());
+///     assert!(parse("12a;", &rules).is_err());
+/// }
+/// ```
+#[macro_export]
+macro_rules! matcher {
+    ($e:expr) => {{
+        $crate::parser::expression::Expression::Simple($crate::parser::atom::Atom::Native(
+            $crate::parser::atom::Native::Matcher($e.to_string()),
+        ))
+    }};
+}
+
+/// Atom::Native predicate, written in rust (```&?name``` on peg)
+///
+/// Look at ```SetOfRules::predicate```
+///
+/// example
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  and!(rep!(dot!(), 1), predicate!("even"))
+///     }
+///     .predicate("even", |text| text.len() % 2 == 0);
+///
+///     assert!(parse("abcd", &rules).is_ok());
+///     assert!(parse("abc", &rules).is_err());
+/// }
+/// ```
+#[macro_export]
+macro_rules! predicate {
+    ($e:expr) => {{
+        $crate::parser::expression::Expression::Simple($crate::parser::atom::Atom::Native(
+            $crate::parser::atom::Native::Predicate($e.to_string()),
+        ))
+    }};
+}
+
 /// Generate a match expression with optional characters and a list
 /// of bounds
 ///
@@ -282,6 +453,53 @@ macro_rules! dot {
 ///     assert!(parse("aabcdj", &rules).is_ok())
 /// }
 /// ```
+///
+/// With `not` at the beginning, it will match any char not
+/// in the list or bounds (`[^...]` on peg)
+///
+/// And with `class`, you can use the shorthand classes `'d'` (digits),
+/// `'w'` (word chars) and `'s'` (spaces) (`\d`, `\w` and `\s` on peg)
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  and!(
+///                         rep!(ematch!(not chlist "\n", from '0', to '9'), 1),
+///                         ematch!(class 'd'),
+///                         ematch!(not class 's')
+///                     )
+///     };
+///
+///     assert!(parse("ABC-1_", &rules).is_ok());
+///     assert!(parse("ABC-12 ", &rules).is_err())
+/// }
+/// ```
+///
+/// Unicode general categories and properties are available with `prop`
+/// (`\p{...}` on peg). Look at `parser::atom::Property` for the supported names
+///
+/// They can also be combined with a list of chars and bounds
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  and!(
+///                         ematch!(prop "XID_Start"),
+///                         rep!(ematch!(chlist "-", from2 vec![], props "XID_Continue"), 0),
+///                         ematch!(not prop "L")
+///                     )
+///     };
+///
+///     assert!(parse("año_1-ñ.", &rules).is_ok());
+///     assert!(parse("1año.", &rules).is_err())
+/// }
+/// ```
 
 #[macro_export]
 macro_rules! ematch {
@@ -302,6 +520,66 @@ macro_rules! ematch {
         let amatch = parser::atom::Atom::Match(parser::atom::MatchRules::init($chars, $vfrom2));
         parser::expression::Expression::Simple(amatch)
     }};
+
+    (class $class:expr) => {{
+        use $crate::parser;
+
+        let mrules = parser::atom::MatchRules::shorthand($class)
+            .expect("unknown shorthand class, expected 'd', 'w' or 's'");
+        parser::expression::Expression::Simple(parser::atom::Atom::Match(mrules))
+    }};
+
+    (not chlist $chars:expr, $(from $from:expr,  to $to:expr),*) => {{
+        use $crate::parser;
+        let v: Vec<(char, char)> = vec![$(($from, $to)),*];
+
+        let mrules = parser::atom::MatchRules::init($chars, v).negate();
+        parser::expression::Expression::Simple(parser::atom::Atom::Match(mrules))
+    }};
+
+    (not chlist $chars:expr, from2 $vfrom2:expr) => {{
+        use $crate::parser;
+
+        let mrules = parser::atom::MatchRules::init($chars, $vfrom2).negate();
+        parser::expression::Expression::Simple(parser::atom::Atom::Match(mrules))
+    }};
+
+    (prop $prop:expr) => {{
+        $crate::ematch!(chlist "", from2 vec![], props $prop)
+    }};
+
+    (chlist $chars:expr, from2 $vfrom2:expr, props $($prop:expr),+) => {{
+        use $crate::parser;
+
+        let mrules = parser::atom::MatchRules::init($chars, $vfrom2);
+        $(let mrules = mrules.add_property(
+            parser::atom::Property::by_name($prop).expect("unknown unicode property")
+        );)+
+        parser::expression::Expression::Simple(parser::atom::Atom::Match(mrules))
+    }};
+
+    (not prop $prop:expr) => {{
+        $crate::ematch!(not chlist "", from2 vec![], props $prop)
+    }};
+
+    (not chlist $chars:expr, from2 $vfrom2:expr, props $($prop:expr),+) => {{
+        use $crate::parser;
+
+        let mrules = parser::atom::MatchRules::init($chars, $vfrom2).negate();
+        $(let mrules = mrules.add_property(
+            parser::atom::Property::by_name($prop).expect("unknown unicode property")
+        );)+
+        parser::expression::Expression::Simple(parser::atom::Atom::Match(mrules))
+    }};
+
+    (not class $class:expr) => {{
+        use $crate::parser;
+
+        let mrules = parser::atom::MatchRules::shorthand($class)
+            .expect("unknown shorthand class, expected 'd', 'w' or 's'")
+            .negate();
+        parser::expression::Expression::Simple(parser::atom::Atom::Match(mrules))
+    }};
 }
 
 /// Concat expressions (and)
@@ -376,6 +654,101 @@ macro_rules! not {
     }};
 }
 
+/// positive lookahead
+///
+/// example
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  and!(peek!(lit!("a")), dot!())
+///     };
+///
+///     assert!(parse("a", &rules).is_ok());
+///     assert!(parse("b", &rules).is_err())
+/// }
+/// ```
+///
+/// peek! will not move the parsing position
+#[macro_export]
+macro_rules! peek {
+    ($e:expr) => {{
+        $crate::parser::expression::Expression::Peek(Box::new($e))
+    }};
+}
+
+/// cut. No backtracking after it
+///
+/// Once passed, a failure in the rest of the sequence will be a
+/// critical error (the other options of an `or` will not be tried)
+/// and the error message will be generated automatically
+///
+/// example
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  or!(
+///                         and!(lit!("if"), cut!(), lit!(" then")),
+///                         lit!("if else")
+///                     )
+///     };
+///
+///     assert!(parse("if then", &rules).is_ok());
+///     let err = parse("if else", &rules).err().unwrap();
+///     assert_eq!(err.descr, "expected ' then'");
+///     assert_eq!(err.pos.n, 2);
+/// }
+/// ```
+#[macro_export]
+macro_rules! cut {
+    () => {{
+        $crate::parser::expression::Expression::Cut
+    }};
+}
+
+/// recover from errors
+///
+/// If the first expression fails, the error will be registered
+/// and the input will be skipped till the second expression
+/// matches (it will not be consumed)
+///
+/// An `ast::Node::Error` will take the place of the first expression
+///
+/// example
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::{ast, parse_recovering};
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  rep!(and!(recover!(ref_rule!("num"), lit!(";")), lit!(";")), 0),
+///        "num"    =>  rep!(ematch!(chlist "", from '0', to '9'), 1)
+///     };
+///
+///     let (ast, errors) = parse_recovering("1;a;2;", &rules);
+///
+///     assert_eq!(errors.len(), 1);
+///     assert_eq!(errors[0].to_string(), "expected [0-9] at 1:3");
+///     match ast.unwrap() {
+///         ast::Node::Rule((_, nodes)) => {
+///             assert_eq!(nodes[2], ast::Node::Error("expected [0-9]".to_string()))
+///         }
+///         _ => panic!("expected a rule"),
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! recover {
+    ($e:expr, $sync:expr) => {{
+        $crate::parser::expression::Expression::Recover(Box::new($e), Box::new($sync))
+    }};
+}
+
 /// repeat expression.
 /// You have to define minimum repetitions and optionally
 /// maximum repetitions (if missing, infinite)
@@ -484,39 +857,350 @@ pub mod peg;
 ///
 
 pub fn parse(s: &str, rules: &parser::expression::SetOfRules) -> Result<ast::Node, parser::Error> {
-    parse_with_debug(s, rules, false)
+    parse_with_options(s, rules, &parser::Options::new())
 }
 
 /// Same as parser, but with debug info
 ///
-/// It will trace the rules called
+/// The error will have the rules followed till the failure
+/// (```parser::Error::parsing_rules```)
 ///
-/// It's expensive, use it just to develop and locate errors
+/// Use it just to develop and locate errors
+///
+/// To follow the full parsing, look at ```parse_with_observer```
 ///
 pub fn parse_debug(
     s: &str,
     rules: &parser::expression::SetOfRules,
 ) -> Result<ast::Node, parser::Error> {
-    parse_with_debug(s, rules, true)
+    parse_with_options(s, rules, &parser::Options::new().trace_rules(true))
 }
 
-fn parse_with_debug(
+/// Parse a string with a set of rules and a set of options
+///
+/// Look into ```parser::Options``` to see the options
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::{parse_with_options, parser::Options};
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main" => ref_rule!("3a"),
+///        "3a"   => lit!("aaa")
+///     };
+///
+///     let options = Options::new().packrat(true).no_memo("3a");
+///     assert!(parse_with_options("aaa", &rules, &options).is_ok())
+/// }
+/// ```
+pub fn parse_with_options(
     s: &str,
     rules: &parser::expression::SetOfRules,
-    debug: bool,
+    options: &parser::Options,
 ) -> Result<ast::Node, parser::Error> {
-    let (st, ast) = if debug {
-        parser::expression::parse(parser::Status::init_debug(s, &rules, debug))?
-    } else {
-        parser::expression::parse(parser::Status::init(s, &rules))?
+    parse_spanned(s, rules, options).map(ast::Node::from)
+}
+
+/// Parse a string with a set of rules and options
+///
+/// The resulting AST will have the source span of every node
+///
+/// Look into ```ast::spanned```
+///
+/// Errors recovered with ```recover!``` are also errors here.
+/// The first one will be returned
+pub fn parse_spanned(
+    s: &str,
+    rules: &parser::expression::SetOfRules,
+    options: &parser::Options,
+) -> Result<ast::spanned::Node, parser::Error> {
+    match parse_recovering_spanned(s, rules, options) {
+        (Some(ast), ref errors) if errors.is_empty() => Ok(ast),
+        (_, mut errors) => Err(errors.swap_remove(0)),
+    }
+}
+
+/// Parse a string, recovering from errors
+///
+/// It will return the AST (if any) and all the errors found.
+///
+/// Errors recovered with ```recover!``` (or `recover(...)` on peg)
+/// will not stop the parsing, and an `ast::Node::Error` will be
+/// inserted on the AST.
+///
+/// Non recovered errors will finish the parsing without AST
+///
+/// ```
+/// use dynparser::{parse_recovering, rules_from_peg};
+///
+/// let rules = rules_from_peg(
+///     r#"
+///
+///     main    =   (recover(assign, ';') ';')*
+///     assign  =   [a-z]+ '=' [0-9]+
+///
+///     "#,
+/// )
+/// .unwrap();
+///
+/// let (ast, errors) = parse_recovering("a=1;b=;c=3;d", &rules);
+///
+/// assert!(ast.is_some());
+/// assert_eq!(errors.len(), 2);
+/// assert_eq!(errors[0].to_string(), "expected [0-9] at 1:7");
+/// assert_eq!(errors[1].to_string(), "expected ';' at 1:13");
+/// ```
+pub fn parse_recovering(
+    s: &str,
+    rules: &parser::expression::SetOfRules,
+) -> (Option<ast::Node>, Vec<parser::Error>) {
+    parse_recovering_with_options(s, rules, &parser::Options::new())
+}
+
+/// Same as ```parse_recovering``` but with a set of options
+pub fn parse_recovering_with_options(
+    s: &str,
+    rules: &parser::expression::SetOfRules,
+    options: &parser::Options,
+) -> (Option<ast::Node>, Vec<parser::Error>) {
+    let (ast, errors) = parse_recovering_spanned(s, rules, options);
+    (ast.map(ast::Node::from), errors)
+}
+
+/// Same as ```parse_recovering_with_options``` keeping the spans
+///
+/// The span of an `ast::spanned::Node::Error` is the skipped input
+pub fn parse_recovering_spanned(
+    s: &str,
+    rules: &parser::expression::SetOfRules,
+    options: &parser::Options,
+) -> (Option<ast::spanned::Node>, Vec<parser::Error>) {
+    let (ast, errors) = parse_recovering_status(parser::Status::init_options(s, rules, options));
+    (ast.map(ast::borrowed::Node::into_spanned), errors)
+}
+
+/// Parse a string without copying the values and the rule names
+/// on the AST
+///
+/// Look into ```ast::borrowed```
+pub fn parse_borrowed<'a>(
+    s: &'a str,
+    rules: &'a parser::expression::SetOfRules,
+) -> Result<ast::borrowed::Node<'a>, parser::Error> {
+    parse_borrowed_with_options(s, rules, &parser::DEFAULT_OPTIONS)
+}
+
+/// Same as ```parse_borrowed``` but with a set of options
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::{ast::borrowed::Node, parse_borrowed_with_options, parser::Options};
+/// use std::borrow::Cow;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  rep!(ematch!(chlist "", from 'a', to 'z'), 1)
+///     };
+///
+///     let options = Options::new();
+///     let ast = parse_borrowed_with_options("abc", &rules, &options).unwrap();
+///     match ast {
+///         Node::Rule((_, nodes, _)) => match nodes[0] {
+///             Node::Val((Cow::Borrowed(v), _)) => assert_eq!(v, "a"),
+///             _ => panic!("expected a borrowed value"),
+///         },
+///         _ => panic!("expected rule"),
+///     }
+/// }
+/// ```
+pub fn parse_borrowed_with_options<'a>(
+    s: &'a str,
+    rules: &'a parser::expression::SetOfRules,
+    options: &'a parser::Options,
+) -> Result<ast::borrowed::Node<'a>, parser::Error> {
+    first_error(parse_recovering_status(parser::Status::init_options(
+        s, rules, options,
+    )))
+}
+
+/// Parse an ```Input``` (bytes, a text on segments...) with a set of options
+///
+/// Look into ```parser::input```
+///
+/// ```
+/// use dynparser::{parse_input, parser::Options, rules_from_peg};
+///
+/// let rules = rules_from_peg(
+///     r#"
+///
+///     main    =   "\x89PNG"b [\x00-\xff]*
+///
+///     "#,
+/// )
+/// .unwrap();
+///
+/// let png: &[u8] = &[0x89, b'P', b'N', b'G', 0x0d, 0x0a, 0xff];
+/// assert!(parse_input(png, &rules, &Options::new()).is_ok());
+/// assert!(parse_input(&png[1..], &rules, &Options::new()).is_err());
+/// ```
+pub fn parse_input<I: parser::input::Input>(
+    input: I,
+    rules: &parser::expression::SetOfRules,
+    options: &parser::Options,
+) -> Result<ast::Node, parser::Error> {
+    parse_input_spanned(input, rules, options).map(ast::Node::from)
+}
+
+/// Same as ```parse_input``` keeping the spans
+pub fn parse_input_spanned<I: parser::input::Input>(
+    input: I,
+    rules: &parser::expression::SetOfRules,
+    options: &parser::Options,
+) -> Result<ast::spanned::Node, parser::Error> {
+    first_error(parse_recovering_status(parser::Status::init_input(
+        input, rules, options,
+    )))
+    .map(ast::borrowed::Node::into_spanned)
+}
+
+//  With trace_rules, the rules on the failure are added to the errors.
+//  The diagnostics (and the input not consumed) get them when found
+pub(crate) fn parse_recovering_status(
+    status: parser::Status,
+) -> (Option<ast::borrowed::Node>, Vec<parser::Error>) {
+    if !status.options.trace_rules {
+        return parse_status(status);
+    }
+
+    let failure = std::rc::Rc::new(std::cell::RefCell::new(
+        parser::observer::FailureStack::new(),
+    ));
+    let (ast, mut errors) = parse_status(status.set_trace(failure.clone()));
+    if ast.is_none() {
+        for e in &mut errors {
+            e.parsing_rules = failure.borrow().rules().to_vec();
+        }
+    }
+    (ast, errors)
+}
+
+pub(crate) fn first_error<T>(result: (Option<T>, Vec<parser::Error>)) -> Result<T, parser::Error> {
+    match result {
+        (Some(ast), ref errors) if errors.is_empty() => Ok(ast),
+        (_, mut errors) => Err(errors.swap_remove(0)),
+    }
+}
+
+/// Parse a string calling an observer entering and leaving
+/// the rules
+///
+/// Look into ```parser::observer```
+pub fn parse_with_observer(
+    s: &str,
+    rules: &parser::expression::SetOfRules,
+    options: &parser::Options,
+    observer: &mut dyn parser::observer::ParseObserver,
+) -> Result<ast::Node, parser::Error> {
+    let observer = std::rc::Rc::new(std::cell::RefCell::new(observer));
+    let status = parser::Status::init_options(s, rules, options).set_observer(observer);
+    match parse_status(status) {
+        (Some(ast), ref errors) if errors.is_empty() => Ok(ast.into()),
+        (_, mut errors) => Err(errors.swap_remove(0)),
+    }
+}
+
+pub(crate) fn parse_status(
+    status: parser::Status,
+) -> (Option<ast::borrowed::Node>, Vec<parser::Error>) {
+    match parser::expression::parse(status) {
+        Err(e) => (None, vec![e]),
+        Ok((st, ast)) => {
+            let mut errors = st.diagnostics.clone();
+            if st.consumed() != parser::input::Input::len(&st.text2parse) {
+                errors.push(not_consumed_error(&st));
+            }
+            (Some(ast), errors)
+        }
+    }
+}
+
+pub(crate) fn not_consumed_error(st: &parser::Status) -> parser::Error {
+    st.expected("end of input");
+    let mut error = match (st.farthest_error(), st.potential_error.as_deref().cloned()) {
+        (Some(e), _) | (None, Some(e)) => e,
+        (None, None) => parser::Error::from_status_normal(st, "not consumed full input"),
     };
-    match (st.pos.n == s.len(), st.potential_error.clone()) {
-        (true, _) => Ok(ast),
-        (false, Some(e)) => Err(e),
-        (false, None) => Err(parser::Error::from_status_normal(
-            &st,
-            "not consumed full input",
-        )),
+    st.add_trace(&mut error);
+    error
+}
+
+/// Parse a string starting on a specific rule (instead of `main`)
+///
+/// Useful to test a sub-grammar in isolation
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::{parse, parse_rule};
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main" => rep!(ref_rule!("stmt"), 1),
+///        "stmt" => and!(lit!("a"), lit!(";"))
+///     };
+///
+///     assert!(parse_rule("a;", &rules, "stmt").is_ok());
+///     assert!(parse_rule("a;a;", &rules, "stmt").is_err());
+///     assert!(parse("a;a;", &rules).is_ok());
+/// }
+/// ```
+pub fn parse_rule(
+    s: &str,
+    rules: &parser::expression::SetOfRules,
+    rule_name: &str,
+) -> Result<ast::Node, parser::Error> {
+    parse_with_options(s, rules, &parser::Options::new().start_rule(rule_name))
+}
+
+/// Parse the begining of a string, starting on a specific rule
+///
+/// It's not necessary to consume the full input
+///
+/// It will return the AST and the number of bytes consumed
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse_prefix;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "number" => rep!(ematch!(chlist "", from '0', to '9'), 1)
+///     };
+///
+///     let (_ast, consumed) = parse_prefix("123 + 4", &rules, "number").unwrap();
+///     assert_eq!(consumed, 3);
+/// }
+/// ```
+pub fn parse_prefix(
+    s: &str,
+    rules: &parser::expression::SetOfRules,
+    rule_name: &str,
+) -> Result<(ast::Node, usize), parser::Error> {
+    parse_prefix_with_options(s, rules, &parser::Options::new().start_rule(rule_name))
+}
+
+/// Same as ```parse_prefix``` but with a set of options
+///
+/// The starting rule will be the one configured on options
+pub fn parse_prefix_with_options(
+    s: &str,
+    rules: &parser::expression::SetOfRules,
+    options: &parser::Options,
+) -> Result<(ast::Node, usize), parser::Error> {
+    let (st, ast) = parser::expression::parse(parser::Status::init_options(s, rules, options))?;
+    match st.diagnostics.first() {
+        Some(e) => Err(e.clone()),
+        None => Ok((ast.into(), st.consumed())),
     }
 }
 
@@ -0,0 +1,448 @@
+//! Static information about a set of rules
+//!
+//! It's computed before parsing. Used to detect left recursion, and
+//! to know the chars an alternative could start with
+
+use crate::parser::atom::Atom;
+use crate::parser::expression::{Expression, MultiExpr, Rules, SetOfRules};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, OnceLock};
+
+#[cfg(test)]
+mod test;
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  T Y P E S
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+/// Rules with left recursion (direct or indirect)
+///
+/// The *leaders* will be parsed growing the seed.
+/// Any cycle of left calls contains a leader.
+///
+/// The *involved* rules are on a cycle of left calls. They don't have
+/// to be memoized, because while a leader is growing, the result
+/// for same position can change
+#[derive(Debug, Default)]
+pub(crate) struct LeftRec {
+    pub(crate) leaders: HashSet<String>,
+    pub(crate) involved: HashSet<String>,
+}
+
+/// Chars an expression could start with (FIRST set)
+///
+/// The ranges are sorted and not overlapped
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct First {
+    pub(crate) ranges: Vec<(char, char)>,
+    /// It could match at the end of input
+    pub(crate) end: bool,
+    /// It could match without consuming input
+    pub(crate) empty: bool,
+}
+
+/// FIRST sets of the alternatives on every *or*
+///
+/// None for the alternatives that can't be known statically
+/// (lookahead, cut, recover, left recursion...)
+///
+/// The key is the address of the alternatives. They don't move, and
+/// the rules can't be changed without analyzing them again
+#[derive(Debug, Default)]
+pub(crate) struct FirstSets(HashMap<usize, Vec<Option<First>>>);
+
+/// Analysis of a ```SetOfRules```, done on first parse and kept with it
+#[derive(Debug, Default)]
+pub(crate) struct Analyzed(OnceLock<(Arc<LeftRec>, Arc<FirstSets>)>);
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  A P I
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+impl LeftRec {
+    pub(crate) fn is_leader(&self, rule_name: &str) -> bool {
+        !self.leaders.is_empty() && self.leaders.contains(rule_name)
+    }
+
+    pub(crate) fn is_involved(&self, rule_name: &str) -> bool {
+        !self.involved.is_empty() && self.involved.contains(rule_name)
+    }
+}
+
+impl Analyzed {
+    pub(crate) fn get(&self, rules: &SetOfRules) -> (Arc<LeftRec>, Arc<FirstSets>) {
+        self.0
+            .get_or_init(|| {
+                let left_rec = left_recursion(rules);
+                let first = first_sets(Rules::Set(rules), &left_rec);
+                (Arc::new(left_rec), Arc::new(first))
+            })
+            .clone()
+    }
+}
+
+pub(crate) fn left_recursion(rules: &SetOfRules) -> LeftRec {
+    let nullables = nullable_rules(rules);
+    let left_calls: HashMap<&str, HashSet<&str>> = rules
+        .0
+        .iter()
+        .map(|(name, expr)| {
+            let mut calls = HashSet::new();
+            add_left_calls(expr, &nullables, &mut calls);
+            (name.as_str(), calls)
+        })
+        .collect();
+
+    let involved: HashSet<&str> = left_calls
+        .keys()
+        .filter(|name| reach(&left_calls, name, name, &HashSet::new()))
+        .cloned()
+        .collect();
+
+    //  remove leaders from the graph till no cycles
+    let mut names: Vec<&str> = involved.iter().cloned().collect();
+    names.sort();
+    let mut leaders = HashSet::new();
+    while let Some(name) = names
+        .iter()
+        .find(|name| !leaders.contains(*name) && reach(&left_calls, name, name, &leaders))
+    {
+        leaders.insert(*name);
+    }
+
+    LeftRec {
+        leaders: leaders.iter().map(|n| n.to_string()).collect(),
+        involved: involved.iter().map(|n| n.to_string()).collect(),
+    }
+}
+
+//-----------------------------------------------------------------------
+//  SUPPORT
+//-----------------------------------------------------------------------
+
+/// Rules that can succeed without consuming input
+fn nullable_rules(rules: &SetOfRules) -> HashSet<&str> {
+    let mut nullables = HashSet::new();
+    loop {
+        let new_nullables: Vec<&str> = rules
+            .0
+            .iter()
+            .filter(|(name, expr)| {
+                !nullables.contains(name.as_str()) && is_nullable(expr, &nullables)
+            })
+            .map(|(name, _)| name.as_str())
+            .collect();
+        if new_nullables.is_empty() {
+            return nullables;
+        }
+        nullables.extend(new_nullables);
+    }
+}
+
+fn is_nullable(expr: &Expression, nullables: &HashSet<&str>) -> bool {
+    match expr {
+        Expression::Simple(Atom::Literal(l)) | Expression::Simple(Atom::ILiteral(l)) => {
+            l.is_empty()
+        }
+        Expression::Simple(Atom::EOF) | Expression::Simple(Atom::SkipUntil(_)) => true,
+        //  a matcher could consume nothing
+        Expression::Simple(Atom::Native(_)) => true,
+        Expression::Simple(Atom::Trie(trie)) => trie.literal(0).is_some(),
+        Expression::Simple(_) => false,
+        Expression::And(mexpr) => mexpr.0.iter().all(|e| is_nullable(e, nullables)),
+        Expression::Or(mexpr) => mexpr.0.iter().any(|e| is_nullable(e, nullables)),
+        Expression::Not(_) | Expression::Peek(_) | Expression::Cut => true,
+        Expression::Recover(_, _) => true,
+        Expression::Repeat(rep) => rep.min.0 == 0 || is_nullable(&rep.expression, nullables),
+        Expression::RuleName(name) => nullables.contains(name.as_str()),
+        //  a set of rules can't have them (the grammar is analyzed
+        //  before resolving the references)
+        Expression::RuleRef(_) => unreachable!("resolved reference on a set of rules"),
+    }
+}
+
+/// Rules that could be called without consuming input
+fn add_left_calls<'a>(
+    expr: &'a Expression,
+    nullables: &HashSet<&str>,
+    calls: &mut HashSet<&'a str>,
+) {
+    match expr {
+        Expression::Simple(_) => (),
+        Expression::And(mexpr) => {
+            for e in &mexpr.0 {
+                add_left_calls(e, nullables, calls);
+                if !is_nullable(e, nullables) {
+                    break;
+                }
+            }
+        }
+        Expression::Or(mexpr) => mexpr
+            .0
+            .iter()
+            .for_each(|e| add_left_calls(e, nullables, calls)),
+        Expression::Not(e) | Expression::Peek(e) => add_left_calls(e, nullables, calls),
+        Expression::Repeat(rep) => add_left_calls(&rep.expression, nullables, calls),
+        Expression::RuleName(name) => {
+            calls.insert(name);
+        }
+        Expression::RuleRef(_) => unreachable!("resolved reference on a set of rules"),
+        Expression::Recover(e, sync) => {
+            add_left_calls(e, nullables, calls);
+            add_left_calls(sync, nullables, calls);
+        }
+        Expression::Cut => (),
+    }
+}
+
+/// Is it possible to go from `from` to `to` without passing
+/// through `excluded`?
+fn reach(
+    graph: &HashMap<&str, HashSet<&str>>,
+    from: &str,
+    to: &str,
+    excluded: &HashSet<&str>,
+) -> bool {
+    let mut visited = HashSet::new();
+    let mut pending: Vec<&str> = vec![from];
+    while let Some(current) = pending.pop() {
+        for &next in graph.get(current).into_iter().flatten() {
+            if next == to {
+                return true;
+            }
+            if !excluded.contains(next) && visited.insert(next) {
+                pending.push(next);
+            }
+        }
+    }
+    false
+}
+
+impl First {
+    fn chars(ranges: Vec<(char, char)>) -> Self {
+        First {
+            ranges,
+            ..First::default()
+        }
+    }
+
+    fn any_char() -> Self {
+        First::chars(vec![('\0', char::MAX)])
+    }
+
+    /// The expression will fail without consuming
+    ///
+    /// ```next``` is None at the end of input
+    pub(crate) fn rejects(&self, next: Option<char>) -> bool {
+        if self.empty {
+            return false;
+        }
+        match next {
+            None => !self.end,
+            Some(ch) => {
+                let i = self.ranges.partition_point(|&(_, to)| to < ch);
+                !matches!(self.ranges.get(i), Some(&(from, _)) if from <= ch)
+            }
+        }
+    }
+
+    fn union(mut self, other: &First) -> Self {
+        self.ranges.extend(other.ranges.iter().cloned());
+        self.ranges.sort();
+        let mut ranges: Vec<(char, char)> = Vec::with_capacity(self.ranges.len());
+        for (from, to) in self.ranges {
+            match ranges.last_mut() {
+                Some(last) if (from as u32) <= (last.1 as u32).saturating_add(1) => {
+                    last.1 = last.1.max(to)
+                }
+                _ => ranges.push((from, to)),
+            }
+        }
+        First {
+            ranges,
+            end: self.end || other.end,
+            empty: self.empty || other.empty,
+        }
+    }
+}
+
+impl FirstSets {
+    pub(crate) fn get(&self, mexpr: &MultiExpr) -> Option<&[Option<First>]> {
+        self.at(key(mexpr))
+    }
+
+    //  By the key kept on the compiled rules (```vm```)
+    pub(crate) fn at(&self, key: usize) -> Option<&[Option<First>]> {
+        self.0.get(&key).map(Vec::as_slice)
+    }
+}
+
+pub(crate) fn key(mexpr: &MultiExpr) -> usize {
+    mexpr.0.as_ptr() as usize
+}
+
+pub(crate) fn first_sets(rules: Rules, left_rec: &LeftRec) -> FirstSets {
+    let mut ctx = FirstCtx {
+        rules,
+        left_rec,
+        by_rule: HashMap::new(),
+        sets: HashMap::new(),
+    };
+    for rule in rules.all() {
+        ctx.add_or_sets(rule.expression);
+    }
+    FirstSets(ctx.sets)
+}
+
+struct FirstCtx<'a, 'r> {
+    rules: Rules<'a>,
+    left_rec: &'r LeftRec,
+    by_rule: HashMap<&'a str, Option<First>>,
+    sets: HashMap<usize, Vec<Option<First>>>,
+}
+
+impl<'a, 'r> FirstCtx<'a, 'r> {
+    //  Look for every *or* on the expression
+    fn add_or_sets(&mut self, expr: &'a Expression) {
+        match expr {
+            Expression::Or(mexpr) => {
+                let firsts = mexpr.0.iter().map(|e| self.first(e)).collect::<Vec<_>>();
+                if firsts.iter().any(Option::is_some) {
+                    self.sets.insert(key(mexpr), firsts);
+                }
+                mexpr.0.iter().for_each(|e| self.add_or_sets(e));
+            }
+            Expression::And(mexpr) => mexpr.0.iter().for_each(|e| self.add_or_sets(e)),
+            Expression::Not(e) | Expression::Peek(e) => self.add_or_sets(e),
+            Expression::Repeat(rep) => self.add_or_sets(&rep.expression),
+            Expression::Recover(e, sync) => {
+                self.add_or_sets(e);
+                self.add_or_sets(sync);
+            }
+            Expression::Simple(_)
+            | Expression::RuleName(_)
+            | Expression::RuleRef(_)
+            | Expression::Cut => (),
+        }
+    }
+
+    fn first(&mut self, expr: &'a Expression) -> Option<First> {
+        match expr {
+            Expression::Simple(atom) => first_atom(atom),
+            Expression::And(mexpr) => {
+                let mut first = First {
+                    empty: true,
+                    ..First::default()
+                };
+                for e in &mexpr.0 {
+                    let f = self.first(e)?;
+                    first = First {
+                        empty: first.empty && f.empty,
+                        ..first.union(&f)
+                    };
+                    if !first.empty {
+                        break;
+                    }
+                }
+                Some(first)
+            }
+            Expression::Or(mexpr) => mexpr
+                .0
+                .iter()
+                .try_fold(First::default(), |acc, e| Some(acc.union(&self.first(e)?))),
+            //  an empty match would loop
+            Expression::Repeat(rep) => match self.first(&rep.expression)? {
+                First { empty: true, .. } => None,
+                first => Some(First {
+                    empty: rep.min.0 == 0,
+                    ..first
+                }),
+            },
+            Expression::RuleName(name) => {
+                let rule = self.rules.get(name)?;
+                self.first_rule(rule.name, rule.expression)
+            }
+            Expression::RuleRef(rule_ref) => {
+                let rule = self.rules.at(rule_ref.index())?;
+                self.first_rule(rule.name, rule.expression)
+            }
+            Expression::Not(_) | Expression::Peek(_) | Expression::Cut => None,
+            Expression::Recover(_, _) => None,
+        }
+    }
+
+    fn first_rule(&mut self, name: &'a str, expr: &'a Expression) -> Option<First> {
+        if let Some(first) = self.by_rule.get(name) {
+            return first.clone();
+        }
+        if self.left_rec.is_involved(name) {
+            return None;
+        }
+        //  a cycle here is a left recursion (not expected)
+        self.by_rule.insert(name, None);
+        let first = self.first(expr);
+        self.by_rule.insert(name, first.clone());
+        first
+    }
+}
+
+fn first_atom(atom: &Atom) -> Option<First> {
+    match atom {
+        Atom::Literal(l) => Some(match l.chars().next() {
+            Some(ch) => First::chars(vec![(ch, ch)]),
+            None => First {
+                empty: true,
+                ..First::default()
+            },
+        }),
+        //  non ascii chars could have an ascii lowercase
+        Atom::ILiteral(l) => Some(match l.chars().next() {
+            Some(ch) if ch.is_ascii() => First::chars(vec![
+                (ch.to_ascii_lowercase(), ch.to_ascii_lowercase()),
+                (ch.to_ascii_uppercase(), ch.to_ascii_uppercase()),
+                ('\u{80}', char::MAX),
+            ])
+            .union(&First::default()),
+            Some(_) => First::any_char(),
+            None => First {
+                empty: true,
+                ..First::default()
+            },
+        }),
+        Atom::Match(mrules) if !mrules.is_negated() && mrules.properties().is_empty() => {
+            let ranges = mrules
+                .chars()
+                .chars()
+                .map(|ch| (ch, ch))
+                .chain(mrules.ranges().iter().cloned())
+                .filter(|(from, to)| from <= to)
+                .collect();
+            Some(First::default().union(&First::chars(ranges)))
+        }
+        Atom::Match(_) | Atom::Dot => Some(First::any_char()),
+        Atom::EOF => Some(First {
+            end: true,
+            ..First::default()
+        }),
+        Atom::Trie(trie) => Some(First {
+            ranges: trie.first_chars().map(|ch| (ch, ch)).collect(),
+            end: false,
+            empty: trie.literal(0).is_some(),
+        }),
+        //  natives are unknown, they never reject
+        Atom::SkipUntil(_) | Atom::Native(_) => Some(First {
+            end: true,
+            empty: true,
+            ..First::any_char()
+        }),
+        Atom::Error(_) => None,
+    }
+}
@@ -0,0 +1,130 @@
+//-----------------------------------------------------------------------
+//
+//  mod parser::analysis  TEST
+//
+//-----------------------------------------------------------------------
+
+use super::{first_sets, left_recursion, First};
+use crate::parser::atom::Atom;
+use crate::parser::expression::{Expression, Rules, SetOfRules};
+
+#[test]
+fn test_no_left_recursion() {
+    let rules = rules! {
+        "main" => and!(ref_rule!("a"), ref_rule!("main")),
+        "a"    => lit!("a")
+    };
+    let lr = left_recursion(&rules);
+
+    assert!(lr.leaders.is_empty());
+    assert!(lr.involved.is_empty());
+}
+
+#[test]
+fn test_direct_left_recursion() {
+    let rules = rules! {
+        "main" => ref_rule!("expr"),
+        "expr" => or!(and!(ref_rule!("expr"), lit!("+"), ref_rule!("num")), ref_rule!("num")),
+        "num"  => lit!("1")
+    };
+    let lr = left_recursion(&rules);
+
+    assert!(lr.is_leader("expr"));
+    assert_eq!(lr.leaders.len(), 1);
+    assert_eq!(lr.involved.len(), 1);
+}
+
+#[test]
+fn test_left_recursion_after_nullable() {
+    let rules = rules! {
+        "expr" => and!(rep!(lit!(" "), 0), ref_rule!("expr"), lit!("+")),
+        "_"    => rep!(lit!(" "), 0)
+    };
+    assert!(left_recursion(&rules).is_leader("expr"));
+
+    let rules = rules! {
+        "expr" => and!(ref_rule!("_"), ref_rule!("expr"), lit!("+")),
+        "_"    => rep!(lit!(" "), 0)
+    };
+    assert!(left_recursion(&rules).is_leader("expr"));
+
+    let rules = rules! {
+        "expr" => and!(lit!(" "), ref_rule!("expr"), lit!("+"))
+    };
+    assert!(!left_recursion(&rules).is_leader("expr"));
+}
+
+#[test]
+fn test_indirect_left_recursion() {
+    let rules = rules! {
+        "a" => or!(and!(ref_rule!("b"), lit!("a")), lit!("x")),
+        "b" => or!(and!(ref_rule!("a"), lit!("b")), lit!("y"))
+    };
+    let lr = left_recursion(&rules);
+
+    assert_eq!(lr.leaders.len(), 1);
+    assert!(lr.is_involved("a"));
+    assert!(lr.is_involved("b"));
+}
+
+//  First sets of the alternatives on the *or* of a rule
+fn or_firsts(rules: &SetOfRules, rule_name: &str) -> Vec<Option<First>> {
+    let lr = left_recursion(rules);
+    let firsts = first_sets(Rules::Set(rules), &lr);
+    match rules.0.get(rule_name) {
+        Some(Expression::Or(mexpr)) => firsts.get(mexpr).unwrap().to_vec(),
+        _ => panic!("expected or on {}", rule_name),
+    }
+}
+
+#[test]
+fn test_first_sets() {
+    let rules = rules! {
+        "main" => or!(
+            and!(ref_rule!("_"), lit!("x")),
+            ref_rule!("num"),
+            and!(not!(lit!("a")), dot!()),
+            rep!(lit!("z"), 0),
+            Expression::Simple(Atom::EOF)
+        ),
+        "num"  => rep!(ematch!(chlist "_", from '0', to '9', from 'a', to 'f'), 1),
+        "_"    => rep!(lit!(" "), 0)
+    };
+    let firsts = or_firsts(&rules, "main");
+
+    assert_eq!(
+        firsts[0],
+        Some(First {
+            ranges: vec![(' ', ' '), ('x', 'x')],
+            end: false,
+            empty: false
+        })
+    );
+    assert_eq!(
+        firsts[1].as_ref().unwrap().ranges,
+        vec![('0', '9'), ('_', '_'), ('a', 'f')]
+    );
+    assert_eq!(firsts[2], None);
+    assert!(firsts[3].as_ref().unwrap().empty);
+    assert!(firsts[4].as_ref().unwrap().end);
+
+    let num = firsts[1].as_ref().unwrap();
+    assert!(num.rejects(Some('g')));
+    assert!(num.rejects(None));
+    assert!(!num.rejects(Some('5')));
+    assert!(!num.rejects(Some('_')));
+    assert!(!firsts[3].as_ref().unwrap().rejects(Some('a')));
+    assert!(!firsts[4].as_ref().unwrap().rejects(None));
+}
+
+#[test]
+fn test_first_sets_left_recursion() {
+    let rules = rules! {
+        "expr" => or!(and!(ref_rule!("expr"), lit!("+"), ref_rule!("num")), ref_rule!("num")),
+        "num"  => lit!("1")
+    };
+    let firsts = or_firsts(&rules, "expr");
+
+    assert_eq!(firsts[0], None);
+    assert!(firsts[1].as_ref().unwrap().rejects(Some('+')));
+}
@@ -1,9 +1,19 @@
-use crate::ast;
+use crate::ast::{borrowed::Node, spanned::Span};
 /// Support for minimum expressions elements
 /// Here we have the parser and types for non dependencies kind
-use crate::parser::{ErrPriority, Error, Result, Status};
+use crate::parser::{input, ErrPriority, Error, Status, Text};
+use std::borrow::Cow;
+use std::rc::Rc;
 use std::result;
 
+mod native;
+mod trie;
+mod unicode;
+pub use self::native::Native;
+pub(crate) use self::native::Natives;
+pub use self::trie::Trie;
+pub use self::unicode::Property;
+
 #[cfg(test)]
 mod test;
 
@@ -16,10 +26,12 @@ mod test;
 //-----------------------------------------------------------------------
 
 /// This is a minimum expression element
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub enum Atom {
     /// Literal string
     Literal(String),
+    /// Literal string ignoring case
+    ILiteral(String),
     /// Character matches a list of chars or a list of ranges
     Match(MatchRules),
     /// Indicates an error.
@@ -29,13 +41,27 @@ pub enum Atom {
     Dot,
     /// End Of File
     EOF,
+    /// Set of literals. Look at ```Trie```
+    Trie(Trie),
+    /// Any text till the literal (not consumed) or the end of input.
+    /// Same as ```(!'literal' .)*```, but just one value for the text
+    SkipUntil(String),
+    /// Matcher or predicate written in Rust. Look at ```Native```
+    Native(Native),
 }
 
 /// contains a char slice and a (char,char) slice
 /// if char matches one in char slice -> OK
 /// if char matches between tuple in elems slice -> OK
-#[derive(Debug)]
-pub struct MatchRules(pub(crate) String, pub(crate) Vec<(char, char)>);
+/// if char has one of the unicode properties -> OK
+/// if it's negated, the result is the opposite (EOF is never a match)
+#[derive(Debug, Clone)]
+pub struct MatchRules(
+    pub(crate) String,
+    pub(crate) Vec<(char, char)>,
+    pub(crate) bool,
+    pub(crate) Vec<Property>,
+);
 
 impl MatchRules {
     ///  get a reference to set of chars of match rule
@@ -47,6 +73,16 @@ impl MatchRules {
     pub fn ranges(&self) -> &Vec<(char, char)> {
         &self.1
     }
+
+    ///  true if the match rule is negated (`[^...]`)
+    pub fn is_negated(&self) -> bool {
+        self.2
+    }
+
+    ///  get a reference to unicode properties of match rule
+    pub fn properties(&self) -> &Vec<Property> {
+        &self.3
+    }
 }
 
 //-----------------------------------------------------------------------
@@ -57,24 +93,153 @@ impl MatchRules {
 //-----------------------------------------------------------------------
 //-----------------------------------------------------------------------
 
-pub(crate) fn parse<'a>(status: Status<'a>, atom: &'a Atom) -> Result<'a> {
+//  No node for the predicates, and for nothing skipped (as the repetition
+//  it replaces)
+pub(crate) fn parse<'a>(
+    status: Status<'a>,
+    atom: &'a Atom,
+) -> result::Result<(Status<'a>, Option<Node<'a>>), Error> {
+    let mut status = status;
+    let node = parse_in_place(&mut status, atom)?;
+    Ok((status, node))
+}
+
+//  On errors, the possition of the status is not restored
+pub(crate) fn parse_in_place<'a>(
+    status: &mut Status<'a>,
+    atom: &'a Atom,
+) -> result::Result<Option<Node<'a>>, Error> {
+    let from = status.pos.byte;
+    parse_after(status, atom, from)
+}
+
+//  ```from``` is where the previous element of the sequence started.
+//  A predicate checks the text from there
+pub(crate) fn parse_after<'a>(
+    status: &mut Status<'a>,
+    atom: &'a Atom,
+    from: usize,
+) -> result::Result<Option<Node<'a>>, Error> {
+    let farthest = if status.quiet {
+        None
+    } else {
+        Some(status.pos.clone())
+    };
+    let result = match atom {
+        Atom::Literal(literal) => parse_literal(status, &literal).map(Some),
+        Atom::ILiteral(literal) => parse_iliteral(status, &literal).map(Some),
+        Atom::Error(error) => parse_error(status, &error).map(Some),
+        Atom::Match(ref match_rules) => parse_match(status, &match_rules).map(Some),
+        Atom::Dot => parse_dot(status).map(Some),
+        Atom::EOF => parse_eof(status).map(Some),
+        Atom::Trie(trie) => parse_trie(status, trie).map(Some),
+        Atom::SkipUntil(literal) => parse_skip_until(status, literal),
+        Atom::Native(Native::Matcher(name)) => parse_matcher(status, name).map(Some),
+        Atom::Native(Native::Predicate(name)) => parse_predicate(status, name, from).map(|_| None),
+    };
+    match (&result, farthest) {
+        //  it registers every literal
+        _ if matches!(atom, Atom::Trie(_)) => (),
+        (Err(e), Some(pos)) if e.priority == ErrPriority::Normal => status
+            .shared
+            .farthest
+            .borrow_mut()
+            .add(&pos, || descr(atom)),
+        _ => (),
+    }
+    result
+}
+
+/// Short description of the atom, used on error messages
+pub(crate) fn descr(atom: &Atom) -> String {
     match atom {
-        Atom::Literal(literal) => parse_literal(status, &literal),
-        Atom::Error(error) => parse_error(&status, &error),
-        Atom::Match(ref match_rules) => parse_match(status, &match_rules),
-        Atom::Dot => parse_dot(status),
-        Atom::EOF => parse_eof(status),
+        Atom::Literal(l) => descr_literal(l),
+        Atom::ILiteral(l) => format!("'{}'i", l.escape_debug()),
+        Atom::Match(mrules) => format!(
+            "[{}{}{}{}]",
+            if mrules.2 { "^" } else { "" },
+            mrules.0.escape_debug(),
+            mrules
+                .1
+                .iter()
+                .map(|(f, t)| format!("{}-{}", f.escape_debug(), t.escape_debug()))
+                .collect::<String>(),
+            mrules
+                .3
+                .iter()
+                .map(|p| format!("{:?}", p))
+                .collect::<String>(),
+        ),
+        Atom::Dot => "any char".to_string(),
+        Atom::EOF => "end of input".to_string(),
+        Atom::Error(e) => e.clone(),
+        Atom::Trie(trie) => descr_trie(trie),
+        Atom::SkipUntil(l) => format!("any char till {}", descr_literal(l)),
+        Atom::Native(Native::Matcher(name)) => format!("@{}", name),
+        Atom::Native(Native::Predicate(name)) => format!("&?{}", name),
+    }
+}
+
+fn descr_trie(trie: &Trie) -> String {
+    trie.literals()
+        .iter()
+        .map(|l| descr_literal(l))
+        .collect::<Vec<_>>()
+        .join(" or ")
+}
+
+fn descr_literal(literal: &str) -> String {
+    format!("'{}'", literal.escape_debug())
+}
+
+/// Register the literals failed before ```found``` (all of them if none)
+///
+/// As an *or* of literals, the ones listed before the literal found
+/// failed. With longest, all of them failed, or none
+pub(crate) fn register_trie(status: &Status, trie: &Trie, found: Option<usize>) {
+    let failed = match found {
+        Some(_) if trie.is_longest() => 0,
+        Some(found) => found,
+        None => trie.literals().len(),
+    };
+    for literal in &trie.literals()[..failed] {
+        status.expected(&descr_literal(literal));
     }
 }
 
 impl MatchRules {
     /// Create a MatchRules instance based on string and bounds
     pub fn init(s: &str, bounds: Vec<(char, char)>) -> Self {
-        MatchRules(s.to_string(), bounds)
+        MatchRules(s.to_string(), bounds, false, vec![])
+    }
+    /// Add an unicode property to match
+    pub fn add_property(mut self, property: Property) -> Self {
+        self.3.push(property);
+        self
+    }
+    /// Shorthand classes
+    ///
+    /// `'d'` digits, `'w'` word chars (ASCII letters, digits and `_`)
+    /// and `'s'` spaces
+    pub fn shorthand(class: char) -> Option<Self> {
+        match class {
+            'd' => Some(MatchRules::init("", vec![('0', '9')])),
+            'w' => Some(MatchRules::init(
+                "_",
+                vec![('a', 'z'), ('A', 'Z'), ('0', '9')],
+            )),
+            's' => Some(MatchRules::init(" \t\n\r\x0B\x0C", vec![])),
+            _ => None,
+        }
+    }
+    /// It will match any char not matching the original rules
+    pub fn negate(mut self) -> Self {
+        self.2 = !self.2;
+        self
     }
     #[allow(dead_code)] //  used in tests
     pub(crate) fn new() -> Self {
-        MatchRules("".to_string(), vec![])
+        MatchRules("".to_string(), vec![], false, vec![])
     }
     #[allow(dead_code)] //  used in tests
     pub(crate) fn with_chars(mut self, chrs: &str) -> Self {
@@ -95,33 +260,239 @@ impl MatchRules {
 //-----------------------------------------------------------------------
 
 macro_rules! ok {
-    ($st:expr, $val:expr) => {
-        Ok(($st, ast::Node::Val($val.to_owned())))
-    };
+    ($start:expr, $st:expr, $val:expr) => {{
+        let span = Span::new($start, $st.pos.clone());
+        Ok(Node::Val(($val.into(), span)))
+    }};
 }
 
-fn parse_literal<'a>(mut status: Status<'a>, literal: &'a str) -> Result<'a> {
+fn parse_literal<'a>(status: &mut Status<'a>, literal: &'a str) -> result::Result<Node<'a>, Error> {
+    let start = status.pos.clone();
     for ch in literal.chars() {
-        status = parse_char(status, ch).map_err(|st| {
-            Error::from_status_normal(&st, &format!("expected literal: <{}>", literal))
-        })?;
+        if !parse_char(status, ch) {
+            return Err(Error::from_status_normal(
+                status,
+                &format!("expected literal: <{}>", literal),
+            ));
+        }
     }
-    ok!(status, literal)
+    ok!(start, status, literal)
 }
 
-fn parse_error<'a>(status: &Status<'a>, error: &'a str) -> Result<'a> {
-    Err(Error::from_status(&status, &error, ErrPriority::Critical))
+//  The node value is the text as it is in the input
+fn parse_iliteral<'a>(
+    status: &mut Status<'a>,
+    literal: &'a str,
+) -> result::Result<Node<'a>, Error> {
+    let start = status.pos.clone();
+    for ch in literal.chars() {
+        if !parse_char_nocase(status, ch) {
+            return Err(Error::from_status_normal(
+                status,
+                &format!("expected literal: <{}>", literal),
+            ));
+        }
+    }
+    let val = status.text2parse.value(start.byte, status.pos.byte);
+    ok!(start, status, val)
 }
 
-fn parse_dot(status: Status) -> Result {
-    let (status, ch) = status
-        .get_char()
-        .map_err(|st| Error::from_status_normal(&st, "dot"))?;
+//  The trie is walked while a better literal could be found
+fn parse_trie<'a>(status: &mut Status<'a>, trie: &'a Trie) -> result::Result<Node<'a>, Error> {
+    let start = status.pos.clone();
+    let better = |literal: usize, found: Option<usize>| match found {
+        Some(found) => trie.is_longest() || literal < found,
+        None => true,
+    };
 
-    ok!(status, ch.to_string())
+    let mut pos = start.clone();
+    let mut node = 0;
+    let mut found = trie.literal(node).map(|literal| (literal, pos.clone()));
+    while matches!(trie.below(node), Some(below) if better(below, found.as_ref().map(|f| f.0))) {
+        status.shared.counters.examined(pos.n);
+        node = match pos
+            .advance(&status.text2parse, status.options.col_mode)
+            .and_then(|ch| trie.next(node, ch))
+        {
+            Some(next) => next,
+            None => break,
+        };
+        match trie.literal(node) {
+            Some(literal) if better(literal, found.as_ref().map(|f| f.0)) => {
+                found = Some((literal, pos.clone()))
+            }
+            _ => (),
+        }
+    }
+
+    register_trie(status, trie, found.as_ref().map(|f| f.0));
+    match found {
+        Some((literal, end)) => {
+            status.pos = end;
+            ok!(start, status, trie.literals()[literal].as_str())
+        }
+        None => Err(Error::from_status_normal(
+            status,
+            &format!("expected {}", descr_trie(trie)),
+        )),
+    }
 }
 
-fn parse_match<'a>(status: Status<'a>, match_rules: &MatchRules) -> Result<'a> {
+//  Scanning with a substring search. The potential error, the chars
+//  examined and the farthest registered are the ones of
+//  ```(!'literal' .)*```
+fn parse_skip_until<'a>(
+    status: &mut Status<'a>,
+    literal: &'a str,
+) -> result::Result<Option<Node<'a>>, Error> {
+    let start = status.pos.clone();
+    let found = status.text2parse.find(start.byte, literal);
+    let end = found.unwrap_or_else(|| input::Input::len(&status.text2parse));
+    while status.pos.byte < end {
+        status
+            .pos
+            .advance(&status.text2parse, status.options.col_mode);
+    }
+
+    let error = match found {
+        Some(_) => {
+            let len = literal.chars().count();
+            if len > 0 {
+                status.shared.counters.examined(status.pos.n + len - 1);
+            }
+            Error::from_status_normal(status, "not")
+        }
+        None => {
+            status.shared.counters.examined(status.pos.n);
+            status.expected(&descr(&Atom::Dot));
+            Error::from_status_normal(status, "dot")
+        }
+    };
+    status.potential_error = Some(Rc::new(error));
+
+    if status.pos.byte == start.byte {
+        return Ok(None);
+    }
+    let val = status.text2parse.value(start.byte, status.pos.byte);
+    ok!(start, status, val).map(Some)
+}
+
+//  The matcher could look at the rest of the input, but it is
+//  expected to decide with the text consumed and the next char
+fn parse_matcher<'a>(status: &mut Status<'a>, name: &'a str) -> result::Result<Node<'a>, Error> {
+    let matcher = match status.rules.natives().matchers.get(name) {
+        Some(matcher) => matcher,
+        None => return Err(missing_native(status, "matcher", name)),
+    };
+    let start = status.pos.clone();
+    let (rest, len) = call_matcher(&status.text2parse, start.byte, matcher);
+    let len = match len {
+        Some(len) if rest.is_char_boundary(len) => len,
+        Some(len) => {
+            return Err(Error::from_status(
+                status,
+                &format!("matcher {} returned an invalid length {}", name, len),
+                ErrPriority::Critical,
+            ))
+        }
+        None => {
+            status.shared.counters.examined(start.n);
+            return Err(Error::from_status_normal(
+                status,
+                &format!("expected @{}", name),
+            ));
+        }
+    };
+    //  len is on rest, not on the input (bytes are converted to chars)
+    for _ in rest[..len].chars() {
+        if status
+            .pos
+            .advance(&status.text2parse, status.options.col_mode)
+            .is_none()
+        {
+            break;
+        }
+    }
+    status.shared.counters.examined(status.pos.n);
+
+    let val = status.text2parse.value(start.byte, status.pos.byte);
+    ok!(start, status, val)
+}
+
+//  On a ```&str``` the matcher gets the rest of the input. On other
+//  inputs it gets a part (a copy if it's not contiguous), doubled
+//  while the matcher could need more: it took all, or it didn't match
+fn call_matcher<'t>(
+    text: &'t Text,
+    from: usize,
+    matcher: &native::MatcherFn,
+) -> (Cow<'t, str>, Option<usize>) {
+    use crate::parser::input::Input;
+
+    let end = text.len();
+    let mut size = match text {
+        Text::Str(_) => end,
+        Text::Input(_) => 256,
+    };
+    loop {
+        let mut to = end.min(from.saturating_add(size));
+        while to < end && text.char_at(to).is_none() {
+            to += 1;
+        }
+        let rest = text.slice(from, to);
+        let len = matcher(&rest);
+        if to == end || matches!(len, Some(len) if len < rest.len()) {
+            return (rest, len);
+        }
+        size *= 2;
+    }
+}
+
+fn parse_predicate<'a>(
+    status: &mut Status<'a>,
+    name: &'a str,
+    from: usize,
+) -> result::Result<(), Error> {
+    let predicate = match status.rules.natives().predicates.get(name) {
+        Some(predicate) => predicate,
+        None => return Err(missing_native(status, "predicate", name)),
+    };
+    if predicate(&status.text2parse.value(from, status.pos.byte)) {
+        Ok(())
+    } else {
+        Err(Error::from_status_normal(
+            status,
+            &format!("expected &?{}", name),
+        ))
+    }
+}
+
+fn missing_native(status: &Status, kind: &str, name: &str) -> Error {
+    Error::from_status(
+        status,
+        &format!("Missing {}: {}", kind, name),
+        ErrPriority::Critical,
+    )
+}
+
+fn parse_error<'a>(status: &Status<'a>, error: &'a str) -> result::Result<Node<'a>, Error> {
+    Err(Error::from_status(status, error, ErrPriority::Critical))
+}
+
+fn parse_dot<'a>(status: &mut Status<'a>) -> result::Result<Node<'a>, Error> {
+    let start = status.pos.clone();
+    if status.next_char().is_none() {
+        return Err(Error::from_status_normal(status, "dot"));
+    }
+
+    let val = status.text2parse.value(start.byte, status.pos.byte);
+    ok!(start, status, val)
+}
+
+fn parse_match<'a>(
+    status: &mut Status<'a>,
+    match_rules: &MatchRules,
+) -> result::Result<Node<'a>, Error> {
     let match_char = |ch: char| -> bool {
         if match_rules.0.find(ch).is_some() {
             true
@@ -131,64 +502,65 @@ fn parse_match<'a>(status: Status<'a>, match_rules: &MatchRules) -> Result<'a> {
                     return true;
                 }
             }
-            false
+            match_rules.3.iter().any(|p| p.matches(ch))
         }
     };
 
-    status
-        .get_char()
-        .and_then(|(st, ch)| {
-            if match_char(ch) {
-                ok!(st, ch.to_string())
-            } else {
-                Err(st)
-            }
-        })
-        .map_err(|st| {
-            Error::from_status_normal(
-                &st,
-                &format!("match. expected {} {:?}", match_rules.0, match_rules.1),
-            )
-        })
+    let start = status.pos.clone();
+    match status.next_char() {
+        Some(ch) if match_char(ch) != match_rules.2 => {
+            let val = status.text2parse.value(start.byte, status.pos.byte);
+            ok!(start, status, val)
+        }
+        _ => {
+            let negated = if match_rules.2 { "not " } else { "" };
+            Err(Error::from_status_normal(
+                status,
+                &format!(
+                    "match. expected {}{} {:?}{}",
+                    negated,
+                    match_rules.0,
+                    match_rules.1,
+                    match_rules
+                        .3
+                        .iter()
+                        .fold(String::new(), |acc, p| format!("{} {:?}", acc, p))
+                ),
+            ))
+        }
+    }
 }
 
-fn parse_eof(status: Status) -> Result {
-    match status.get_char() {
-        Ok((st, _ch)) => Err(Error::from_status_normal(&st, "expected EOF")),
-        Err(st) => ok!(st, "EOF"),
+fn parse_eof<'a>(status: &mut Status<'a>) -> result::Result<Node<'a>, Error> {
+    let start = status.pos.clone();
+    match status.next_char() {
+        Some(_) => Err(Error::from_status_normal(status, "expected EOF")),
+        None => ok!(start, status, "EOF"),
     }
 }
 
-fn parse_char(status: Status, ch: char) -> result::Result<Status, Status> {
-    let (st, got_ch) = status.get_char()?;
-    if ch == got_ch {
-        Ok(st)
-    } else {
-        Err(st)
+fn parse_char(status: &mut Status, ch: char) -> bool {
+    status.next_char() == Some(ch)
+}
+
+fn parse_char_nocase(status: &mut Status, ch: char) -> bool {
+    match status.next_char() {
+        Some(got_ch) => ch == got_ch || ch.to_lowercase().eq(got_ch.to_lowercase()),
+        None => false,
     }
 }
 
 impl<'a> Status<'a> {
-    fn get_char(mut self) -> result::Result<(Self, char), Self> {
-        match self.it_parsing.next() {
+    pub(crate) fn get_char(mut self) -> result::Result<(Self, char), Self> {
+        match self.next_char() {
             None => Err(self),
-            Some(ch) => {
-                self.pos.n += 1;
-                match ch {
-                    '\n' => {
-                        self.pos.col = 0;
-                        self.pos.row += 1;
-                        self.pos.start_line = self.pos.n;
-                    }
-                    '\r' => {
-                        self.pos.col = 0;
-                    }
-                    _ => {
-                        self.pos.col += 1;
-                    }
-                }
-                Ok((self, ch))
-            }
+            Some(ch) => Ok((self, ch)),
         }
     }
+
+    /// Move after next char (if any)
+    pub(crate) fn next_char(&mut self) -> Option<char> {
+        self.shared.counters.examined(self.pos.n);
+        self.pos.advance(&self.text2parse, self.options.col_mode)
+    }
 }
@@ -0,0 +1,50 @@
+//! Matchers and predicates written in Rust
+//!
+//! They are registered by name on ```SetOfRules``` and referenced from
+//! the rules (```@name``` and ```&?name``` on peg)
+
+use std::collections::HashMap;
+
+/// A matcher or a predicate, by name
+#[derive(Debug, Clone)]
+pub enum Native {
+    /// Called with the rest of the input. It returns the length (bytes)
+    /// consumed, or ```None``` if it doesn't match
+    Matcher(String),
+    /// Called with the text matched by the previous element of the
+    /// sequence (empty if there is no one). It doesn't consume
+    Predicate(String),
+}
+
+pub(crate) type MatcherFn = Box<dyn Fn(&str) -> Option<usize> + Send + Sync>;
+pub(crate) type PredicateFn = Box<dyn Fn(&str) -> bool + Send + Sync>;
+
+/// Matchers and predicates registered
+#[derive(Default)]
+pub(crate) struct Natives {
+    pub(crate) matchers: HashMap<String, MatcherFn>,
+    pub(crate) predicates: HashMap<String, PredicateFn>,
+}
+
+impl std::fmt::Debug for Natives {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Natives")
+            .field("matchers", &self.matchers.keys().collect::<Vec<_>>())
+            .field("predicates", &self.predicates.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Natives {
+    /// On duplicated names, the ones on ```self``` win
+    pub(crate) fn merge(self, natives: Self) -> Self {
+        Natives {
+            matchers: natives.matchers.into_iter().chain(self.matchers).collect(),
+            predicates: natives
+                .predicates
+                .into_iter()
+                .chain(self.predicates)
+                .collect(),
+        }
+    }
+}
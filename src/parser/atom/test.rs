@@ -4,7 +4,50 @@
 //
 //-----------------------------------------------------------------------
 use super::Status;
-use super::{parse_dot, parse_eof, parse_literal, parse_match, MatchRules};
+use super::{MatchRules, Trie};
+use crate::ast::borrowed::Node;
+use crate::parser::{Error, Result};
+
+//  The parsers work in place. Here they take and return the status
+fn by_value<'a, F>(mut status: Status<'a>, parse: F) -> Result<'a>
+where
+    F: FnOnce(&mut Status<'a>) -> std::result::Result<Node<'a>, Error>,
+{
+    let node = parse(&mut status)?;
+    Ok((status, node))
+}
+
+fn parse_literal<'a>(status: Status<'a>, literal: &'a str) -> Result<'a> {
+    by_value(status, |st| super::parse_literal(st, literal))
+}
+
+fn parse_iliteral<'a>(status: Status<'a>, literal: &'a str) -> Result<'a> {
+    by_value(status, |st| super::parse_iliteral(st, literal))
+}
+
+fn parse_match<'a>(status: Status<'a>, match_rules: &MatchRules) -> Result<'a> {
+    by_value(status, |st| super::parse_match(st, match_rules))
+}
+
+fn parse_trie<'a>(status: Status<'a>, trie: &'a Trie) -> Result<'a> {
+    by_value(status, |st| super::parse_trie(st, trie))
+}
+
+fn parse_skip_until<'a>(
+    mut status: Status<'a>,
+    literal: &'a str,
+) -> std::result::Result<(Status<'a>, Option<Node<'a>>), Error> {
+    let node = super::parse_skip_until(&mut status, literal)?;
+    Ok((status, node))
+}
+
+fn parse_dot(status: Status) -> Result {
+    by_value(status, super::parse_dot)
+}
+
+fn parse_eof(status: Status) -> Result {
+    by_value(status, super::parse_eof)
+}
 
 #[test]
 fn test_parse_literal_ok() {
@@ -49,6 +92,20 @@ fn test_parse_literal_fail_short_text2parse() {
     assert!(parse_literal(status_init, "abcdefghij").is_err());
 }
 
+#[test]
+fn test_parse_iliteral() {
+    let rules = rules!{};
+    let status_init = Status::init("SeLeCt *", &rules);
+    let (status_end, node) = parse_iliteral(status_init, "select").ok().unwrap();
+
+    assert_eq!(status_end.pos.n, 6);
+    assert_eq!(node.into_node(), crate::ast::Node::Val("SeLeCt".to_string()));
+
+    let status_init = Status::init("SELEC *", &rules);
+    let err = parse_iliteral(status_init, "Select").err().unwrap();
+    assert_eq!(err.descr, "expected literal: <Select>");
+}
+
 #[test]
 fn test_parse_literal_with_new_line() {
     let rules = rules!{};
@@ -144,3 +201,177 @@ fn test_parse_match_eof_error() {
 
     assert!(parse_eof(status).is_err());
 }
+
+#[test]
+fn test_parse_non_ascii_possition() {
+    let rules = rules!{};
+    let status = Status::init("año€x", &rules);
+
+    let (status, _) = parse_literal(status, "año€").ok().unwrap();
+    assert_eq!(status.pos.n, 4);
+    assert_eq!(status.pos.byte, "año€".len());
+    assert_eq!(status.pos.col, 4);
+
+    assert!(parse_literal(status, "y").is_err());
+}
+
+#[test]
+fn test_parse_new_lines() {
+    let rules = rules!{};
+    let status = Status::init("a\r\nb\nc\rd", &rules);
+
+    let (status, _) = parse_literal(status, "a\r\n").ok().unwrap();
+    assert_eq!(status.pos.row, 1);
+    assert_eq!(status.pos.col, 0);
+    assert_eq!(status.pos.start_line, 3);
+
+    let (status, _) = parse_literal(status, "b\n").ok().unwrap();
+    assert_eq!(status.pos.row, 2);
+
+    let (status, _) = parse_literal(status, "c\r").ok().unwrap();
+    assert_eq!(status.pos.row, 3);
+    assert_eq!(status.pos.start_line, 7);
+
+    let (status, _) = parse_dot(status).ok().unwrap();
+    assert_eq!(status.pos.row, 3);
+    assert_eq!(status.pos.col, 1);
+}
+
+#[test]
+fn test_parse_col_mode() {
+    use crate::parser::{ColMode, Options};
+
+    let rules = rules!{};
+    let text = "\t😀a";
+
+    let options = Options::new().col_mode(ColMode::Chars);
+    let status = Status::init_options(text, &rules, &options);
+    let (status, _) = parse_literal(status, text).ok().unwrap();
+    assert_eq!(status.pos.col, 3);
+
+    let options = Options::new().col_mode(ColMode::Utf16);
+    let status = Status::init_options(text, &rules, &options);
+    let (status, _) = parse_literal(status, text).ok().unwrap();
+    assert_eq!(status.pos.col, 4);
+
+    let options = Options::new().col_mode(ColMode::TabWidth(4));
+    let status = Status::init_options(text, &rules, &options);
+    let (status, _) = parse_literal(status, text).ok().unwrap();
+    assert_eq!(status.pos.col, 6);
+
+    let status = Status::init_options("ab\tc", &rules, &options);
+    let (status, _) = parse_literal(status, "ab\t").ok().unwrap();
+    assert_eq!(status.pos.col, 4);
+}
+
+#[test]
+fn test_parse_match_negated() {
+    let rules = rules!{};
+    let match_rules = MatchRules::new()
+        .with_chars("ab")
+        .with_bound_chars(vec![('0', '9')])
+        .negate();
+
+    let status_init = Status::init("c", &rules);
+    assert!(parse_match(status_init, &match_rules).is_ok());
+
+    let status_init = Status::init("b", &rules);
+    assert!(parse_match(status_init, &match_rules).is_err());
+
+    let status_init = Status::init("5", &rules);
+    assert!(parse_match(status_init, &match_rules).is_err());
+
+    let status_init = Status::init("", &rules);
+    assert!(parse_match(status_init, &match_rules).is_err());
+}
+
+#[test]
+fn test_parse_match_property() {
+    let rules = rules!{};
+    let match_rules = MatchRules::new()
+        .with_chars("_")
+        .add_property(super::Property::by_name("Lu").unwrap());
+
+    let status_init = Status::init("Ñ", &rules);
+    assert!(parse_match(status_init, &match_rules).is_ok());
+
+    let status_init = Status::init("_", &rules);
+    assert!(parse_match(status_init, &match_rules).is_ok());
+
+    let status_init = Status::init("ñ", &rules);
+    assert!(parse_match(status_init, &match_rules).is_err());
+}
+
+#[test]
+fn test_parse_trie_first_wins() {
+    let rules = rules!{};
+    let trie = Trie::first_wins(&["if", "i", "ifdef", "else"]);
+
+    let status_init = Status::init("ifdef", &rules);
+    let (status, _) = parse_trie(status_init, &trie).ok().unwrap();
+    assert_eq!(status.pos.n, 2);
+
+    let status_init = Status::init("id", &rules);
+    let (status, _) = parse_trie(status_init, &trie).ok().unwrap();
+    assert_eq!(status.pos.n, 1);
+    //  "if" failed before "i"
+    assert_eq!(status.shared.farthest.borrow().expected, vec!["'if'"]);
+
+    let status_init = Status::init("els", &rules);
+    let status = status_init.clone();
+    assert!(parse_trie(status_init, &trie).is_err());
+    assert_eq!(
+        status.shared.farthest.borrow().expected,
+        vec!["'if'", "'i'", "'ifdef'", "'else'"]
+    );
+}
+
+#[test]
+fn test_parse_trie_longest() {
+    let rules = rules!{};
+    let trie = Trie::longest(&["i", "if", "ifdef", ""]);
+
+    let status_init = Status::init("ifdef", &rules);
+    let (status, _) = parse_trie(status_init, &trie).ok().unwrap();
+    assert_eq!(status.pos.n, 5);
+    assert!(status.shared.farthest.borrow().expected.is_empty());
+
+    let status_init = Status::init("ifde", &rules);
+    let (status, _) = parse_trie(status_init, &trie).ok().unwrap();
+    assert_eq!(status.pos.n, 2);
+    assert_eq!(status.shared.counters.reach.get(), 5);
+
+    let status_init = Status::init("x", &rules);
+    let (status, _) = parse_trie(status_init, &trie).ok().unwrap();
+    assert_eq!(status.pos.n, 0);
+}
+
+#[test]
+fn test_parse_skip_until() {
+    let rules = rules!{};
+
+    let status_init = Status::init("añb*c*/d", &rules);
+    let (status, node) = parse_skip_until(status_init, "*/").ok().unwrap();
+    assert_eq!(status.pos.n, 5);
+    assert_eq!(status.pos.byte, 6);
+    assert_eq!(status.shared.counters.reach.get(), 7);
+    assert_eq!(status.potential_error.unwrap().descr, "not");
+    match node {
+        Some(Node::Val((val, span))) => {
+            assert_eq!(val, "añb*c");
+            assert_eq!((span.start.n, span.end.n), (0, 5));
+        }
+        _ => panic!("expected a value"),
+    }
+
+    let status_init = Status::init("a\nb*", &rules);
+    let (status, _) = parse_skip_until(status_init, "*/").ok().unwrap();
+    assert_eq!((status.pos.n, status.pos.row, status.pos.col), (4, 1, 2));
+    assert_eq!(status.potential_error.unwrap().descr, "dot");
+    assert_eq!(status.shared.farthest.borrow().expected, vec!["any char"]);
+
+    let status_init = Status::init("abc", &rules);
+    let (status, node) = parse_skip_until(status_init, "").ok().unwrap();
+    assert_eq!(status.pos.n, 0);
+    assert!(node.is_none());
+}
@@ -0,0 +1,106 @@
+//! Set of literals, matched walking a trie
+//!
+//! ```Grammar::compile``` folds the *or* of literals into it
+
+/// A set of literals
+///
+/// By default, the first literal listed matching wins (as on an *or*).
+/// With ```longest```, the longest one matching
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parse;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  and!(longest!("=", "==", "=>"), lit!(" "))
+///     };
+///
+///     assert!(parse("== ", &rules).is_ok());
+///     assert!(parse("=> ", &rules).is_ok());
+///     assert!(parse("=! ", &rules).is_err());
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Trie {
+    literals: Vec<String>,
+    /// The root is the first one
+    nodes: Vec<TrieNode>,
+    longest: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+struct TrieNode {
+    /// Sorted by char
+    next: Vec<(char, usize)>,
+    /// First literal listed ending here
+    literal: Option<usize>,
+    /// First literal listed ending below
+    below: Option<usize>,
+}
+
+impl Trie {
+    /// The first literal listed matching wins
+    pub fn first_wins(literals: &[&str]) -> Self {
+        Trie::new(literals.iter().map(|l| l.to_string()).collect(), false)
+    }
+
+    /// The longest literal matching wins
+    pub fn longest(literals: &[&str]) -> Self {
+        Trie::new(literals.iter().map(|l| l.to_string()).collect(), true)
+    }
+
+    /// The literals, in the order listed
+    pub fn literals(&self) -> &[String] {
+        &self.literals
+    }
+
+    /// The longest literal matching wins
+    pub fn is_longest(&self) -> bool {
+        self.longest
+    }
+
+    pub(crate) fn new(literals: Vec<String>, longest: bool) -> Self {
+        let mut nodes = vec![TrieNode::default()];
+        for (index, literal) in literals.iter().enumerate() {
+            let mut node = 0;
+            for ch in literal.chars() {
+                nodes[node].below.get_or_insert(index);
+                node = match nodes[node].next.binary_search_by_key(&ch, |&(c, _)| c) {
+                    Ok(i) => nodes[node].next[i].1,
+                    Err(i) => {
+                        nodes.push(TrieNode::default());
+                        let new = nodes.len() - 1;
+                        nodes[node].next.insert(i, (ch, new));
+                        new
+                    }
+                };
+            }
+            nodes[node].literal.get_or_insert(index);
+        }
+        Trie {
+            literals,
+            nodes,
+            longest,
+        }
+    }
+
+    pub(crate) fn next(&self, node: usize, ch: char) -> Option<usize> {
+        let next = &self.nodes[node].next;
+        next.binary_search_by_key(&ch, |&(c, _)| c)
+            .ok()
+            .map(|i| next[i].1)
+    }
+
+    pub(crate) fn literal(&self, node: usize) -> Option<usize> {
+        self.nodes[node].literal
+    }
+
+    pub(crate) fn below(&self, node: usize) -> Option<usize> {
+        self.nodes[node].below
+    }
+
+    pub(crate) fn first_chars(&self) -> impl Iterator<Item = char> + '_ {
+        self.nodes[0].next.iter().map(|&(ch, _)| ch)
+    }
+}
@@ -0,0 +1,110 @@
+//! Unicode properties to be used on match rules
+//!
+//! Tables are compiled in (no system ICU is required)
+
+use unicode_properties::{
+    GeneralCategory as GC, GeneralCategoryGroup as GCG, UnicodeGeneralCategory,
+};
+use unicode_xid::UnicodeXID;
+
+/// An Unicode general category, group of categories or property
+///
+/// Supported names...
+///
+/// * general categories: `Lu`, `Ll`, `Lt`, `Lm`, `Lo`, `Mn`, `Mc`, `Me`, `Nd`, `Nl`,
+///   `No`, `Pc`, `Pd`, `Ps`, `Pe`, `Pi`, `Pf`, `Po`, `Sm`, `Sc`, `Sk`, `So`,
+///   `Zs`, `Zl`, `Zp`, `Cc`, `Cf`, `Cs`, `Co`, `Cn`
+/// * groups: `L`, `M`, `N`, `P`, `S`, `Z`, `C`
+/// * properties: `XID_Start`, `XID_Continue`, `White_Space`
+///
+/// ```
+/// use dynparser::parser::atom::Property;
+///
+/// let letter = Property::by_name("L").unwrap();
+/// assert!(letter.matches('ñ'));
+/// assert!(!letter.matches('1'));
+/// assert!(Property::by_name("Unknown").is_none());
+/// ```
+#[derive(Clone, Copy)]
+pub struct Property {
+    name: &'static str,
+    check: fn(char) -> bool,
+}
+
+impl Property {
+    /// Look for a property by name
+    pub fn by_name(name: &str) -> Option<Self> {
+        PROPERTIES
+            .iter()
+            .find(|(n, _)| *n == name)
+            .map(|&(name, check)| Property { name, check })
+    }
+
+    /// Name of the property
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    /// Check if a char has the property
+    pub fn matches(&self, ch: char) -> bool {
+        (self.check)(ch)
+    }
+}
+
+impl std::fmt::Debug for Property {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, r#"\p{{{}}}"#, self.name)
+    }
+}
+
+impl PartialEq for Property {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+type Check = fn(char) -> bool;
+
+const PROPERTIES: &[(&str, Check)] = &[
+    ("L", |c| c.general_category_group() == GCG::Letter),
+    ("Lu", |c| c.general_category() == GC::UppercaseLetter),
+    ("Ll", |c| c.general_category() == GC::LowercaseLetter),
+    ("Lt", |c| c.general_category() == GC::TitlecaseLetter),
+    ("Lm", |c| c.general_category() == GC::ModifierLetter),
+    ("Lo", |c| c.general_category() == GC::OtherLetter),
+    ("M", |c| c.general_category_group() == GCG::Mark),
+    ("Mn", |c| c.general_category() == GC::NonspacingMark),
+    ("Mc", |c| c.general_category() == GC::SpacingMark),
+    ("Me", |c| c.general_category() == GC::EnclosingMark),
+    ("N", |c| c.general_category_group() == GCG::Number),
+    ("Nd", |c| c.general_category() == GC::DecimalNumber),
+    ("Nl", |c| c.general_category() == GC::LetterNumber),
+    ("No", |c| c.general_category() == GC::OtherNumber),
+    ("P", |c| c.general_category_group() == GCG::Punctuation),
+    ("Pc", |c| c.general_category() == GC::ConnectorPunctuation),
+    ("Pd", |c| c.general_category() == GC::DashPunctuation),
+    ("Ps", |c| c.general_category() == GC::OpenPunctuation),
+    ("Pe", |c| c.general_category() == GC::ClosePunctuation),
+    ("Pi", |c| c.general_category() == GC::InitialPunctuation),
+    ("Pf", |c| c.general_category() == GC::FinalPunctuation),
+    ("Po", |c| c.general_category() == GC::OtherPunctuation),
+    ("S", |c| c.general_category_group() == GCG::Symbol),
+    ("Sm", |c| c.general_category() == GC::MathSymbol),
+    ("Sc", |c| c.general_category() == GC::CurrencySymbol),
+    ("Sk", |c| c.general_category() == GC::ModifierSymbol),
+    ("So", |c| c.general_category() == GC::OtherSymbol),
+    ("Z", |c| c.general_category_group() == GCG::Separator),
+    ("Zs", |c| c.general_category() == GC::SpaceSeparator),
+    ("Zl", |c| c.general_category() == GC::LineSeparator),
+    ("Zp", |c| c.general_category() == GC::ParagraphSeparator),
+    ("C", |c| c.general_category_group() == GCG::Other),
+    ("Cc", |c| c.general_category() == GC::Control),
+    ("Cf", |c| c.general_category() == GC::Format),
+    ("Cs", |c| c.general_category() == GC::Surrogate),
+    ("Co", |c| c.general_category() == GC::PrivateUse),
+    ("Cn", |c| c.general_category() == GC::Unassigned),
+    ("XID_Start", UnicodeXID::is_xid_start),
+    ("XID_Continue", UnicodeXID::is_xid_continue),
+    //  char::is_whitespace follows the White_Space property
+    ("White_Space", char::is_whitespace),
+];
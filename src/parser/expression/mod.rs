@@ -5,10 +5,19 @@ use super::super::idata::{
     cont::IVec,
     tc::{tail_call, TailCall},
 };
-use crate::ast;
-use crate::parser::{atom, atom::Atom, ErrPriority, Error, Result, Status};
+use crate::ast::{borrowed::Node, spanned::Span};
+use crate::parser::{
+    analysis, atom,
+    atom::{Atom, Native, Natives},
+    grammar::{Grammar, RuleRef},
+    incremental::Cached,
+    input::Input,
+    vm, ErrKind, ErrPriority, Error, Farthest, Mark, MemoEntry, Memoized, Result, Status,
+};
 use std::collections::HashMap;
+use std::rc::Rc;
 use std::result;
+use std::sync::Arc;
 
 #[cfg(test)]
 mod test;
@@ -24,20 +33,38 @@ mod test;
 #[derive(Clone, Copy, Debug)]
 pub(crate) struct Started(usize);
 
-pub(crate) type ResultExpr<'a> = result::Result<(Status<'a>, Vec<ast::Node>), Error>;
+pub(crate) type ResultExpr<'a> = result::Result<(Status<'a>, Vec<Node<'a>>), Error>;
 
 /// The set of rules to be parsed
 /// Any rule has a name
 /// A rule can be registered just once
 /// The starting rule is main
+///
+/// Matchers and predicates written in Rust are registered here too, so
+/// it's built with ```SetOfRules::new``` (or the ```rules!``` macro)
+///
+/// The rules are analyzed (left recursion...) and compiled (for the
+/// VM) on first parse, and the result is kept. The map is private, so
+/// they are changed with ```add```, ```merge```, ```insert``` or
+/// ```remove```, and done again
 #[derive(Debug)]
-pub struct SetOfRules(pub HashMap<String, Expression>);
+pub struct SetOfRules(
+    pub(crate) HashMap<String, Expression>,
+    pub(crate) Natives,
+    pub(crate) analysis::Analyzed,
+    pub(crate) vm::Bytecode,
+);
 
 impl SetOfRules {
     /// Initialize a set of rules with a hashmap of <String, Expression>
     /// In general, is better to use the ```rules!``` macro
     pub fn new(mrules: HashMap<String, Expression>) -> Self {
-        SetOfRules(mrules)
+        SetOfRules(
+            mrules,
+            Natives::default(),
+            analysis::Analyzed::default(),
+            vm::Bytecode::default(),
+        )
     }
 
     /// As this is a dynamic parser, it is necessary to add rules on
@@ -68,7 +95,7 @@ impl SetOfRules {
     /// }
     /// ```
     pub fn add(mut self, name: &str, expr: Expression) -> Self {
-        self.0.insert(name.to_owned(), expr);
+        self.insert(name, expr);
         self
     }
 
@@ -100,7 +127,180 @@ impl SetOfRules {
     /// }
     /// ```
     pub fn merge(self, rules2merge: Self) -> Self {
-        SetOfRules(rules2merge.0.into_iter().chain(self.0).collect())
+        SetOfRules(
+            rules2merge.0.into_iter().chain(self.0).collect(),
+            self.1.merge(rules2merge.1),
+            analysis::Analyzed::default(),
+            vm::Bytecode::default(),
+        )
+    }
+
+    /// The rules, by name
+    pub fn rules(&self) -> &HashMap<String, Expression> {
+        &self.0
+    }
+
+    /// Same as ```add```, on a mutable set. It returns the rule
+    /// replaced (if any)
+    ///
+    /// ```
+    /// #[macro_use]  extern crate dynparser;
+    /// use dynparser::parse;
+    ///
+    /// fn main() {
+    ///     let mut rules = rules!{"main" => lit!("a")};
+    ///     assert!(parse("a", &rules).is_ok());
+    ///
+    ///     assert!(rules.insert("main", lit!("b")).is_some());
+    ///     assert!(parse("a", &rules).is_err());
+    ///     assert!(parse("b", &rules).is_ok());
+    /// }
+    /// ```
+    pub fn insert(&mut self, name: &str, expr: Expression) -> Option<Expression> {
+        self.changed();
+        self.0.insert(name.to_owned(), expr)
+    }
+
+    /// Remove a rule, returning it (if any)
+    pub fn remove(&mut self, name: &str) -> Option<Expression> {
+        self.changed();
+        self.0.remove(name)
+    }
+
+    //  The rules will be analyzed and compiled again
+    fn changed(&mut self) {
+        self.2 = analysis::Analyzed::default();
+        self.3 = vm::Bytecode::default();
+    }
+
+    /// Register a matcher written in Rust, referenced as ```@name```
+    ///
+    /// It's called with the rest of the input, and returns the length
+    /// (bytes) consumed, or ```None``` if it doesn't match. It should
+    /// decide looking at the text consumed and the next char
+    ///
+    /// ```
+    /// #[macro_use]  extern crate dynparser;
+    /// use dynparser::{parse, rules_from_peg};
+    ///
+    /// fn main() {
+    ///     let rules = rules_from_peg(
+    ///         r#"
+    ///         main    =   'port ' @u16
+    ///         "#,
+    ///     )
+    ///     .unwrap()
+    ///     .matcher("u16", |rest| {
+    ///         let len = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+    ///         rest[..len].parse::<u16>().ok().map(|_| len)
+    ///     });
+    ///
+    ///     assert!(parse("port 8080", &rules).is_ok());
+    ///     assert!(parse("port 80800", &rules).is_err());
+    /// }
+    /// ```
+    pub fn matcher<F>(mut self, name: &str, matcher: F) -> Self
+    where
+        F: Fn(&str) -> Option<usize> + Send + Sync + 'static,
+    {
+        self.1.matchers.insert(name.to_owned(), Box::new(matcher));
+        self
+    }
+
+    /// Register a predicate written in Rust, referenced as ```&?name```
+    ///
+    /// It's called with the text matched by the previous element of the
+    /// sequence, and it accepts or rejects it. It doesn't consume
+    ///
+    /// ```
+    /// #[macro_use]  extern crate dynparser;
+    /// use dynparser::{parse, rules_from_peg};
+    ///
+    /// fn main() {
+    ///     let rules = rules_from_peg(
+    ///         r#"
+    ///         main    =   ident &?is_not_reserved
+    ///         ident   =   [a-z]+
+    ///         "#,
+    ///     )
+    ///     .unwrap()
+    ///     .predicate("is_not_reserved", |id| !["if", "else"].contains(&id));
+    ///
+    ///     assert!(parse("iff", &rules).is_ok());
+    ///     assert!(parse("if", &rules).is_err());
+    /// }
+    /// ```
+    pub fn predicate<F>(mut self, name: &str, predicate: F) -> Self
+    where
+        F: Fn(&str) -> bool + Send + Sync + 'static,
+    {
+        self.1
+            .predicates
+            .insert(name.to_owned(), Box::new(predicate));
+        self
+    }
+
+    pub(crate) fn analysis(&self) -> (Arc<analysis::LeftRec>, Arc<analysis::FirstSets>) {
+        self.2.get(self)
+    }
+}
+
+/// Rules used by the parser. A set of rules, or a compiled grammar
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Rules<'a> {
+    Set(&'a SetOfRules),
+    Grammar(&'a Grammar),
+}
+
+/// A rule ready to be parsed
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Rule<'a> {
+    pub(crate) name: &'a str,
+    pub(crate) expression: &'a Expression,
+}
+
+impl<'a> Rules<'a> {
+    pub(crate) fn get(&self, name: &str) -> Option<Rule<'a>> {
+        match *self {
+            Rules::Set(rules) => rules
+                .0
+                .get_key_value(name)
+                .map(|(name, expression)| Rule { name, expression }),
+            Rules::Grammar(grammar) => grammar.index_of(name).and_then(|i| grammar.rule(i)),
+        }
+    }
+
+    pub(crate) fn all(&self) -> Vec<Rule<'a>> {
+        match *self {
+            Rules::Set(rules) => rules
+                .0
+                .iter()
+                .map(|(name, expression)| Rule { name, expression })
+                .collect(),
+            Rules::Grammar(grammar) => grammar.all_rules(),
+        }
+    }
+
+    pub(crate) fn natives(&self) -> &'a Natives {
+        match *self {
+            Rules::Set(rules) => &rules.1,
+            Rules::Grammar(grammar) => grammar.natives(),
+        }
+    }
+
+    pub(crate) fn program(&self) -> &'a vm::Program {
+        match *self {
+            Rules::Set(rules) => rules.3.get(*self),
+            Rules::Grammar(grammar) => grammar.program(),
+        }
+    }
+
+    //  Indexes are only on compiled grammars
+    pub(crate) fn at(&self, index: usize) -> Option<Rule<'a>> {
+        match *self {
+            Rules::Set(_) => None,
+            Rules::Grammar(grammar) => grammar.rule(index),
+        }
     }
 }
 
@@ -111,8 +311,56 @@ pub enum Expression {
     And(MultiExpr),
     Or(MultiExpr),
     Not(Box<Expression>),
+    Peek(Box<Expression>),
     Repeat(RepInfo),
     RuleName(String),
+    /// Rule referenced on a compiled ```Grammar```
+    ///
+    /// ```Grammar::compile``` replaces ```RuleName``` with it. It can't be
+    /// built out of a grammar
+    RuleRef(RuleRef),
+    /// After passing it, a failure in the rest of the sequence
+    /// will be critical (no backtracking)
+    Cut,
+    /// If the first expression fails, the error is registered and
+    /// the input is skipped till the second one (sync) matches
+    Recover(Box<Expression>, Box<Expression>),
+}
+
+impl Expression {
+    /// Make every literal on the expression case insensitive
+    ///
+    /// Referenced rules are not modified
+    pub fn nocase(self) -> Self {
+        let nocase_vec = |v: Vec<Expression>| v.into_iter().map(Expression::nocase).collect();
+        match self {
+            Expression::Simple(Atom::Literal(l)) => Expression::Simple(Atom::ILiteral(l)),
+            //  no case insensitive scanning, back to the repetition
+            Expression::Simple(Atom::SkipUntil(l)) => Expression::Repeat(RepInfo {
+                expression: Box::new(Expression::And(MultiExpr(vec![
+                    Expression::Not(Box::new(Expression::Simple(Atom::ILiteral(l)))),
+                    Expression::Simple(Atom::Dot),
+                ]))),
+                min: NRep(0),
+                max: None,
+            }),
+            Expression::Simple(_)
+            | Expression::RuleName(_)
+            | Expression::RuleRef(_)
+            | Expression::Cut => self,
+            Expression::And(mexpr) => Expression::And(MultiExpr(nocase_vec(mexpr.0))),
+            Expression::Or(mexpr) => Expression::Or(MultiExpr(nocase_vec(mexpr.0))),
+            Expression::Not(e) => Expression::Not(Box::new(e.nocase())),
+            Expression::Peek(e) => Expression::Peek(Box::new(e.nocase())),
+            Expression::Recover(e, sync) => {
+                Expression::Recover(Box::new(e.nocase()), Box::new(sync.nocase()))
+            }
+            Expression::Repeat(rep) => Expression::Repeat(RepInfo {
+                expression: Box::new(rep.expression.nocase()),
+                ..rep
+            }),
+        }
+    }
 }
 
 /// Opaque type to manage multiple expressions
@@ -169,92 +417,482 @@ impl std::fmt::Display for NRep {
 
 //-----------------------------------------------------------------------
 pub(crate) fn parse(status: Status) -> Result {
-    parse_rule_name(status, "main")
+    let start_rule = status.options.start_rule_name();
+    let text2parse = status.text2parse.clone();
+    let shared = status.shared.clone();
+    let rule = match status.rules.get(start_rule) {
+        Some(rule) => rule,
+        None => return Err(missing_rule(&status, start_rule)),
+    };
+    let result = if vm::runs(&status) {
+        vm::parse_rule(status, rule)
+    } else {
+        parse_rule(status, rule)
+    };
+    result.map_err(|e| match e.priority {
+        ErrPriority::Normal => {
+            Error::from_farthest(&text2parse, &shared.farthest.borrow()).unwrap_or(e)
+        }
+        ErrPriority::Critical => e,
+    })
 }
 
 //-----------------------------------------------------------------------
 //  SUPPORT
 
 //-----------------------------------------------------------------------
-fn parse_rule_name<'a>(status: Status<'a>, rule_name: &str) -> Result<'a> {
-    // use std::time::{Duration, Instant};
-    // let start = Instant::now();
+//  Observed, labelled and memoized rules are parsed on their own functions
+//  (not inlined), so the usual ones don't add their frames to the stack
+fn parse_rule<'a>(status: Status<'a>, rule: Rule<'a>) -> Result<'a> {
+    if status.shared.observer.is_some() {
+        parse_rule_observed(status, rule)
+    } else if is_labelled(&status, rule) {
+        parse_rule_labelled(status, rule)
+    } else {
+        parse_rule_dispatch(status, rule)
+    }
+}
 
-    let status = if status.trace_rules {
-        status.push_rule(&format!("r:{}", rule_name))
+#[inline(never)]
+fn parse_rule_observed<'a>(status: Status<'a>, rule: Rule<'a>) -> Result<'a> {
+    let start = status.pos.clone();
+    status.observe(|o| o.enter_rule(rule.name, &start));
+    let observed = status.clone();
+    let result = if is_labelled(&status, rule) {
+        parse_rule_labelled(status, rule)
     } else {
-        status
+        parse_rule_dispatch(status, rule)
     };
+    match result {
+        Ok((ref st, _)) => observed.observe(|o| o.rule_ok(rule.name, &start, &st.pos)),
+        Err(ref e) => observed.observe(|o| o.rule_err(rule.name, &start, e)),
+    }
+    result
+}
 
-    let rules = &status.rules.0;
-    let expression = rules.get(rule_name).ok_or_else(|| {
-        Error::from_status(
+//  Labelled rules are reported by name on errors, instead of
+//  the elements inside them
+fn is_labelled(status: &Status, rule: Rule) -> bool {
+    !status.quiet && status.options.is_label(rule.name)
+}
+
+#[inline(never)]
+fn parse_rule_labelled<'a>(status: Status<'a>, rule: Rule<'a>) -> Result<'a> {
+    let start = status.clone();
+    let quiet_status = Status {
+        quiet: true,
+        ..status
+    };
+    match parse_rule_dispatch(quiet_status, rule) {
+        Ok((st, node)) => Ok((Status { quiet: false, ..st }, node)),
+        Err(e) => {
+            if e.priority == ErrPriority::Normal {
+                start.expected(rule.name);
+            }
+            Err(e)
+        }
+    }
+}
+
+pub(crate) fn parse_rule_dispatch<'a>(status: Status<'a>, rule: Rule<'a>) -> Result<'a> {
+    if status.shared.left_rec.is_leader(rule.name) {
+        parse_rule_left_rec(status, rule)
+    } else if status.shared.reuse.is_some() && !status.shared.left_rec.is_involved(rule.name) {
+        parse_rule_reuse(status, rule)
+    } else if status.options.memoize(rule.name) && !status.shared.left_rec.is_involved(rule.name) {
+        parse_rule_memo(status, rule)
+    } else {
+        parse_rule_no_memo(status, rule)
+    }
+}
+
+//  Growing the seed
+//
+//  The first time, the recursive call will fail (the seed)
+//  Next iterations, the recursive call will return the previous
+//  result, till it doesn't consume more input
+//
+//  expr    =   expr '+' num  /  num
+//
+//  "1+2+3" -> num -> (num + num) -> ((num + num) + num)
+//
+//  On interlocking cycles, a leader can grow inside another one on the
+//  same possition. Its result depends on the outer seed, so it's not kept
+//  and it will be grown again on next iteration of the outer one
+//
+//  a   =   b 'x' / 'a'
+//  b   =   a 'y' / b 'z' / 'b'
+//
+//  The failures registered on all the iterations are kept with the
+//  result
+#[inline(never)]
+fn parse_rule_left_rec<'a>(status: Status<'a>, rule: Rule<'a>) -> Result<'a> {
+    let key = (rule.name, status.pos.n);
+    let cached = status.shared.memo.borrow().get(&key, status.quiet).cloned();
+    if let Some(entry) = cached {
+        return apply_memo_entry(status, entry);
+    }
+
+    let mark = status.mark();
+    let seed = Error::from_status_normal(&status, &format!("left recursion on {}", rule.name));
+    status.shared.memo.borrow_mut().insert(
+        key,
+        memo_entry_from_result(&Err(seed), &mark, Farthest::new()),
+    );
+    let nested = status.shared.memo.borrow_mut().start_growing(status.pos.n);
+    let outer_farthest = status.shared.farthest.replace(Farthest::new());
+
+    let mut grown: Option<usize> = None;
+    loop {
+        let result = parse_rule_no_memo(status.clone(), rule);
+        match (&result, grown) {
+            (Ok((st, _)), Some(n)) if st.pos.n <= n => break,
+            (Ok((st, _)), _) => grown = Some(st.pos.n),
+            (Err(_), Some(_)) => break,
+            (Err(_), None) => (),
+        };
+        status
+            .shared
+            .memo
+            .borrow_mut()
+            .insert(key, memo_entry_from_result(&result, &mark, Farthest::new()));
+        if result.is_err() {
+            break;
+        }
+    }
+
+    status.shared.memo.borrow_mut().stop_growing();
+    let inner_farthest = status.shared.farthest.replace(outer_farthest);
+
+    let entry = {
+        let mut memo = status.shared.memo.borrow_mut();
+        let entry = memo.remove(&key).map(|entry| MemoEntry {
+            farthest: inner_farthest,
+            ..entry
+        });
+        match entry {
+            Some(ref entry) if !nested => memo.insert(key, entry.clone()),
+            _ => (),
+        }
+        entry
+    };
+    match entry {
+        Some(entry) => apply_memo_entry(status, entry),
+        None => Err(Error::from_status(
             &status,
-            &format!("Missing rule: {}", rule_name),
+            &format!("LOGIC ERROR!!! missing left recursion result {}", rule.name),
             ErrPriority::Critical,
-        )
-    })?;
-    let (st, nodes) = parse_expr(status, &expression)?;
+        )),
+    }
+}
+
+#[inline(never)]
+fn parse_rule_memo<'a>(status: Status<'a>, rule: Rule<'a>) -> Result<'a> {
+    let key = (rule.name, status.pos.n);
+    let cached = status.shared.memo.borrow().get(&key, status.quiet).cloned();
+
+    match cached {
+        Some(entry) => apply_memo_entry(status, entry),
+        None => {
+            let shared = status.shared.clone();
+            let mark = status.mark();
+            let outer_farthest = shared.farthest.replace(Farthest::new());
+            let result = parse_rule_no_memo(status, rule);
+            let inner_farthest = shared.farthest.replace(outer_farthest);
+            shared.farthest.borrow_mut().merge(inner_farthest.clone());
+            shared
+                .memo
+                .borrow_mut()
+                .insert(key, memo_entry_from_result(&result, &mark, inner_farthest));
+            result
+        }
+    }
+}
+
+//  Just the diagnostics and nodes added by the rule are stored
+//  Incremental parsing
+//
+//  The chars examined and the failures registered by the rule are
+//  stored with the result
+//  It also works as packrat (just for rules parsed correctly)
+#[inline(never)]
+fn parse_rule_reuse<'a>(status: Status<'a>, rule: Rule<'a>) -> Result<'a> {
+    let reuse = match status.shared.reuse {
+        Some(ref reuse) => reuse.clone(),
+        None => return parse_rule_no_memo(status, rule),
+    };
+    let cached = reuse
+        .borrow_mut()
+        .take(rule.name, &status.pos, status.quiet);
+    if let Some(cached) = cached {
+        return apply_cached(status, cached);
+    }
+
+    let shared = status.shared.clone();
+    let (counters, farthest) = (&shared.counters, &shared.farthest);
+    let outer_reach = counters.reach.replace(0);
+    let outer_farthest = farthest.replace(Farthest::new());
+    let start = status.pos.clone();
+    let quiet = status.quiet;
+    let mark = status.mark();
+
+    let result = parse_rule_no_memo(status, rule);
+
+    let reach = counters.reach.get();
+    counters.reach.set(reach.max(outer_reach));
+    let inner_farthest = farthest.replace(outer_farthest);
+    farthest.borrow_mut().merge(inner_farthest.clone());
+
+    if let Ok((ref st, ref node)) = result {
+        let potential_error = match st.potential_error {
+            Some(ref e) if e.pos.n >= start.n => Some((**e).clone()),
+            _ => None,
+        };
+        reuse.borrow_mut().store(
+            rule.name,
+            start.n,
+            Cached {
+                quiet,
+                end: st.pos.clone(),
+                reach,
+                node: node.clone().into_spanned(),
+                potential_error,
+                diagnostics: st.diagnostics[mark.diagnostics..].to_vec(),
+                nodes: st.nodes - mark.nodes,
+                farthest: inner_farthest,
+            },
+        );
+    }
+    result
+}
+
+fn apply_cached(status: Status, cached: Cached) -> Result {
+    let mut status = status;
+    status
+        .shared
+        .counters
+        .examined(cached.reach.saturating_sub(1));
+    if !status.quiet {
+        status.shared.farthest.borrow_mut().merge(cached.farthest);
+    }
+    status.pos = cached.end;
+    if cached.potential_error.is_some() {
+        status.potential_error = cached.potential_error.map(Rc::new);
+    }
+    status.diagnostics.extend(cached.diagnostics);
+    let status = status.add_nodes(cached.nodes)?;
+    let node = Node::from_spanned(cached.node, status.rules);
+    Ok((status, node))
+}
+
+//  The failures registered inside the rule are stored, to register
+//  them again when it's taken from the memo
+pub(crate) fn memo_entry_from_result<'a>(
+    result: &Result<'a>,
+    mark: &Mark,
+    farthest: Farthest,
+) -> MemoEntry<'a> {
+    let result = match result {
+        Ok((st, node)) => Memoized::Ok {
+            pos: st.pos.clone(),
+            potential_error: st.potential_error.clone(),
+            diagnostics: st.diagnostics[mark.diagnostics..].to_vec(),
+            nodes: st.nodes - mark.nodes,
+            node: node.clone(),
+        },
+        Err(e) => Memoized::Err(e.clone()),
+    };
+    MemoEntry {
+        quiet: mark.quiet,
+        farthest,
+        result,
+    }
+}
+
+pub(crate) fn apply_memo_entry<'a>(status: Status<'a>, entry: MemoEntry<'a>) -> Result<'a> {
+    if !status.quiet {
+        status.shared.farthest.borrow_mut().merge(entry.farthest);
+    }
+    match entry.result {
+        Memoized::Ok {
+            pos,
+            potential_error,
+            diagnostics,
+            nodes,
+            node,
+        } => {
+            let mut status = status;
+            status.pos = pos;
+            status.potential_error = potential_error;
+            status.diagnostics.extend(diagnostics);
+            let status = status.add_nodes(nodes)?;
+            Ok((status, node))
+        }
+        Memoized::Err(e) => Err(e),
+    }
+}
+
+fn parse_rule_no_memo<'a>(status: Status<'a>, rule: Rule<'a>) -> Result<'a> {
+    // use std::time::{Duration, Instant};
+    // let start = Instant::now();
+
+    let start = status.pos.clone();
+    let (st, nodes) = parse_expr(status, rule.expression)?;
+    let st = st.add_nodes(1)?;
 
     // let elapsed = start.elapsed();
     // println!(
     //     "____ elapsed time parsing {} {}.{}",
-    //     rule_name,
+    //     rule.name,
     //     elapsed.as_secs(),
     //     elapsed.subsec_millis()
     // );
-    Ok((st, ast::Node::Rule((rule_name.to_owned(), nodes))))
+    let span = Span::new(start, st.pos.clone());
+    Ok((st, Node::Rule((rule.name, nodes, span))))
 }
 
 fn parse_atom_as_expr<'a>(status: Status<'a>, a: &'a Atom) -> ResultExpr<'a> {
-    let (st, node) = atom::parse(status, a)?;
-    Ok((st, vec![node]))
+    match atom::parse(status, a)? {
+        (st, Some(node)) => Ok((st.add_nodes(1)?, vec![node])),
+        (st, None) => Ok((st, vec![])),
+    }
+}
+
+fn parse_predicate_after<'a>(status: Status<'a>, a: &'a Atom, from: usize) -> ResultExpr<'a> {
+    status.step()?;
+    let mut status = status;
+    match atom::parse_after(&mut status, a, from)? {
+        Some(node) => Ok((status.add_nodes(1)?, vec![node])),
+        None => Ok((status, vec![])),
+    }
+}
+
+fn parse_rule_name_as_expr<'a>(status: Status<'a>, rule_name: &'a str) -> ResultExpr<'a> {
+    match status.rules.get(rule_name) {
+        Some(rule) => parse_rule(status, rule).map(|(st, ast)| (st, vec![ast])),
+        None => Err(missing_rule(&status, rule_name)),
+    }
 }
 
-fn parse_rule_name_as_expr<'a>(status: Status<'a>, rule_name: &str) -> ResultExpr<'a> {
-    let (st, ast) = parse_rule_name(status, rule_name)?;
-    Ok((st, vec![ast]))
+fn parse_rule_ref_as_expr<'a>(status: Status<'a>, rule_ref: RuleRef) -> ResultExpr<'a> {
+    match status.rules.at(rule_ref.index()) {
+        Some(rule) => parse_rule(status, rule).map(|(st, ast)| (st, vec![ast])),
+        None => Err(missing_rule(&status, &format!("#{}", rule_ref.index()))),
+    }
+}
+
+pub(crate) fn missing_rule(status: &Status, rule_name: &str) -> Error {
+    Error::from_status(
+        status,
+        &format!("Missing rule: {}", rule_name),
+        ErrPriority::Critical,
+    )
 }
 
+//  The depth is restored on return
 fn parse_expr<'a>(status: Status<'a>, expression: &'a Expression) -> ResultExpr<'a> {
-    match *expression {
+    status.step()?;
+    let mut status = status;
+    status.depth += 1;
+    let (mut st, nodes) = match *expression {
         Expression::Simple(ref val) => parse_atom_as_expr(status, &val),
         Expression::And(ref val) => parse_and(status, &val),
         Expression::Or(ref val) => parse_or(&status, &val),
         Expression::Not(ref val) => parse_not(status, &val),
+        Expression::Peek(ref val) => parse_peek(status, &val),
         Expression::Repeat(ref val) => parse_repeat(status, &val),
         Expression::RuleName(ref val) => parse_rule_name_as_expr(status, &val),
-    }
+        Expression::RuleRef(rule_ref) => parse_rule_ref_as_expr(status, rule_ref),
+        Expression::Cut => Ok((status, vec![])),
+        Expression::Recover(ref expr, ref sync) => parse_recover(status, expr, sync),
+    }?;
+    st.depth -= 1;
+    Ok((st, nodes))
 }
 
 //-----------------------------------------------------------------------
+//  After a cut, normal errors are promoted to critical
+//  pointing to the element it couldn't be parsed
+//
+//  A predicate checks the text matched by the previous element
 fn parse_and<'a>(status: Status<'a>, multi_expr: &'a MultiExpr) -> ResultExpr<'a> {
-    let init_tc: (_, &[Expression], Vec<ast::Node>) = (status, &(multi_expr.0), vec![]);
+    let from = status.pos.byte;
+    let init_tc: (_, &[Expression], Vec<Node>, bool, usize) =
+        (status, &(multi_expr.0), vec![], false, from);
 
     tail_call(init_tc, |acc| {
         if acc.1.is_empty() {
             TailCall::Return(Ok((acc.0, acc.2)))
         } else {
-            let result_parse = parse_expr(acc.0, &acc.1[0]);
-            match result_parse {
-                Ok((status, vnodes)) => {
-                    TailCall::Call((status, &acc.1[1..], acc.2.iappend(vnodes)))
+            let cut = acc.3 || matches!(acc.1[0], Expression::Cut);
+            let cut_status = if cut { Some(acc.0.clone()) } else { None };
+            let from = acc.0.pos.byte;
+            let result_parse = match acc.1[0] {
+                Expression::Simple(ref atom @ Atom::Native(Native::Predicate(_))) => {
+                    parse_predicate_after(acc.0, atom, acc.4)
+                }
+                ref expression => parse_expr(acc.0, expression),
+            };
+            match (result_parse, cut_status) {
+                (Ok((status, vnodes)), _) => {
+                    TailCall::Call((status, &acc.1[1..], acc.2.iappend(vnodes), cut, from))
+                }
+                (Err(ref err), Some(ref st)) if err.priority == ErrPriority::Normal => {
+                    TailCall::Return(Err(Error::from_status(
+                        st,
+                        &format!("expected {}", expected_descr(&acc.1[0], &st.rules)),
+                        ErrPriority::Critical,
+                    )))
                 }
-                Err(err) => TailCall::Return(Err(err)),
+                (Err(err), _) => TailCall::Return(Err(err)),
             }
         }
     })
 }
 
+pub(crate) fn expected_descr(expression: &Expression, rules: &Rules) -> String {
+    let mexpr_descr = |mexpr: &MultiExpr, sep: &str| {
+        mexpr
+            .0
+            .iter()
+            .filter(|e| !matches!(e, Expression::Cut))
+            .map(|e| expected_descr(e, rules))
+            .collect::<Vec<_>>()
+            .join(sep)
+    };
+
+    match expression {
+        Expression::Simple(atom) => atom::descr(atom),
+        Expression::And(mexpr) => mexpr_descr(mexpr, " "),
+        Expression::Or(mexpr) => mexpr_descr(mexpr, " or "),
+        Expression::Not(e) => format!("not {}", expected_descr(e, rules)),
+        Expression::Repeat(RepInfo { expression: e, .. }) => expected_descr(e, rules),
+        Expression::Peek(e) | Expression::Recover(e, _) => expected_descr(e, rules),
+        Expression::RuleName(name) => name.clone(),
+        Expression::RuleRef(rule_ref) => rules
+            .at(rule_ref.index())
+            .map(|rule| rule.name.to_string())
+            .unwrap_or_default(),
+        Expression::Cut => "".to_string(),
+    }
+}
+
 //-----------------------------------------------------------------------
+//  The alternatives that can't start with next char are not tried
+//  Their failures are registered, to get the same errors
 fn parse_or<'a>(status: &Status<'a>, multi_expr: &'a MultiExpr) -> ResultExpr<'a> {
-    let deep_err = |oe1: Option<Error>, e2: Error| match oe1 {
-        Some(e1) => match (e1.priority > e2.priority, e1.pos.n > e2.pos.n) {
-            (true, _) => Some(e1),
-            (false, true) => Some(e1),
-            (false, false) => Some(e2),
-        },
-        None => Some(e2),
+    let firsts = if status.quiet || status.shared.observer.is_some() {
+        None
+    } else {
+        status.shared.first.get(multi_expr)
+    };
+    let next = match firsts {
+        Some(_) => status.text2parse.char_at(status.pos.byte).map(|(ch, _)| ch),
+        None => None,
+    };
+    let rejected = |index: usize| match firsts {
+        Some(firsts) => matches!(firsts[index], Some(ref first) if first.rejects(next)),
+        None => false,
     };
 
     let init_tc: (_, &[Expression], Option<Error>) = (status.clone(), &(multi_expr.0), None);
@@ -263,11 +901,11 @@ fn parse_or<'a>(status: &Status<'a>, multi_expr: &'a MultiExpr) -> ResultExpr<'a
         if acc.1.is_empty() {
             TailCall::Return(Err(match acc.2 {
                 Some(err) => err,
-                _ => Error::from_status_normal(
-                    &status,
-                    "LOGIC ERROR!!! checked all options in or with ¿NO? errors",
-                ),
+                _ => or_without_errors(status),
             }))
+        } else if rejected(multi_expr.0.len() - acc.1.len()) {
+            let e = rejected_error(&acc.0, &acc.1[0]);
+            TailCall::Call((acc.0, &acc.1[1..], deep_err(acc.2, e)))
         } else {
             let try_parse = parse_expr(acc.0.clone(), &acc.1[0]);
             match try_parse {
@@ -276,6 +914,12 @@ fn parse_or<'a>(status: &Status<'a>, multi_expr: &'a MultiExpr) -> ResultExpr<'a
                     if e.priority == ErrPriority::Critical {
                         TailCall::Return(Err(e))
                     } else {
+                        if acc.1.len() > 1 {
+                            acc.0.observe(|o| o.backtrack(&e.pos, &acc.0.pos));
+                            if let Err(e) = acc.0.backtrack() {
+                                return TailCall::Return(Err(e));
+                            }
+                        }
                         TailCall::Call((acc.0, &acc.1[1..], deep_err(acc.2, e)))
                     }
                 }
@@ -284,23 +928,181 @@ fn parse_or<'a>(status: &Status<'a>, multi_expr: &'a MultiExpr) -> ResultExpr<'a
     })
 }
 
+//  The error of an *or* is the deepest one
+pub(crate) fn deep_err(oe1: Option<Error>, e2: Error) -> Option<Error> {
+    match oe1 {
+        Some(e1) => match (e1.priority > e2.priority, e1.pos.n > e2.pos.n) {
+            (true, _) => Some(e1),
+            (false, true) => Some(e1),
+            (false, false) => Some(e2),
+        },
+        None => Some(e2),
+    }
+}
+
+pub(crate) fn or_without_errors(status: &Status) -> Error {
+    Error::from_status_normal(
+        status,
+        "LOGIC ERROR!!! checked all options in or with ¿NO? errors",
+    )
+}
+
+pub(crate) fn rejected_error(status: &Status, expression: &Expression) -> Error {
+    status.shared.counters.examined(status.pos.n);
+    register_rejected(status, expression, false);
+    Error::from_status_normal(status, "rejected by next char")
+}
+
+//  Register the failures the expression would register, failing
+//  on the first char. It's false if it would match without consuming
+//
+//  Only the expressions with a first set are rejected
+fn register_rejected(status: &Status, expression: &Expression, quiet: bool) -> bool {
+    let register = |descr: &str| {
+        if !quiet {
+            status.expected(descr)
+        }
+    };
+    let rule = match expression {
+        Expression::RuleName(name) => status.rules.get(name),
+        Expression::RuleRef(rule_ref) => status.rules.at(rule_ref.index()),
+        _ => None,
+    };
+
+    match expression {
+        Expression::Simple(Atom::Literal(l)) | Expression::Simple(Atom::ILiteral(l))
+            if l.is_empty() =>
+        {
+            false
+        }
+        Expression::Simple(Atom::SkipUntil(_)) | Expression::Simple(Atom::Native(_)) => false,
+        //  only the empty literal could match
+        Expression::Simple(Atom::Trie(trie)) => {
+            if !quiet {
+                atom::register_trie(status, trie, trie.literal(0));
+            }
+            trie.literal(0).is_none()
+        }
+        Expression::Simple(atom) => {
+            register(&atom::descr(atom));
+            true
+        }
+        Expression::And(mexpr) => mexpr.0.iter().any(|e| register_rejected(status, e, quiet)),
+        Expression::Or(mexpr) => mexpr.0.iter().all(|e| register_rejected(status, e, quiet)),
+        Expression::Repeat(rep) => {
+            register_rejected(status, &rep.expression, quiet) && rep.min.0 > 0
+        }
+        Expression::RuleName(_) | Expression::RuleRef(_) => match rule {
+            Some(rule) if status.options.is_label(rule.name) => {
+                let failed = register_rejected(status, rule.expression, true);
+                if failed {
+                    register(rule.name);
+                }
+                failed
+            }
+            Some(rule) => register_rejected(status, rule.expression, quiet),
+            None => true,
+        },
+        Expression::Not(_) | Expression::Peek(_) | Expression::Cut => true,
+        Expression::Recover(_, _) => true,
+    }
+}
+
 //-----------------------------------------------------------------------
 fn parse_not<'a>(status: Status<'a>, expression: &'a Expression) -> ResultExpr<'a> {
-    match parse_expr(status.clone(), expression) {
+    let quiet_status = Status {
+        quiet: true,
+        ..status.clone()
+    };
+    match parse_expr(quiet_status, expression) {
         Ok(_) => Err(Error::from_status_normal(&status, "not")),
+        Err(e) if e.kind != ErrKind::Syntax => Err(e),
         Err(_) => Ok((status, vec![])),
     }
 }
 
+//-----------------------------------------------------------------------
+fn parse_peek<'a>(status: Status<'a>, expression: &'a Expression) -> ResultExpr<'a> {
+    let _ = parse_expr(status.clone(), expression)?;
+    Ok((status, vec![]))
+}
+
+//-----------------------------------------------------------------------
+//  The error is registered on diagnostics and an error node, with
+//  the skipped input, takes the place of the expression
+//
+//  The sync expression is not consumed. Repeating a recover without
+//  consuming the sync would loop forever
+//      stmts   =   (recover(stmt, ';') ';')*
+//
+//  Not inlined, its frame is big
+#[inline(never)]
+fn parse_recover<'a>(
+    status: Status<'a>,
+    expression: &'a Expression,
+    sync: &'a Expression,
+) -> ResultExpr<'a> {
+    let outer_farthest = status.shared.farthest.replace(Farthest::new());
+    let result = parse_expr(status.clone(), expression);
+    let inner_farthest = status.shared.farthest.replace(outer_farthest);
+
+    match result {
+        Ok(result) => {
+            status.shared.farthest.borrow_mut().merge(inner_farthest);
+            Ok(result)
+        }
+        Err(e) if e.kind != ErrKind::Syntax => Err(e),
+        Err(e) => {
+            let error = match e.priority {
+                ErrPriority::Normal => {
+                    Error::from_farthest(&status.text2parse, &inner_farthest).unwrap_or(e)
+                }
+                ErrPriority::Critical => e,
+            };
+            let mut error = error;
+            status.add_trace(&mut error);
+            let start = status.pos.clone();
+            let mut status = skip_till(status, sync)?.add_nodes(1)?;
+            let span = Span::new(start, status.pos.clone());
+            let node = Node::Error((error.descr.clone(), span));
+            status.diagnostics.push(error);
+            Ok((status, vec![node]))
+        }
+    }
+}
+
+fn skip_till<'a>(status: Status<'a>, sync: &'a Expression) -> result::Result<Status<'a>, Error> {
+    let mut status = status;
+    loop {
+        let sync_status = Status {
+            quiet: true,
+            ..status.clone()
+        };
+        match parse_expr(sync_status, sync) {
+            Ok(_) => return Ok(status),
+            Err(e) if e.kind != ErrKind::Syntax => return Err(e),
+            Err(_) => (),
+        }
+        status = match status.get_char() {
+            Ok((st, _)) => st,
+            Err(st) => return Ok(st),
+        };
+    }
+}
+
 //-----------------------------------------------------------------------
 fn parse_repeat<'a>(status: Status<'a>, rep_info: &'a RepInfo) -> ResultExpr<'a> {
+    //  x{0} is not even tried
+    if matches!(rep_info.max, Some(NRep(0))) {
+        return Ok((status, vec![]));
+    }
     let big_min_bound = |counter| counter >= rep_info.min.0;
     let touch_max_bound = |counter: usize| match rep_info.max {
         Some(ref m) => counter + 1 == m.0,
         None => false,
     };
 
-    let init_tc: (_, _, Vec<ast::Node>) = (status, 0, vec![]);
+    let init_tc: (_, _, Vec<Node>) = (status, 0, vec![]);
     Ok(tail_call(init_tc, |acc| {
         let try_parse = parse_expr(acc.0.clone(), &rep_info.expression);
         match (try_parse, big_min_bound(acc.1), touch_max_bound(acc.1)) {
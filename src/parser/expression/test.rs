@@ -5,7 +5,7 @@
 //-----------------------------------------------------------------------
 
 use super::{parse_expr, Expression, MultiExpr, NRep, RepInfo, Status};
-use crate::parser::atom::Atom;
+use crate::parser::{atom::Atom, ErrPriority};
 
 #[test]
 fn test_parse_literal_ok() {
@@ -218,3 +218,135 @@ fn test_parse_repeat_fail() {
         assert!(parse_expr(status_init, &expr).is_err());
     }
 }
+
+#[test]
+fn test_parse_peek() {
+    let rules = rules! {};
+    let expr_peek = Expression::Peek(Box::new(Expression::Simple(Atom::Literal(
+        "aa".to_string(),
+    ))));
+
+    let status_init = Status::init("aa", &rules);
+    let (status, nodes) = parse_expr(status_init, &expr_peek).ok().unwrap();
+    assert_eq!(status.pos.n, 0);
+    assert!(nodes.is_empty());
+
+    let status_init = Status::init("bb", &rules);
+    assert!(parse_expr(status_init, &expr_peek).is_err());
+}
+
+#[test]
+fn test_parse_and_cut() {
+    let rules = rules! {};
+    let expr = or!(
+        and!(
+            lit!("a"),
+            cut!(),
+            lit!("b"),
+            ematch!(chlist "cd", from2 vec![])
+        ),
+        lit!("ax")
+    );
+
+    let status_init = Status::init("abc", &rules);
+    let (status, nodes) = parse_expr(status_init, &expr).ok().unwrap();
+    assert_eq!(status.pos.n, 3);
+    assert_eq!(nodes.len(), 3);
+
+    let status_init = Status::init("ax", &rules);
+    let err = parse_expr(status_init, &expr).err().unwrap();
+    assert_eq!(err.descr, "expected 'b'");
+    assert_eq!(err.pos.n, 1);
+    assert_eq!(err.priority, ErrPriority::Critical);
+
+    let status_init = Status::init("abx", &rules);
+    let err = parse_expr(status_init, &expr).err().unwrap();
+    assert_eq!(err.descr, "expected [cd]");
+    assert_eq!(err.pos.n, 2);
+}
+
+#[test]
+fn test_parse_recover() {
+    use crate::ast::borrowed::Node;
+
+    let rules = rules! {};
+    let expr = rep!(and!(recover!(lit!("ab"), lit!(";")), lit!(";")), 0);
+
+    let status_init = Status::init("ab;xx;ab;", &rules);
+    let (status, nodes) = parse_expr(status_init, &expr).ok().unwrap();
+    assert_eq!(status.pos.n, 9);
+    assert_eq!(status.diagnostics.len(), 1);
+    assert_eq!(status.diagnostics[0].descr, "expected 'ab'");
+    assert_eq!(status.diagnostics[0].pos.n, 3);
+    match &nodes[2] {
+        Node::Error((descr, span)) => {
+            assert_eq!(descr, "expected 'ab'");
+            assert_eq!((span.start.n, span.end.n), (3, 5));
+        }
+        _ => panic!("expected an error node"),
+    }
+
+    //  diagnostics on discarded branches are discarded too
+    let expr = or!(and!(recover!(lit!("a"), lit!(";")), lit!("!")), lit!("x;"));
+    let status_init = Status::init("x;", &rules);
+    let (status, _) = parse_expr(status_init, &expr).ok().unwrap();
+    assert!(status.diagnostics.is_empty());
+}
+
+#[test]
+fn test_parse_or_first_sets() {
+    use crate::parser::{observer::ParseObserver, Options};
+    use crate::{parse_status, rules_from_peg};
+    use std::{cell::RefCell, rc::Rc};
+
+    //  with an observer, all the alternatives are tried
+    struct Silent;
+    impl ParseObserver for Silent {}
+
+    let rules = rules_from_peg(
+        r#"
+        main    =   (stmt ';')* _ eof?
+        stmt    =   _ (assign / call / block / 'nop'i / recover(bad, ';' / eof))
+        assign  =   id _ '=' _ expr
+        call    =   id _ '(' _ (expr (_ ',' _ expr)*)? _ ')'
+        block   =   '{' (stmt ';')* _ '}'
+        bad     =   !'x' [a-z] '!'
+        expr    =   num / str / id / &'-' '-' num
+        num     =   [0-9]+ ('.' [0-9]+)?
+        str     =   '"' (!'"' .)* '"'
+        id      =   [_a-zA-Z] [_a-zA-Z0-9]*
+        _       =   ' '*
+        "#,
+    )
+    .unwrap();
+
+    let texts = [
+        "a=1;b = f(1, \"x\", -2);",
+        "{a=1; {NOP;};};",
+        "a=;",
+        "f(1,);",
+        "{a=1;",
+        "q!;a=1;",
+        "a = 1.",
+        "NoP;x!;",
+        "7",
+        "",
+    ];
+    for options in &[Options::new(), Options::new().label("expr").label("id")] {
+        for text in texts.iter() {
+            let status = Status::init_options(text, &rules, options);
+            let (ast, errors) = parse_status(status);
+            let observer = Rc::new(RefCell::new(Silent));
+            let status = Status::init_options(text, &rules, options).set_observer(observer);
+            let (all_ast, all_errors) = parse_status(status);
+
+            assert_eq!(ast, all_ast, "on {:?}", text);
+            assert_eq!(errors.len(), all_errors.len(), "on {:?}", text);
+            for (e, all_e) in errors.iter().zip(all_errors.iter()) {
+                assert_eq!(e.descr, all_e.descr, "on {:?}", text);
+                assert_eq!(e.pos, all_e.pos, "on {:?}", text);
+                assert_eq!(e.expected, all_e.expected, "on {:?}", text);
+            }
+        }
+    }
+}
@@ -0,0 +1,331 @@
+//! Set of rules compiled, ready to parse
+//!
+//! ```Grammar::compile``` checks all the referenced rules are defined,
+//! and replaces the names with the possition of the rule. No lookups
+//! by name while parsing (just for the start rule)
+//!
+//! The *or* of literals are folded into a ```Trie``` (same result,
+//! and same errors)
+//!
+//! The analysis of the rules (left recursion, first chars) is also done
+//! once, when compiling
+//!
+//! A ```Grammar``` is immutable, ```Send``` and ```Sync```. Cloning it is
+//! cheap (it's shared), so it can be used from several threads
+
+use crate::ast;
+use crate::parser::{
+    self, analysis,
+    atom::{Atom, Natives, Trie},
+    expression::{Expression, MultiExpr, RepInfo, Rule, Rules, SetOfRules},
+    vm, Options, Status,
+};
+use std::collections::HashMap;
+use std::result;
+use std::sync::Arc;
+
+#[cfg(test)]
+mod test;
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  T Y P E S
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+/// A set of rules with the references resolved
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::parser::grammar::Grammar;
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  and!(ref_rule!("a"), ref_rule!("b")),
+///        "a"      =>  lit!("a"),
+///        "b"      =>  rep!(lit!("b"), 1)
+///     };
+///
+///     let grammar = Grammar::compile(rules).unwrap();
+///     assert!(grammar.parse("abbb").is_ok());
+///     assert!(grammar.parse("aaa").is_err());
+///
+///     let err = Grammar::compile(rules!{ "main" => ref_rule!("b") })
+///         .err()
+///         .unwrap();
+///     assert_eq!(err.undefined, vec![("main".to_string(), "b".to_string())]);
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Grammar(Arc<Compiled>);
+
+#[derive(Debug)]
+struct Compiled {
+    /// Name and expression, sorted by name
+    rules: Vec<(String, Expression)>,
+    index: HashMap<String, usize>,
+    left_rec: Arc<analysis::LeftRec>,
+    first: Arc<analysis::FirstSets>,
+    natives: Natives,
+    program: vm::Bytecode,
+}
+
+/// Reference to a rule, resolved by ```Grammar::compile```
+///
+/// It's the possition of the rule on the grammar, so it can't be built
+/// out of it
+///
+/// ```compile_fail
+/// use dynparser::parser::grammar::RuleRef;
+///
+/// let rule_ref = RuleRef(0);
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RuleRef(usize);
+
+/// Error compiling a set of rules
+#[derive(Debug, Clone, PartialEq)]
+pub struct CompileError {
+    /// Rules referenced but not defined
+    ///
+    /// (rule with the reference, rule referenced)
+    pub undefined: Vec<(String, String)>,
+}
+
+impl std::fmt::Display for CompileError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        let undefined = self
+            .undefined
+            .iter()
+            .map(|(rule, name)| format!("{} (on {})", name, rule))
+            .collect::<Vec<_>>()
+            .join(", ");
+        write!(f, "undefined rules: {}", undefined)
+    }
+}
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  A P I
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+impl Grammar {
+    /// Resolve the references between rules
+    ///
+    /// It fails if a rule referenced is not defined
+    pub fn compile(rules: SetOfRules) -> result::Result<Self, CompileError> {
+        let left_rec = Arc::new(analysis::left_recursion(&rules));
+
+        let SetOfRules(rules, natives, _, _) = rules;
+        let mut rules = rules.into_iter().collect::<Vec<_>>();
+        rules.sort_by(|(n1, _), (n2, _)| n1.cmp(n2));
+        let index = rules
+            .iter()
+            .enumerate()
+            .map(|(i, (name, _))| (name.clone(), i))
+            .collect::<HashMap<_, _>>();
+
+        let mut undefined = vec![];
+        let rules = rules
+            .into_iter()
+            .map(|(name, expr)| {
+                let expr = resolve(expr, &index, &mut |rname| {
+                    undefined.push((name.clone(), rname.to_string()))
+                });
+                (name, fold_literals(expr))
+            })
+            .collect();
+
+        if !undefined.is_empty() {
+            return Err(CompileError { undefined });
+        }
+
+        let mut grammar = Grammar(Arc::new(Compiled {
+            rules,
+            index,
+            left_rec,
+            first: Arc::default(),
+            natives,
+            program: vm::Bytecode::default(),
+        }));
+        let first = analysis::first_sets(Rules::Grammar(&grammar), &grammar.0.left_rec);
+        Arc::get_mut(&mut grammar.0)
+            .expect("grammar not shared yet")
+            .first = Arc::new(first);
+        Ok(grammar)
+    }
+
+    /// Parse a text, starting on rule ```main```
+    pub fn parse(&self, s: &str) -> result::Result<ast::Node, parser::Error> {
+        self.parse_with_options(s, &parser::DEFAULT_OPTIONS)
+    }
+
+    /// Parse a text with options. Look at ```parse_with_options```
+    pub fn parse_with_options(
+        &self,
+        s: &str,
+        options: &Options,
+    ) -> result::Result<ast::Node, parser::Error> {
+        self.parse_spanned(s, options).map(ast::Node::from)
+    }
+
+    /// Parse a text keeping the spans. Look at ```parse_spanned```
+    pub fn parse_spanned(
+        &self,
+        s: &str,
+        options: &Options,
+    ) -> result::Result<ast::spanned::Node, parser::Error> {
+        let status = Status::init_grammar(s, self, options);
+        crate::first_error(crate::parse_recovering_status(status))
+            .map(ast::borrowed::Node::into_spanned)
+    }
+
+    /// Names of the rules
+    pub fn rule_names(&self) -> impl Iterator<Item = &str> {
+        self.0.rules.iter().map(|(name, _)| name.as_str())
+    }
+
+    pub(crate) fn index_of(&self, name: &str) -> Option<usize> {
+        self.0.index.get(name).cloned()
+    }
+
+    pub(crate) fn rule(&self, index: usize) -> Option<Rule<'_>> {
+        self.0
+            .rules
+            .get(index)
+            .map(|(name, expression)| Rule { name, expression })
+    }
+
+    pub(crate) fn all_rules(&self) -> Vec<Rule<'_>> {
+        self.0
+            .rules
+            .iter()
+            .map(|(name, expression)| Rule { name, expression })
+            .collect()
+    }
+
+    pub(crate) fn left_rec(&self) -> Arc<analysis::LeftRec> {
+        self.0.left_rec.clone()
+    }
+
+    pub(crate) fn first(&self) -> Arc<analysis::FirstSets> {
+        self.0.first.clone()
+    }
+
+    pub(crate) fn natives(&self) -> &Natives {
+        &self.0.natives
+    }
+
+    //  Compiled on the first parse with the VM
+    pub(crate) fn program(&self) -> &vm::Program {
+        self.0.program.get(Rules::Grammar(self))
+    }
+}
+
+impl RuleRef {
+    pub(crate) fn index(self) -> usize {
+        self.0
+    }
+}
+
+//-----------------------------------------------------------------------
+//  SUPPORT
+
+//  Names to indexes. Undefined names are reported and kept
+fn resolve<F>(expr: Expression, index: &HashMap<String, usize>, undefined: &mut F) -> Expression
+where
+    F: FnMut(&str),
+{
+    let mut resolve_box = |e: Box<Expression>| Box::new(resolve(*e, index, undefined));
+    match expr {
+        Expression::RuleName(name) => match index.get(&name) {
+            Some(i) => Expression::RuleRef(RuleRef(*i)),
+            None => {
+                undefined(&name);
+                Expression::RuleName(name)
+            }
+        },
+        Expression::And(mexpr) => Expression::And(resolve_multi(mexpr, index, undefined)),
+        Expression::Or(mexpr) => Expression::Or(resolve_multi(mexpr, index, undefined)),
+        Expression::Not(e) => Expression::Not(resolve_box(e)),
+        Expression::Peek(e) => Expression::Peek(resolve_box(e)),
+        Expression::Repeat(rep) => Expression::Repeat(RepInfo {
+            expression: resolve_box(rep.expression),
+            ..rep
+        }),
+        Expression::Recover(e, sync) => {
+            let e = resolve_box(e);
+            Expression::Recover(e, resolve_box(sync))
+        }
+        Expression::Simple(_) | Expression::RuleRef(_) | Expression::Cut => expr,
+    }
+}
+
+fn resolve_multi<F>(
+    mexpr: MultiExpr,
+    index: &HashMap<String, usize>,
+    undefined: &mut F,
+) -> MultiExpr
+where
+    F: FnMut(&str),
+{
+    MultiExpr(
+        mexpr
+            .0
+            .into_iter()
+            .map(|e| resolve(e, index, undefined))
+            .collect(),
+    )
+}
+
+//  Consecutive literals on an *or* to a trie
+fn fold_literals(expr: Expression) -> Expression {
+    let fold_box = |e: Box<Expression>| Box::new(fold_literals(*e));
+    match expr {
+        Expression::Or(mexpr) => {
+            let mut alternatives: Vec<Expression> = vec![];
+            let mut literals: Vec<String> = vec![];
+            for e in mexpr.0.into_iter().map(fold_literals) {
+                match e {
+                    Expression::Simple(Atom::Literal(l)) => literals.push(l),
+                    e => {
+                        add_literals(&mut alternatives, std::mem::take(&mut literals));
+                        alternatives.push(e);
+                    }
+                }
+            }
+            add_literals(&mut alternatives, literals);
+            match alternatives.len() {
+                1 => alternatives.remove(0),
+                _ => Expression::Or(MultiExpr(alternatives)),
+            }
+        }
+        Expression::And(mexpr) => {
+            Expression::And(MultiExpr(mexpr.0.into_iter().map(fold_literals).collect()))
+        }
+        Expression::Not(e) => Expression::Not(fold_box(e)),
+        Expression::Peek(e) => Expression::Peek(fold_box(e)),
+        Expression::Repeat(rep) => Expression::Repeat(RepInfo {
+            expression: fold_box(rep.expression),
+            ..rep
+        }),
+        Expression::Recover(e, sync) => Expression::Recover(fold_box(e), fold_box(sync)),
+        Expression::Simple(_)
+        | Expression::RuleName(_)
+        | Expression::RuleRef(_)
+        | Expression::Cut => expr,
+    }
+}
+
+fn add_literals(alternatives: &mut Vec<Expression>, mut literals: Vec<String>) {
+    match literals.len() {
+        0 => (),
+        1 => alternatives.push(Expression::Simple(Atom::Literal(literals.remove(0)))),
+        _ => alternatives.push(Expression::Simple(Atom::Trie(Trie::new(literals, false)))),
+    }
+}
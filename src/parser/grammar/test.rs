@@ -0,0 +1,149 @@
+//-----------------------------------------------------------------------
+//
+//  mod parser::grammar  TEST
+//
+//-----------------------------------------------------------------------
+
+use super::{CompileError, Grammar};
+use crate::parser::{expression::SetOfRules, Options};
+use crate::{parse_spanned, rules_from_peg};
+use std::sync::Arc;
+use std::thread;
+
+fn rules_calc() -> SetOfRules {
+    rules_from_peg(
+        r#"
+        main    =   expr (';' expr)*
+        expr    =   expr _ '+' _ term  /  term
+        term    =   '(' _ ^ expr _ ')'  /  num  /  id
+        num     =   [0-9]+
+        id      =   [a-z]+
+        _       =   ' '*
+        "#,
+    )
+    .unwrap()
+}
+
+fn check_same(text: &str, grammar: &Grammar, rules: &SetOfRules, options: &Options) {
+    let full = parse_spanned(text, rules, options);
+    match (grammar.parse_spanned(text, options), &full) {
+        (Ok(compiled), Ok(full)) => assert_eq!(&compiled, full, "on {:?}", text),
+        (Err(compiled), Err(full)) => {
+            assert_eq!(compiled.descr, full.descr, "on {:?}", text);
+            assert_eq!(compiled.pos, full.pos);
+            assert_eq!(compiled.expected, full.expected);
+            assert_eq!(compiled.priority, full.priority);
+        }
+        (compiled, _) => panic!("on {:?} {:?} vs {:?}", text, compiled, full),
+    }
+}
+
+#[test]
+fn test_compiled_same_as_rules() {
+    let grammar = Grammar::compile(rules_calc()).unwrap();
+    let rules = rules_calc();
+    let options = Options::new();
+
+    for text in &["1+2+3", "a + (b+1);2", "(1", "1+(a b)", "( ;", "1;", ""] {
+        check_same(text, &grammar, &rules, &options);
+    }
+
+    let options = Options::new().start_rule("term");
+    check_same("(1+x)", &grammar, &rules, &options);
+}
+
+#[test]
+fn test_compile_undefined() {
+    let rules = rules! {
+        "main" => and!(ref_rule!("a"), ref_rule!("c")),
+        "a"    => or!(ref_rule!("b"), rep!(ref_rule!("main"), 0))
+    };
+    let err = Grammar::compile(rules).err().unwrap();
+    assert_eq!(
+        err,
+        CompileError {
+            undefined: vec![
+                ("a".to_string(), "b".to_string()),
+                ("main".to_string(), "c".to_string()),
+            ]
+        }
+    );
+    assert_eq!(err.to_string(), "undefined rules: b (on a), c (on main)");
+}
+
+#[test]
+fn test_compiled_missing_start_rule() {
+    let grammar = Grammar::compile(rules! { "a" => lit!("a") }).unwrap();
+    let err = grammar.parse("a").err().unwrap();
+    assert_eq!(err.descr, "Missing rule: main");
+    assert_eq!(grammar.rule_names().collect::<Vec<_>>(), vec!["a"]);
+}
+
+#[test]
+fn test_compiled_shared_by_threads() {
+    fn send_sync<T: Send + Sync>(_: &T) {}
+
+    let grammar = Arc::new(Grammar::compile(rules_calc()).unwrap());
+    send_sync(&grammar);
+
+    let handles = (0..4)
+        .map(|i| {
+            let grammar = grammar.clone();
+            thread::spawn(move || grammar.parse(&format!("{}+(x+{})", i, i)).is_ok())
+        })
+        .collect::<Vec<_>>();
+    for h in handles {
+        assert!(h.join().unwrap());
+    }
+}
+
+#[test]
+fn test_compiled_literals_trie() {
+    let rules = || {
+        rules_from_peg(
+            r#"
+            main    =   (stmt _)*
+            stmt    =   kw  /  id  /  op
+            kw      =   'if' / 'ifdef' / 'in' / 'else' / 'end'
+            op      =   '=' / '==' / '!' / '!=' / ('<' / '<=') / '(' _ main _ ')'
+            id      =   [a-z]+
+            _       =   ' '*
+            "#,
+        )
+        .unwrap()
+    };
+    let grammar = Grammar::compile(rules()).unwrap();
+    let rules = rules();
+    let options = Options::new();
+
+    for text in &["if ifdef x", "== != <= (in)", "else(", "en + d", "!", "("] {
+        check_same(text, &grammar, &rules, &options);
+    }
+    check_same("if +", &grammar, &rules, &options);
+    let err = grammar.parse("if +").err().unwrap();
+    assert_eq!(
+        err.descr,
+        "expected one of ' ', 'if', 'ifdef', 'in', 'else', 'end', [a-z], '=', '==', '!', '!=', \
+         '<', '<=', '(', end of input"
+    );
+}
+
+#[test]
+fn test_compiled_natives() {
+    let rules = rules_from_peg(
+        r#"
+        main    =   @hex ([g-z]+ &?even)?
+        "#,
+    )
+    .unwrap()
+    .matcher("hex", |rest| {
+        rest.find(|c: char| !c.is_ascii_hexdigit())
+            .or(Some(rest.len()))
+    })
+    .predicate("even", |text| text.len() % 2 == 0);
+
+    let grammar = Grammar::compile(rules).unwrap();
+    assert!(grammar.parse("a0ffxy").is_ok());
+    assert!(grammar.parse("a0ffxyz").is_err());
+    assert!(grammar.parse_with_options("", &Options::new()).is_ok());
+}
@@ -0,0 +1,354 @@
+//! Parse again a text after an edit, reusing the rules parsed before
+//!
+//! The result of every rule is stored with the chars examined to get
+//! it (lookahead included)
+//!
+//! After an edit, a rule on same possition will be reused if the chars
+//! examined were before the edit, or if it started after the edit (moving
+//! the spans)
+//!
+//! The result is the same as parsing the full text again
+//!
+//! Rules involved on left recursion are not stored. With limits
+//! (```Options::max_steps```...) or ```Options::trace_rules```, nothing
+//! is reused, as they have to see every rule parsed
+//!
+//! The rules parsed correctly are also reused on same parsing
+//! (as packrat, that is not used)
+
+use crate::ast::spanned::Node;
+use crate::parser::{expression::SetOfRules, ColMode, Error, Farthest, Options, Possition, Status};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+use std::ops::Range;
+use std::rc::Rc;
+use std::result;
+
+#[cfg(test)]
+mod test;
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  T Y P E S
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+/// A text parsed, ready to receive edits
+///
+/// ```
+/// use dynparser::{parser::{incremental::IncrementalParse, Options}, rules_from_peg};
+///
+/// let rules = rules_from_peg(
+///     r#"
+///
+///     main    =   (stmt _eol)*
+///     stmt    =   [a-z]+ '=' [0-9]+
+///     _eol    =   "\n"
+///
+///     "#,
+/// )
+/// .unwrap();
+///
+/// let mut doc = IncrementalParse::new("a=1\nb=2\nc=3\n", &rules, &Options::new());
+/// assert!(doc.result().is_ok());
+///
+/// doc.edit(6..7, "22");
+/// assert_eq!(doc.text(), "a=1\nb=22\nc=3\n");
+/// assert!(doc.result().is_ok());
+/// assert!(doc.reused() > 0);
+///
+/// doc.edit(4..5, "");
+/// assert!(doc.result().is_err());
+/// ```
+pub struct IncrementalParse<'r> {
+    rules: &'r SetOfRules,
+    options: Options,
+    text: String,
+    result: result::Result<Node, Error>,
+    cache: Cache,
+    reused: usize,
+}
+
+/// Key is (possition.n, rule name)
+type Cache = BTreeMap<(usize, String), Cached>;
+
+/// Result of a rule, with the parts of the status modified
+#[derive(Clone)]
+pub(crate) struct Cached {
+    pub(crate) quiet: bool,
+    pub(crate) end: Possition,
+    /// Chars examined (exclusive)
+    pub(crate) reach: usize,
+    pub(crate) node: Node,
+    /// Only if it was produced inside the rule
+    pub(crate) potential_error: Option<Error>,
+    pub(crate) diagnostics: Vec<Error>,
+    pub(crate) nodes: usize,
+    /// Failures registered inside the rule
+    pub(crate) farthest: Farthest,
+}
+
+/// Edited chars. `start..old_end` was replaced by `start..new_end`
+#[derive(Clone, Copy, Default)]
+struct Edit {
+    start: usize,
+    old_end: usize,
+    new_end: usize,
+}
+
+/// Rules stored on previous parsing, and the ones of the current one
+pub(crate) struct Reuse<'a> {
+    text: &'a str,
+    col_mode: ColMode,
+    /// Possitions after every new line char
+    lines: Vec<Possition>,
+    edit: Edit,
+    old: Cache,
+    new: Cache,
+    reused: usize,
+}
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  A P I
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+impl<'r> IncrementalParse<'r> {
+    /// Parse the text for first time
+    pub fn new(text: &str, rules: &'r SetOfRules, options: &Options) -> Self {
+        let mut inc = IncrementalParse {
+            rules,
+            options: options.clone(),
+            text: text.to_string(),
+            result: Ok(Node::EOF),
+            cache: Cache::new(),
+            reused: 0,
+        };
+        inc.parse(Edit::default());
+        inc
+    }
+
+    /// Replace the ```range``` (in bytes) with ```replacement``` and
+    /// parse again
+    ///
+    /// As ```String::replace_range```, it panics if the range is not
+    /// on char boundaries
+    pub fn edit(&mut self, range: Range<usize>, replacement: &str) -> &result::Result<Node, Error> {
+        let start = self.text[..range.start].chars().count();
+        let edit = Edit {
+            start,
+            old_end: start + self.text[range.clone()].chars().count(),
+            new_end: start + replacement.chars().count(),
+        };
+        self.text.replace_range(range, replacement);
+        self.parse(edit);
+        &self.result
+    }
+
+    /// Current text
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    /// Result of last parsing
+    ///
+    /// The same as ```parse_spanned``` with the full text
+    pub fn result(&self) -> &result::Result<Node, Error> {
+        &self.result
+    }
+
+    /// Rules reused on last parsing
+    pub fn reused(&self) -> usize {
+        self.reused
+    }
+
+    fn parse(&mut self, edit: Edit) {
+        let old = std::mem::take(&mut self.cache);
+        let reuse = Rc::new(RefCell::new(Reuse::new(
+            &self.text,
+            self.options.col_mode,
+            edit,
+            old,
+        )));
+        let status = Status::init_options(self.text.as_str(), self.rules, &self.options);
+        let status = if self.options.has_limits() || self.options.trace_rules {
+            status
+        } else {
+            status.set_reuse(reuse.clone())
+        };
+
+        self.result = crate::first_error(crate::parse_recovering_status(status))
+            .map(|ast| ast.into_spanned());
+
+        let reuse = reuse.replace(Reuse::new("", ColMode::Chars, edit, Cache::new()));
+        self.cache = reuse.new;
+        self.reused = reuse.reused;
+    }
+}
+
+impl<'a> std::fmt::Debug for Reuse<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "Reuse {{ old: {}, new: {}, reused: {} }}",
+            self.old.len(),
+            self.new.len(),
+            self.reused
+        )
+    }
+}
+
+impl<'a> Reuse<'a> {
+    fn new(text: &'a str, col_mode: ColMode, edit: Edit, old: Cache) -> Self {
+        let mut pos = Possition::init();
+        let mut lines = vec![pos.clone()];
+        while let Some(ch) = pos.advance(&text, col_mode) {
+            if ch == '\n' || ch == '\r' {
+                lines.push(pos.clone());
+            }
+        }
+
+        Reuse {
+            text,
+            col_mode,
+            lines,
+            edit,
+            old,
+            new: Cache::new(),
+            reused: 0,
+        }
+    }
+
+    /// Look for a valid rule parsed before the edit
+    ///
+    /// The rules inside it, are also moved to the new cache
+    pub(crate) fn take(&mut self, rule_name: &str, pos: &Possition, quiet: bool) -> Option<Cached> {
+        match self.new.get(&(pos.n, rule_name.to_string())) {
+            Some(cached) if cached.quiet == quiet => return Some(cached.clone()),
+            _ => (),
+        }
+
+        let (n_old, after) = if pos.n < self.edit.start {
+            (pos.n, false)
+        } else if pos.n >= self.edit.new_end {
+            (pos.n - self.edit.new_end + self.edit.old_end, true)
+        } else {
+            return None;
+        };
+
+        let key = (n_old, rule_name.to_string());
+        match self.old.get(&key) {
+            Some(cached) if cached.quiet == quiet && self.valid(cached, after) => (),
+            _ => return None,
+        }
+        let cached = self.old.remove(&key)?;
+
+        let inside = self
+            .old
+            .range((n_old, String::new())..(cached.end.n + 1, String::new()))
+            .filter(|(_, c)| self.valid(c, after))
+            .map(|(k, _)| k.clone())
+            .collect::<Vec<_>>();
+        for (n, name) in inside {
+            if let Some(c) = self.old.remove(&(n, name.clone())) {
+                let (n, c) = self.moved(n, c, after);
+                self.new.insert((n, name), c);
+            }
+        }
+
+        let (_, cached) = self.moved(n_old, cached, after);
+        self.store(rule_name, pos.n, cached.clone());
+        self.reused += 1;
+        Some(cached)
+    }
+
+    pub(crate) fn store(&mut self, rule_name: &str, n: usize, cached: Cached) {
+        self.new.insert((n, rule_name.to_string()), cached);
+    }
+
+    //  Rules after the edit, started after it
+    fn valid(&self, cached: &Cached, after: bool) -> bool {
+        after || cached.reach <= self.edit.start
+    }
+
+    //  Lines on errors could be modified, even before the edit
+    fn moved(&self, n: usize, cached: Cached, after: bool) -> (usize, Cached) {
+        if after {
+            (self.new_n(n), self.remap(cached))
+        } else {
+            let cached = Cached {
+                potential_error: cached.potential_error.map(|e| self.update_lines(e)),
+                diagnostics: cached
+                    .diagnostics
+                    .into_iter()
+                    .map(|e| self.update_lines(e))
+                    .collect(),
+                ..cached
+            };
+            (n, cached)
+        }
+    }
+
+    fn new_n(&self, n_old: usize) -> usize {
+        n_old + self.edit.new_end - self.edit.old_end
+    }
+
+    fn pos_at(&self, n: usize) -> Possition {
+        let line = self.lines.partition_point(|p| p.n <= n) - 1;
+        let mut pos = self.lines[line].clone();
+        for _ in pos.n..n {
+            pos.advance(&self.text, self.col_mode);
+        }
+        pos
+    }
+
+    fn remap_pos(&self, pos: &Possition) -> Possition {
+        self.pos_at(self.new_n(pos.n))
+    }
+
+    fn remap(&self, cached: Cached) -> Cached {
+        let farthest = if cached.farthest.expected.is_empty() {
+            cached.farthest
+        } else {
+            Farthest {
+                pos: self.remap_pos(&cached.farthest.pos),
+                ..cached.farthest
+            }
+        };
+        Cached {
+            quiet: cached.quiet,
+            end: self.remap_pos(&cached.end),
+            reach: self.new_n(cached.reach),
+            node: cached.node.map_possitions(&|pos| self.remap_pos(pos)),
+            potential_error: cached.potential_error.map(|e| self.remap_error(e)),
+            diagnostics: cached
+                .diagnostics
+                .into_iter()
+                .map(|e| self.remap_error(e))
+                .collect(),
+            nodes: cached.nodes,
+            farthest,
+        }
+    }
+
+    fn remap_error(&self, error: Error) -> Error {
+        self.update_lines(Error {
+            pos: self.remap_pos(&error.pos),
+            ..error
+        })
+    }
+
+    fn update_lines(&self, error: Error) -> Error {
+        let (line_before, line_after) = Error::line_context(&self.text, &error.pos);
+        Error {
+            line_before,
+            line_after,
+            ..error
+        }
+    }
+}
@@ -0,0 +1,201 @@
+//-----------------------------------------------------------------------
+//
+//  mod parser::incremental  TEST
+//
+//-----------------------------------------------------------------------
+
+use super::IncrementalParse;
+use crate::parser::{expression::SetOfRules, ColMode, Options};
+use crate::{parse_spanned, rules_from_peg};
+
+//  Same result as parsing the full text
+fn check_full(doc: &IncrementalParse, rules: &SetOfRules, options: &Options) {
+    let full = parse_spanned(doc.text(), rules, options);
+    match (doc.result(), &full) {
+        (Ok(inc), Ok(full)) => assert_eq!(inc, full, "on {:?}", doc.text()),
+        (Err(inc), Err(full)) => {
+            assert_eq!(inc.descr, full.descr, "on {:?}", doc.text());
+            assert_eq!(inc.pos, full.pos);
+            assert_eq!(inc.line_before, full.line_before);
+            assert_eq!(inc.line_after, full.line_after);
+            assert_eq!(inc.expected, full.expected);
+            assert_eq!(inc.kind, full.kind);
+            assert_eq!(inc.parsing_rules, full.parsing_rules);
+        }
+        (inc, _) => panic!("on {:?} {:?} vs {:?}", doc.text(), inc, full),
+    }
+}
+
+fn check_edits(
+    text: &str,
+    edits: &[(std::ops::Range<usize>, &str)],
+    rules: &SetOfRules,
+    options: &Options,
+) -> Vec<usize> {
+    let mut doc = IncrementalParse::new(text, rules, options);
+    check_full(&doc, rules, options);
+    let mut reused = vec![];
+    for (range, replacement) in edits {
+        doc.edit(range.clone(), replacement);
+        check_full(&doc, rules, options);
+        reused.push(doc.reused());
+    }
+    reused
+}
+
+fn rules_stmts() -> SetOfRules {
+    rules_from_peg(
+        r#"
+        main    =   (stmt _eol)*
+        stmt    =   id _ '=' _ expr
+        expr    =   term  (_ ('+' / '-') _ term)*
+        term    =   num / id / '(' _ expr _ ')'
+        id      =   [a-z]+
+        num     =   [0-9]+
+        _       =   ' '*
+        _eol    =   "\r\n" / "\n"
+        "#,
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_incremental_same_as_full() {
+    let rules = rules_stmts();
+    let text = "a = 1\nb = (a + 2)\nc = b - a\n";
+
+    let reused = check_edits(
+        text,
+        &[
+            (10..11, "a + 33"),
+            (0..1, "abc"),
+            (0..0, "z = 0\n"),
+            (14..14, "k = 7\n"),
+        ],
+        &rules,
+        &Options::new(),
+    );
+    assert!(reused.iter().all(|r| *r > 0));
+}
+
+#[test]
+fn test_incremental_errors() {
+    let rules = rules_stmts();
+    let text = "a = 1\nb = 2\nc = 3\n";
+
+    check_edits(
+        text,
+        &[
+            (10..11, ""),
+            (10..10, "2"),
+            (6..7, "+"),
+            (6..7, "b"),
+            (17..18, ""),
+            (12..12, "d = ("),
+        ],
+        &rules,
+        &Options::new(),
+    );
+}
+
+#[test]
+fn test_incremental_lines() {
+    let rules = rules_stmts();
+    let text = "a = 1\r\nb = 2\r\nc = 3\r\n";
+
+    check_edits(
+        text,
+        &[
+            (7..7, "x = 1\r\n"),
+            (0..0, "\r\n"),
+            (0..2, ""),
+            (13..14, "ñ"),
+        ],
+        &rules,
+        &Options::new(),
+    );
+    check_edits(
+        "año = 1\nb = 2\n",
+        &[(0..4, "a"), (0..0, "€"), (9..9, "€")],
+        &rules,
+        &Options::new().col_mode(ColMode::Utf16),
+    );
+}
+
+#[test]
+fn test_incremental_lookahead() {
+    //  'kw' depends on the char after it
+    let rules = rules_from_peg(
+        r#"
+        main    =   (item ' '?)*
+        item    =   kw / id
+        kw      =   'if' !id_char
+        id      =   id_char+
+        id_char =   [a-z]
+        "#,
+    )
+    .unwrap();
+
+    check_edits(
+        "if ab if",
+        &[(2..2, "x"), (2..3, ""), (8..8, "z"), (7..9, "")],
+        &rules,
+        &Options::new(),
+    );
+}
+
+#[test]
+fn test_incremental_left_recursion_and_recover() {
+    let rules = rules_from_peg(
+        r#"
+        main    =   (recover(expr, ';') ';')*
+        expr    =   expr '+' num / num
+        num     =   [0-9]+
+        "#,
+    )
+    .unwrap();
+
+    check_edits(
+        "1+2;3;4+5+6;",
+        &[(2..3, "x"), (2..3, "7"), (5..5, "+"), (0..0, "8+")],
+        &rules,
+        &Options::new(),
+    );
+}
+
+#[test]
+fn test_incremental_packrat() {
+    let rules = rules_stmts();
+    let options = Options::new().packrat(true);
+
+    check_edits(
+        "a = 1\nb = (a + 2)\n",
+        &[(11..12, "b"), (0..1, "x"), (6..7, "")],
+        &rules,
+        &options,
+    );
+}
+
+#[test]
+fn test_incremental_limits() {
+    let rules = rules_stmts();
+    let text = "a = 1\nb = (a + 2)\nc = b - a\n";
+    let edits = [
+        (10..11, "a + 33"),
+        (0..1, "abc"),
+        (6..7, ""),
+        (0..0, "z = 0\n"),
+    ];
+
+    let mut options = vec![Options::new().trace_rules(true)];
+    for n in (5..40).step_by(5) {
+        options.push(Options::new().max_depth(n));
+        options.push(Options::new().max_nodes(n));
+        options.push(Options::new().max_backtracks(n));
+        options.push(Options::new().max_steps(n * 10));
+    }
+    for options in &options {
+        let reused = check_edits(text, &edits, &rules, options);
+        assert!(reused.iter().all(|r| *r == 0));
+    }
+}
@@ -0,0 +1,215 @@
+//! Input to parse
+//!
+//! The parser reads the input through the ```Input``` trait
+//!
+//! Provided inputs
+//!
+//! * ```&str``` The usual one
+//! * ```&[u8]``` Binary formats. Every byte is a char from
+//!   ```'\u{0}'``` to ```'\u{ff}'```
+//! * ```Segments``` A text on several pieces (a rope, i.e.)
+//!
+//! With ```&[u8]```, literals and matches are bytes. Use ```\xHH``` to
+//! write them (or a byte literal ```b"..."``` on peg grammars)
+
+use std::borrow::Cow;
+
+#[cfg(test)]
+mod test;
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  T Y P E S
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+/// A text to parse
+///
+/// Possitions are in bytes (as ```Possition::byte```). On a ```&str```
+/// they are the utf-8 bytes
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::{parse_input, parser::{input::Input, Options}};
+/// use std::borrow::Cow;
+///
+/// //  A text with the chars in reverse order
+/// struct Reversed(Vec<char>);
+///
+/// impl Input for Reversed {
+///     fn len(&self) -> usize {
+///         self.0.len()
+///     }
+///     fn char_at(&self, byte: usize) -> Option<(char, usize)> {
+///         let n = self.0.len().checked_sub(byte + 1)?;
+///         Some((self.0[n], 1))
+///     }
+///     fn slice(&self, from: usize, to: usize) -> Cow<'_, str> {
+///         Cow::Owned((from..to).filter_map(|b| self.char_at(b)).map(|(ch, _)| ch).collect())
+///     }
+/// }
+///
+/// fn main() {
+///     let rules = rules!{ "main" => lit!("abc") };
+///     let input = Reversed("cba".chars().collect());
+///
+///     assert!(parse_input(input, &rules, &Options::new()).is_ok());
+/// }
+/// ```
+pub trait Input {
+    /// Length in bytes
+    fn len(&self) -> usize;
+
+    /// Char starting on ```byte```, and its length in bytes
+    ///
+    /// None at the end, or if ```byte``` is not the start of a char
+    fn char_at(&self, byte: usize) -> Option<(char, usize)>;
+
+    /// Text between two possitions (starts of char)
+    fn slice(&self, from: usize, to: usize) -> Cow<'_, str>;
+
+    /// Nothing to parse
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// A text on several pieces
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::{parse_input, parser::{input::Segments, Options}};
+///
+/// fn main() {
+///     let rules = rules!{ "main" => lit!("hello world") };
+///     let input = Segments::new(&["hel", "", "lo wo", "rld"]);
+///
+///     assert!(parse_input(input, &rules, &Options::new()).is_ok());
+/// }
+/// ```
+#[derive(Debug, Clone)]
+pub struct Segments<'a> {
+    segments: Vec<&'a str>,
+    /// Byte where every segment starts
+    starts: Vec<usize>,
+    len: usize,
+}
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  A P I
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+impl Input for &str {
+    fn len(&self) -> usize {
+        str::len(self)
+    }
+
+    fn char_at(&self, byte: usize) -> Option<(char, usize)> {
+        let ch = self.get(byte..)?.chars().next()?;
+        Some((ch, ch.len_utf8()))
+    }
+
+    fn slice(&self, from: usize, to: usize) -> Cow<'_, str> {
+        Cow::Borrowed(&self[from..to])
+    }
+}
+
+impl<T: Input + ?Sized> Input for &T {
+    fn len(&self) -> usize {
+        (**self).len()
+    }
+
+    fn char_at(&self, byte: usize) -> Option<(char, usize)> {
+        (**self).char_at(byte)
+    }
+
+    fn slice(&self, from: usize, to: usize) -> Cow<'_, str> {
+        (**self).slice(from, to)
+    }
+}
+
+impl Input for &[u8] {
+    fn len(&self) -> usize {
+        <[u8]>::len(self)
+    }
+
+    fn char_at(&self, byte: usize) -> Option<(char, usize)> {
+        self.get(byte).map(|&b| (char::from(b), 1))
+    }
+
+    fn slice(&self, from: usize, to: usize) -> Cow<'_, str> {
+        Cow::Owned(self[from..to].iter().map(|&b| char::from(b)).collect())
+    }
+}
+
+impl<'a> Segments<'a> {
+    /// The text is all the segments together
+    pub fn new(segments: &[&'a str]) -> Self {
+        let segments = segments
+            .iter()
+            .filter(|s| !s.is_empty())
+            .cloned()
+            .collect::<Vec<_>>();
+        let mut starts = Vec::with_capacity(segments.len());
+        let mut len = 0;
+        for s in &segments {
+            starts.push(len);
+            len += s.len();
+        }
+        Segments {
+            segments,
+            starts,
+            len,
+        }
+    }
+
+    //  Segment with the byte, and the byte on it
+    fn locate(&self, byte: usize) -> Option<(usize, usize)> {
+        if byte >= self.len {
+            return None;
+        }
+        let i = self.starts.partition_point(|&start| start <= byte) - 1;
+        Some((i, byte - self.starts[i]))
+    }
+}
+
+impl<'a> Input for Segments<'a> {
+    fn len(&self) -> usize {
+        self.len
+    }
+
+    fn char_at(&self, byte: usize) -> Option<(char, usize)> {
+        let (i, byte) = self.locate(byte)?;
+        self.segments[i].char_at(byte)
+    }
+
+    fn slice(&self, from: usize, to: usize) -> Cow<'_, str> {
+        if from == to {
+            return Cow::Borrowed("");
+        }
+        let (first, from) = self.locate(from).expect("slice out of input");
+        let (last, to) = match self.locate(to) {
+            Some((i, 0)) => (i - 1, self.segments[i - 1].len()),
+            Some(located) => located,
+            None => (
+                self.segments.len() - 1,
+                self.segments[self.segments.len() - 1].len(),
+            ),
+        };
+        if first == last {
+            return Cow::Borrowed(&self.segments[first][from..to]);
+        }
+        let mut text = self.segments[first][from..].to_string();
+        for s in &self.segments[first + 1..last] {
+            text.push_str(s);
+        }
+        text.push_str(&self.segments[last][..to]);
+        Cow::Owned(text)
+    }
+}
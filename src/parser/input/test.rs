@@ -0,0 +1,185 @@
+//-----------------------------------------------------------------------
+//
+//  mod parser::input  TEST
+//
+//-----------------------------------------------------------------------
+
+use super::{Input, Segments};
+use crate::parser::Options;
+use crate::{parse_input, parse_input_spanned, parse_spanned, rules_from_peg};
+
+#[test]
+fn test_input_str() {
+    let text = "añb";
+    assert_eq!(text.char_at(0), Some(('a', 1)));
+    assert_eq!(text.char_at(1), Some(('ñ', 2)));
+    assert_eq!(text.char_at(2), None);
+    assert_eq!(text.char_at(3), Some(('b', 1)));
+    assert_eq!(text.char_at(4), None);
+    assert_eq!(text.slice(1, 3), "ñ");
+}
+
+#[test]
+fn test_input_bytes() {
+    let bytes: &[u8] = &[b'a', 0xf1, 0x00];
+    assert_eq!(Input::len(&bytes), 3);
+    assert_eq!(bytes.char_at(1), Some(('\u{f1}', 1)));
+    assert_eq!(bytes.char_at(3), None);
+    assert_eq!(bytes.slice(0, 2), "a\u{f1}");
+}
+
+#[test]
+fn test_input_segments() {
+    let input = Segments::new(&["ab", "", "cñ", "d"]);
+    assert_eq!(input.len(), 6);
+    assert_eq!(input.char_at(2), Some(('c', 1)));
+    assert_eq!(input.char_at(3), Some(('ñ', 2)));
+    assert_eq!(input.char_at(5), Some(('d', 1)));
+    assert_eq!(input.char_at(6), None);
+    assert_eq!(input.slice(0, 2), "ab");
+    assert_eq!(input.slice(1, 6), "bcñd");
+    assert_eq!(input.slice(3, 5), "ñ");
+    assert_eq!(input.slice(6, 6), "");
+}
+
+#[test]
+fn test_parse_bytes() {
+    let rules = rules_from_peg(
+        r#"
+        main    =   magic  size  data
+        magic   =   'GIF'b  "\x38\x39"b
+        size    =   [\x00-\xff] [\x00-\xff]
+        data    =   (!"\x3b"b .)*  "\x3b"b
+        "#,
+    )
+    .unwrap();
+
+    let gif: &[u8] = &[b'G', b'I', b'F', 0x38, 0x39, 0x01, 0xff, 0x80, 0x3b];
+    assert!(parse_input(gif, &rules, &Options::new()).is_ok());
+
+    let error = parse_input(&gif[..8], &rules, &Options::new())
+        .err()
+        .unwrap();
+    assert_eq!(error.pos.byte, 8);
+    assert_eq!(error.descr, "expected one of any char, ';'");
+
+    //  a byte literal matches the utf-8 bytes
+    let rules = rules_from_peg(
+        r#"
+        main    =   "a\xc3\xb1o"b
+        "#,
+    )
+    .unwrap();
+    assert!(parse_input("año".as_bytes(), &rules, &Options::new()).is_ok());
+    assert!(parse_input("año", &rules, &Options::new()).is_err());
+
+    assert!(rules_from_peg(
+        r#"
+        main    =   "€"b
+        "#,
+    )
+    .is_err());
+}
+
+#[test]
+fn test_matcher_on_bytes() {
+    //  the matcher sees a byte over 0x7f as a char of two bytes
+    let rules = rules_from_peg(
+        r#"
+        main    =   @all
+        "#,
+    )
+    .unwrap()
+    .matcher("all", |rest| Some(rest.len()));
+
+    let bytes: &[u8] = &[0xe9, 0xe9];
+    assert!(parse_input(bytes, &rules, &Options::new()).is_ok());
+
+    let rules = rules_from_peg(
+        r#"
+        main    =   @one  "\xe9"b
+        "#,
+    )
+    .unwrap()
+    .matcher("one", |rest| rest.chars().next().map(char::len_utf8));
+    assert!(parse_input(bytes, &rules, &Options::new()).is_ok());
+}
+
+#[test]
+fn test_matcher_gets_a_part_of_the_input() {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    //  a word, and a quoted text (it needs the closing quote)
+    let longest = Arc::new(AtomicUsize::new(0));
+    let seen = longest.clone();
+    let rules = rules_from_peg(
+        r#"
+        main    =   ((@word / @quoted) ' ')*
+        "#,
+    )
+    .unwrap()
+    .matcher("word", move |rest| {
+        seen.fetch_max(rest.len(), Ordering::Relaxed);
+        Some(
+            rest.find(|c: char| !c.is_ascii_lowercase())
+                .unwrap_or(rest.len()),
+        )
+        .filter(|&l| l > 0)
+    })
+    .matcher("quoted", |rest| {
+        let end = rest.strip_prefix('"')?.find('"')?;
+        Some(end + 2)
+    });
+
+    let long = "x".repeat(1000);
+    let text = format!(
+        "{}\"{}\" {}",
+        "ab cd ".repeat(2000),
+        long,
+        "ef ".repeat(100)
+    );
+    let bytes = text.as_bytes();
+    assert!(parse_input(bytes, &rules, &Options::new()).is_ok());
+    assert!(longest.load(Ordering::Relaxed) < 4 * long.len());
+
+    let (first, second) = text.split_at(text.len() / 2);
+    let segments = Segments::new(&[first, second]);
+    assert!(parse_input(segments, &rules, &Options::new()).is_ok());
+
+    //  the rest of a str is borrowed whole
+    longest.store(0, Ordering::Relaxed);
+    assert!(crate::parse(&text, &rules).is_ok());
+    assert_eq!(longest.load(Ordering::Relaxed), text.len());
+}
+
+#[test]
+fn test_parse_segments() {
+    let rules = rules_from_peg(
+        r#"
+        main    =   (line _eol)*
+        line    =   [ña-z]+ ' ' [0-9]+
+        _eol    =   "\r\n" / "\n"
+        "#,
+    )
+    .unwrap();
+    let options = Options::new();
+
+    let parts = ["añ 1", "2\r", "\nb 3\n", "c", " 4\n"];
+    let text = parts.concat();
+    assert_eq!(
+        parse_input_spanned(Segments::new(&parts), &rules, &options).unwrap(),
+        parse_spanned(&text, &rules, &options).unwrap()
+    );
+
+    let parts = ["a 1\nb", " x\n"];
+    let error = parse_input(Segments::new(&parts), &rules, &options)
+        .err()
+        .unwrap();
+    let full_error = parse_spanned(&parts.concat(), &rules, &options)
+        .err()
+        .unwrap();
+    assert_eq!(error.pos, full_error.pos);
+    assert_eq!(error.line_before, "b ");
+    assert_eq!(error.line_after, "x");
+}
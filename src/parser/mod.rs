@@ -2,7 +2,15 @@
 //! Tools to execute parser of a expression
 
 use crate::ast;
+use std::borrow::Cow;
+use std::cell::{Cell, RefCell};
+use std::collections::HashMap;
+use std::rc::Rc;
 use std::result;
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
 
 //-----------------------------------------------------------------------
 //-----------------------------------------------------------------------
@@ -14,11 +22,16 @@ use std::result;
 //-----------------------------------------------------------------------
 //-----------------------------------------------------------------------
 
+mod analysis;
 /// Support for minimum expressions elements
 pub mod atom;
 pub mod expression;
-
-use std::str::Chars;
+pub mod grammar;
+pub mod incremental;
+pub mod input;
+pub mod observer;
+pub mod stream;
+mod vm;
 
 //-----------------------------------------------------------------------
 //-----------------------------------------------------------------------
@@ -33,23 +46,364 @@ use std::str::Chars;
 pub struct Possition {
     /// char position parsing
     pub n: usize,
+    /// byte position parsing
+    pub byte: usize,
     /// row parsing row
+    /// `\r\n`, `\n` and `\r` are a new line
     pub row: usize,
     /// parsing col
+    /// The units are configured on ```Options::col_mode```
     pub col: usize,
-    /// possition were line started for current pos *m*
+    /// byte possition were line started for current pos *m*
     pub start_line: usize,
 }
 
 impl Possition {
-    fn init() -> Self {
+    pub(crate) fn init() -> Self {
         Self {
             n: 0,
+            byte: 0,
             row: 0,
             col: 0,
             start_line: 0,
         }
     }
+
+    /// Move after next char on text (if any)
+    pub(crate) fn advance(&mut self, text: &dyn input::Input, col_mode: ColMode) -> Option<char> {
+        let (ch, len) = text.char_at(self.byte)?;
+        let prev_byte = self.byte;
+        self.n += 1;
+        self.byte += len;
+        match ch {
+            '\n' => {
+                //  "\r\n" is just one new line
+                if prev_byte == 0 || text.char_at(prev_byte - 1) != Some(('\r', 1)) {
+                    self.row += 1;
+                }
+                self.col = 0;
+                self.start_line = self.byte;
+            }
+            '\r' => {
+                self.col = 0;
+                self.row += 1;
+                self.start_line = self.byte;
+            }
+            _ => {
+                self.col = col_mode.next_col(self.col, ch);
+            }
+        }
+        Some(ch)
+    }
+}
+
+/// How columns are counted on ```Possition```
+#[derive(Clone, Copy, Debug, PartialEq, Default)]
+pub enum ColMode {
+    /// Unicode scalar values (chars)
+    #[default]
+    Chars,
+    /// UTF-16 code units (as many editors)
+    Utf16,
+    /// Chars, but a tab will move to next tab stop
+    TabWidth(usize),
+}
+
+impl ColMode {
+    pub(crate) fn next_col(self, col: usize, ch: char) -> usize {
+        match (self, ch) {
+            (ColMode::Chars, _) => col + 1,
+            (ColMode::Utf16, _) => col + ch.len_utf16(),
+            (ColMode::TabWidth(width), '\t') => {
+                let width = width.max(1);
+                col + width - col % width
+            }
+            (ColMode::TabWidth(_), _) => col + 1,
+        }
+    }
+}
+
+/// Engine running the rules
+///
+/// Both of them produce the same AST and the same errors
+///
+/// With the feature `vm`, the default one is ```Engine::Vm```
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Engine {
+    /// Walks the expressions recursively
+    Tree,
+    /// Rules compiled to instructions, run with an explicit stack
+    ///
+    /// The nesting of the input doesn't grow the stack of the
+    /// program. Observers and incremental parsing run on the
+    /// ```Engine::Tree```, as the rules with left recursion
+    Vm,
+}
+
+impl Engine {
+    const DEFAULT: Engine = if cfg!(feature = "vm") {
+        Engine::Vm
+    } else {
+        Engine::Tree
+    };
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Engine::DEFAULT
+    }
+}
+
+/// Options to configure the parsing
+///
+/// The default values keep the classic behaviour
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::{parse_with_options, parser::Options};
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  or!(
+///                         and!(ref_rule!("a"), lit!("b")),
+///                         and!(ref_rule!("a"), lit!("c"))
+///                     ),
+///        "a"      =>  rep!(lit!("a"), 1)
+///     };
+///
+///     let options = Options::new().packrat(true);
+///     assert!(parse_with_options("aaac", &rules, &options).is_ok())
+/// }
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Options {
+    pub(crate) trace_rules: bool,
+    pub(crate) packrat: bool,
+    pub(crate) no_memo: Vec<String>,
+    pub(crate) start_rule: Option<String>,
+    pub(crate) col_mode: ColMode,
+    pub(crate) labels: Vec<String>,
+    pub(crate) max_depth: Option<usize>,
+    pub(crate) max_steps: Option<usize>,
+    pub(crate) max_backtracks: Option<usize>,
+    pub(crate) max_nodes: Option<usize>,
+    pub(crate) cancel: Option<Arc<AtomicBool>>,
+    pub(crate) engine: Engine,
+}
+
+pub(crate) static DEFAULT_OPTIONS: Options = Options {
+    trace_rules: false,
+    packrat: false,
+    no_memo: Vec::new(),
+    start_rule: None,
+    col_mode: ColMode::Chars,
+    labels: Vec::new(),
+    max_depth: None,
+    max_steps: None,
+    max_backtracks: None,
+    max_nodes: None,
+    cancel: None,
+    engine: Engine::DEFAULT,
+};
+
+/// The cancel flag is checked once every this number of steps
+const CANCEL_CHECK_STEPS: usize = 1024;
+
+impl Options {
+    /// Options with default values
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// It will fill the rules followed till the error
+    /// (using an ```observer::FailureStack```)
+    ///
+    /// Use it just to debug errors
+    pub fn trace_rules(mut self, trace: bool) -> Self {
+        self.trace_rules = trace;
+        self
+    }
+
+    /// Packrat mode
+    ///
+    /// The result of parsing a rule on a position will be memoized
+    /// during the parsing. When an *or* backtracks, the rule will not
+    /// be parsed again on same position
+    ///
+    /// It avoids exponential times, but it will consume more memory
+    pub fn packrat(mut self, active: bool) -> Self {
+        self.packrat = active;
+        self
+    }
+
+    /// Do not memoize this rule in packrat mode
+    ///
+    /// Useful to limit the memory used by small rules called
+    /// very often
+    pub fn no_memo(mut self, rule_name: &str) -> Self {
+        self.no_memo.push(rule_name.to_string());
+        self
+    }
+
+    /// Rule to start parsing. By default `main`
+    pub fn start_rule(mut self, rule_name: &str) -> Self {
+        self.start_rule = Some(rule_name.to_string());
+        self
+    }
+
+    /// How to count the columns on ```Possition```
+    pub fn col_mode(mut self, col_mode: ColMode) -> Self {
+        self.col_mode = col_mode;
+        self
+    }
+
+    /// On errors, the rule will be reported by name
+    /// (i.e. `expected identifier`) instead of the elements
+    /// inside it
+    ///
+    /// ```
+    /// #[macro_use]  extern crate dynparser;
+    /// use dynparser::{parse_with_options, parser::Options};
+    ///
+    /// fn main() {
+    ///     let rules = rules!{
+    ///        "main"   =>  and!(lit!("("), ref_rule!("id"), or!(lit!(")"), lit!(","))),
+    ///        "id"     =>  rep!(ematch!(chlist "", from 'a', to 'z'), 1)
+    ///     };
+    ///
+    ///     let err = parse_with_options("(1", &rules, &Options::new()).err().unwrap();
+    ///     assert_eq!(err.descr, "expected [a-z]");
+    ///
+    ///     let options = Options::new().label("id");
+    ///     let err = parse_with_options("(1", &rules, &options).err().unwrap();
+    ///     assert_eq!(err.descr, "expected id");
+    ///
+    ///     let err = parse_with_options("(ab", &rules, &options).err().unwrap();
+    ///     assert_eq!(err.to_string(), "expected one of ')', ',' at 1:4");
+    /// }
+    /// ```
+    pub fn label(mut self, rule_name: &str) -> Self {
+        self.labels.push(rule_name.to_string());
+        self
+    }
+
+    /// Maximum nesting of expressions while parsing
+    ///
+    /// Deeply nested inputs could overflow the stack. Every level takes
+    /// up to 12 KB of stack on debug builds (4 KB on release ones)
+    ///
+    /// ```max_depth(150)``` is safe on a thread with 2 MB (the default
+    /// for spawned threads and tests). ```Engine::Vm``` counts the nesting
+    /// the same way, but it doesn't use the stack for it
+    ///
+    /// ```
+    /// #[macro_use]  extern crate dynparser;
+    /// use dynparser::{parse_with_options, parser::{ErrKind, Options}};
+    ///
+    /// fn main() {
+    ///     let rules = rules!{
+    ///        "main"   =>  or!(and!(lit!("("), ref_rule!("main"), lit!(")")), lit!("x"))
+    ///     };
+    ///
+    ///     let options = Options::new().max_depth(100);
+    ///     assert!(parse_with_options("((x))", &rules, &options).is_ok());
+    ///
+    ///     let deep = format!("{}x{}", "(".repeat(1000), ")".repeat(1000));
+    ///     let err = parse_with_options(&deep, &rules, &options).err().unwrap();
+    ///     assert_eq!(err.kind, ErrKind::DepthLimit);
+    /// }
+    /// ```
+    pub fn max_depth(mut self, depth: usize) -> Self {
+        self.max_depth = Some(depth);
+        self
+    }
+
+    /// Maximum number of expressions to evaluate
+    pub fn max_steps(mut self, steps: usize) -> Self {
+        self.max_steps = Some(steps);
+        self
+    }
+
+    /// Maximum number of failed options on *or* expressions
+    pub fn max_backtracks(mut self, backtracks: usize) -> Self {
+        self.max_backtracks = Some(backtracks);
+        self
+    }
+
+    /// Maximum number of nodes on the AST
+    pub fn max_nodes(mut self, nodes: usize) -> Self {
+        self.max_nodes = Some(nodes);
+        self
+    }
+
+    /// The parsing will stop when the flag is true
+    ///
+    /// It is checked periodically, it could be modified from
+    /// other thread
+    ///
+    /// ```
+    /// #[macro_use]  extern crate dynparser;
+    /// use dynparser::{parse_with_options, parser::{ErrKind, Options}};
+    /// use std::sync::{atomic::AtomicBool, Arc};
+    ///
+    /// fn main() {
+    ///     let rules = rules!{
+    ///        "main"   =>  rep!(lit!("a"), 0)
+    ///     };
+    ///
+    ///     let cancel = Arc::new(AtomicBool::new(true));
+    ///     let options = Options::new().cancel_flag(cancel);
+    ///     let err = parse_with_options("aaa", &rules, &options).err().unwrap();
+    ///     assert_eq!(err.kind, ErrKind::Cancelled);
+    /// }
+    /// ```
+    pub fn cancel_flag(mut self, cancel: Arc<AtomicBool>) -> Self {
+        self.cancel = Some(cancel);
+        self
+    }
+
+    /// Engine to run the rules
+    ///
+    /// ```
+    /// #[macro_use]  extern crate dynparser;
+    /// use dynparser::{parse_with_options, parser::{Engine, Options}};
+    ///
+    /// fn main() {
+    ///     let rules = rules!{
+    ///        "main"   =>  or!(and!(lit!("("), ref_rule!("main"), lit!(")")), lit!("x"))
+    ///     };
+    ///
+    ///     let deep = format!("{}x{}", "(".repeat(3000), ")".repeat(3000));
+    ///     let options = Options::new().engine(Engine::Vm);
+    ///     assert!(parse_with_options(&deep, &rules, &options).is_ok());
+    /// }
+    /// ```
+    pub fn engine(mut self, engine: Engine) -> Self {
+        self.engine = engine;
+        self
+    }
+
+    pub(crate) fn is_label(&self, rule_name: &str) -> bool {
+        self.labels.iter().any(|r| r == rule_name)
+    }
+
+    pub(crate) fn start_rule_name(&self) -> &str {
+        match self.start_rule {
+            Some(ref rule_name) => rule_name,
+            None => "main",
+        }
+    }
+
+    pub(crate) fn has_limits(&self) -> bool {
+        self.max_depth.is_some()
+            || self.max_steps.is_some()
+            || self.max_backtracks.is_some()
+            || self.max_nodes.is_some()
+    }
+
+    pub(crate) fn memoize(&self, rule_name: &str) -> bool {
+        self.packrat && !self.no_memo.iter().any(|r| r == rule_name)
+    }
 }
 
 /// Error priority
@@ -61,6 +415,26 @@ pub enum ErrPriority {
     Critical,
 }
 
+/// Kind of error
+///
+/// Limits configured on ```Options``` have their own kind.
+/// They are critical, and they cannot be recovered
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrKind {
+    /// The input doesn't match the grammar
+    Syntax,
+    /// Reached ```Options::max_depth```
+    DepthLimit,
+    /// Reached ```Options::max_steps```
+    StepLimit,
+    /// Reached ```Options::max_backtracks```
+    BacktrackLimit,
+    /// Reached ```Options::max_nodes```
+    NodeLimit,
+    /// ```Options::cancel_flag``` was activated
+    Cancelled,
+}
+
 /// Context error information
 #[derive(Debug, Clone)]
 pub struct Error {
@@ -74,72 +448,475 @@ pub struct Error {
     pub line_after: String,
     // Suberrors when parsing an *or* (it could be removed!)
     // pub errors: Vec<Error>,
-    /// Rules path followed till got the error
+    /// Rules path followed till the farthest failure
     /// Only available if trace_rules is on
     pub parsing_rules: Vec<String>,
     /// error priority
     pub priority: ErrPriority,
+    /// Elements expected on the farthest possition reached
+    pub expected: Vec<String>,
+    /// Kind of error
+    pub kind: ErrKind,
+}
+
+impl std::fmt::Display for Error {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(
+            f,
+            "{} at {}:{}",
+            self.descr,
+            self.pos.row + 1,
+            self.pos.col + 1
+        )
+    }
+}
+
+/// Farthest possition with an error, and the elements
+/// expected there
+#[derive(Debug, Clone)]
+pub(crate) struct Farthest {
+    pos: Possition,
+    expected: Vec<String>,
+}
+
+impl Farthest {
+    fn new() -> Self {
+        Farthest {
+            pos: Possition::init(),
+            expected: vec![],
+        }
+    }
+
+    pub(crate) fn add<F>(&mut self, pos: &Possition, descr: F)
+    where
+        F: FnOnce() -> String,
+    {
+        use std::cmp::Ordering;
+        match pos.n.cmp(&self.pos.n) {
+            Ordering::Greater => {
+                self.pos = pos.clone();
+                self.expected = vec![descr()];
+            }
+            Ordering::Equal => {
+                let descr = descr();
+                if !self.expected.contains(&descr) {
+                    self.expected.push(descr);
+                }
+            }
+            Ordering::Less => (),
+        }
+    }
+
+    pub(crate) fn merge(&mut self, other: Farthest) {
+        for descr in other.expected {
+            self.add(&other.pos, || descr);
+        }
+    }
 }
 
 //-----------------------------------------------------------------------
 #[derive(Debug, Clone)]
 pub(crate) struct Status<'a> {
-    pub(crate) text2parse: &'a str,
-    pub(crate) it_parsing: Chars<'a>,
+    pub(crate) text2parse: Text<'a>,
     pub(crate) pos: Possition,
-    pub(crate) rules: &'a expression::SetOfRules,
+    pub(crate) rules: expression::Rules<'a>,
     //  main            =   ("a")*
     //  if you try to parse "abb" i.e.
     //  the error will not be processed full input
     //  It's true, but it could be more useful to know where
     //  it fail trying to repeat
-    pub(crate) potential_error: Option<Error>,
+    pub(crate) potential_error: Option<Rc<Error>>,
+    pub(crate) options: &'a Options,
+    /// Do not register the failures on farthest
+    /// (inside a labelled rule or a negation)
+    pub(crate) quiet: bool,
+    /// Errors registered recovering (they didn't stop the parsing)
+    pub(crate) diagnostics: Vec<Error>,
+    /// Nesting of expressions
+    pub(crate) depth: usize,
+    /// Nodes on the AST (backtracking discards them)
+    pub(crate) nodes: usize,
+    pub(crate) shared: Rc<Shared<'a>>,
+}
 
-    /// If true, it will fill walking rules
-    /// too expensive. For use just to debug errors
-    pub(crate) trace_rules: bool,
-    pub(crate) walking_rules: Vec<String>,
+/// Shared by the whole parsing
+///
+/// Behind one pointer, so the status is small (it's moved on every
+/// nested expression, and copied on backtracking)
+#[derive(Debug)]
+pub(crate) struct Shared<'a> {
+    /// Called entering and leaving the rules
+    pub(crate) observer: Option<Observer<'a>>,
+    /// Rules to add to the errors (```Options::trace_rules```)
+    pub(crate) trace: Option<Rc<RefCell<observer::FailureStack>>>,
+    /// Results of rules on a position
+    pub(crate) memo: RefCell<Memo<'a>>,
+    pub(crate) left_rec: Arc<analysis::LeftRec>,
+    /// Chars the alternatives could start with
+    pub(crate) first: Arc<analysis::FirstSets>,
+    pub(crate) farthest: RefCell<Farthest>,
+    pub(crate) counters: Counters,
+    /// Rules parsed before an edit (incremental parsing)
+    pub(crate) reuse: Option<Rc<RefCell<incremental::Reuse<'a>>>>,
+}
+
+/// Counters to check the limits, shared by the whole parsing
+#[derive(Debug, Default)]
+pub(crate) struct Counters {
+    steps: Cell<usize>,
+    backtracks: Cell<usize>,
+    /// Chars examined (exclusive). Also checking the end of input
+    reach: Cell<usize>,
+}
+
+impl Counters {
+    pub(crate) fn examined(&self, n: usize) {
+        if n + 1 > self.reach.get() {
+            self.reach.set(n + 1);
+        }
+    }
+}
+
+/// Counters at a point, to get what a rule added
+#[derive(Debug)]
+pub(crate) struct Mark {
+    pub(crate) diagnostics: usize,
+    pub(crate) nodes: usize,
+    pub(crate) quiet: bool,
+}
+
+/// Input to parse, shared by the whole parsing
+///
+/// Values on a ```&str``` are borrowed
+#[derive(Clone)]
+pub(crate) enum Text<'a> {
+    Str(&'a str),
+    Input(Rc<dyn input::Input + 'a>),
+}
+
+impl<'a> std::fmt::Debug for Text<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Text({} bytes)", input::Input::len(self))
+    }
+}
+
+impl<'a> input::Input for Text<'a> {
+    fn len(&self) -> usize {
+        match self {
+            Text::Str(s) => s.len(),
+            Text::Input(input) => input.len(),
+        }
+    }
+
+    fn char_at(&self, byte: usize) -> Option<(char, usize)> {
+        match self {
+            Text::Str(s) => s.char_at(byte),
+            Text::Input(input) => input.char_at(byte),
+        }
+    }
+
+    fn slice(&self, from: usize, to: usize) -> Cow<'_, str> {
+        match self {
+            Text::Str(s) => Cow::Borrowed(&s[from..to]),
+            Text::Input(input) => input.slice(from, to),
+        }
+    }
+}
+
+impl<'a> Text<'a> {
+    /// Text between two possitions, borrowed if possible
+    pub(crate) fn value(&self, from: usize, to: usize) -> Cow<'a, str> {
+        match self {
+            Text::Str(s) => Cow::Borrowed(&s[from..to]),
+            Text::Input(input) => Cow::Owned(input.slice(from, to).into_owned()),
+        }
+    }
+
+    /// Possition of the first occurrence of the literal, from a possition
+    pub(crate) fn find(&self, from: usize, literal: &str) -> Option<usize> {
+        let input = match self {
+            Text::Str(s) => return s[from..].find(literal).map(|found| from + found),
+            Text::Input(input) => input,
+        };
+        let starts_with = |mut byte: usize| {
+            literal.chars().all(|ch| match input.char_at(byte) {
+                Some((got, len)) if got == ch => {
+                    byte += len;
+                    true
+                }
+                _ => false,
+            })
+        };
+        let mut byte = from;
+        while !starts_with(byte) {
+            byte += input.char_at(byte)?.1;
+        }
+        Some(byte)
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct Observer<'a>(Rc<RefCell<dyn observer::ParseObserver + 'a>>);
+
+impl<'a> std::fmt::Debug for Observer<'a> {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        write!(f, "Observer")
+    }
+}
+
+/// Memoized result of a rule on a position
+///
+/// The status is not stored, just the parts modified parsing the rule
+#[derive(Debug, Clone)]
+pub(crate) struct MemoEntry<'a> {
+    /// Parsed quiet (no failures registered)
+    pub(crate) quiet: bool,
+    /// Failures registered inside the rule
+    pub(crate) farthest: Farthest,
+    pub(crate) result: Memoized<'a>,
+}
+
+#[derive(Debug, Clone)]
+#[allow(clippy::large_enum_variant)]
+pub(crate) enum Memoized<'a> {
+    Ok {
+        pos: Possition,
+        potential_error: Option<Rc<Error>>,
+        diagnostics: Vec<Error>,
+        nodes: usize,
+        node: ast::borrowed::Node<'a>,
+    },
+    Err(Error),
+}
+
+/// Key is (rule name, Possition.n)
+///
+/// The left recursion leaders growing are kept too
+#[derive(Debug, Default)]
+pub(crate) struct Memo<'a> {
+    entries: HashMap<(&'a str, usize), MemoEntry<'a>>,
+    growing: Vec<usize>,
+}
+
+impl<'a> Memo<'a> {
+    /// A result parsed quiet is not valid for a caller registering
+    /// the failures
+    pub(crate) fn get(&self, key: &(&'a str, usize), quiet: bool) -> Option<&MemoEntry<'a>> {
+        self.entries.get(key).filter(|entry| quiet || !entry.quiet)
+    }
+
+    pub(crate) fn insert(&mut self, key: (&'a str, usize), entry: MemoEntry<'a>) {
+        self.entries.insert(key, entry);
+    }
+
+    pub(crate) fn remove(&mut self, key: &(&'a str, usize)) -> Option<MemoEntry<'a>> {
+        self.entries.remove(key)
+    }
+
+    #[allow(dead_code)] //  used in tests
+    pub(crate) fn contains_key(&self, key: &(&'a str, usize)) -> bool {
+        self.entries.contains_key(key)
+    }
+
+    /// A leader starts growing on a possition. Returns if there was
+    /// another one growing on it
+    pub(crate) fn start_growing(&mut self, n: usize) -> bool {
+        let nested = self.growing.contains(&n);
+        self.growing.push(n);
+        nested
+    }
+
+    pub(crate) fn stop_growing(&mut self) {
+        self.growing.pop();
+    }
 }
 
 impl<'a> Status<'a> {
+    #[allow(dead_code)] //  used in tests
     pub(crate) fn init(t2p: &'a str, rules: &'a expression::SetOfRules) -> Self {
-        Status {
-            text2parse: t2p,
-            it_parsing: t2p.chars(),
-            pos: Possition::init(),
-            trace_rules: false,
-            walking_rules: vec![],
-            rules,
-            potential_error: None,
-        }
+        Self::init_options(t2p, rules, &DEFAULT_OPTIONS)
     }
 
-    pub(crate) fn init_debug(
+    pub(crate) fn init_options(
         t2p: &'a str,
         rules: &'a expression::SetOfRules,
-        trace_rules: bool,
+        options: &'a Options,
     ) -> Self {
+        Self::init_text(Text::Str(t2p), expression::Rules::Set(rules), options)
+    }
+
+    pub(crate) fn init_grammar(
+        t2p: &'a str,
+        grammar: &'a grammar::Grammar,
+        options: &'a Options,
+    ) -> Self {
+        Self::init_text(Text::Str(t2p), expression::Rules::Grammar(grammar), options)
+    }
+
+    pub(crate) fn init_input<I: input::Input + 'a>(
+        input: I,
+        rules: &'a expression::SetOfRules,
+        options: &'a Options,
+    ) -> Self {
+        let rules = expression::Rules::Set(rules);
+        Self::init_text(Text::Input(Rc::new(input)), rules, options)
+    }
+
+    //  The analysis is done once, and kept on the rules (or the grammar)
+    fn init_text(text2parse: Text<'a>, rules: expression::Rules<'a>, options: &'a Options) -> Self {
+        let (left_rec, first) = match rules {
+            expression::Rules::Set(set) => set.analysis(),
+            expression::Rules::Grammar(grammar) => (grammar.left_rec(), grammar.first()),
+        };
         Status {
-            text2parse: t2p,
-            it_parsing: t2p.chars(),
+            text2parse,
             pos: Possition::init(),
-            trace_rules,
-            walking_rules: vec![],
             rules,
             potential_error: None,
+            options,
+            quiet: false,
+            diagnostics: vec![],
+            depth: 0,
+            nodes: 0,
+            shared: Rc::new(Shared {
+                observer: None,
+                trace: None,
+                memo: RefCell::new(Memo::default()),
+                left_rec,
+                first,
+                farthest: RefCell::new(Farthest::new()),
+                counters: Counters::default(),
+                reuse: None,
+            }),
         }
     }
-    pub(crate) fn push_rule(mut self, on_node: &str) -> Self {
-        self.walking_rules.push(on_node.to_string());
+
+    //  Before parsing (the shared part is not shared yet)
+    fn shared_mut(&mut self) -> &mut Shared<'a> {
+        Rc::get_mut(&mut self.shared).expect("status shared before parsing")
+    }
+
+    pub(crate) fn set_observer(
+        mut self,
+        observer: Rc<RefCell<dyn observer::ParseObserver + 'a>>,
+    ) -> Self {
+        self.shared_mut().observer = Some(Observer(observer));
+        self
+    }
+
+    //  The failure stack observes the parsing too
+    pub(crate) fn set_trace(mut self, trace: Rc<RefCell<observer::FailureStack>>) -> Self {
+        self.shared_mut().trace = Some(trace.clone());
+        self.set_observer(trace)
+    }
+
+    //  The rules at the farthest failure till now
+    pub(crate) fn add_trace(&self, error: &mut Error) {
+        if let Some(ref trace) = self.shared.trace {
+            error.parsing_rules = trace.borrow().rules().to_vec();
+        }
+    }
+
+    pub(crate) fn set_reuse(mut self, reuse: Rc<RefCell<incremental::Reuse<'a>>>) -> Self {
+        self.shared_mut().reuse = Some(reuse);
         self
     }
+    /// Call the observer (if any)
+    pub(crate) fn observe<F>(&self, f: F)
+    where
+        F: FnOnce(&mut dyn observer::ParseObserver),
+    {
+        if let Some(ref observer) = self.shared.observer {
+            f(&mut *observer.0.borrow_mut())
+        }
+    }
+    /// Bytes consumed from the begining of text
+    pub(crate) fn consumed(&self) -> usize {
+        self.pos.byte
+    }
     pub(crate) fn set_potential_error(mut self, err: Error) -> Self {
-        self.potential_error = Some(err);
+        self.potential_error = Some(Rc::new(err));
         self
     }
+    /// Register something expected on current possition
+    pub(crate) fn expected(&self, descr: &str) {
+        if !self.quiet {
+            self.shared
+                .farthest
+                .borrow_mut()
+                .add(&self.pos, || descr.to_string());
+        }
+    }
+    pub(crate) fn mark(&self) -> Mark {
+        Mark {
+            diagnostics: self.diagnostics.len(),
+            nodes: self.nodes,
+            quiet: self.quiet,
+        }
+    }
+    /// Called on every expression. Checks depth, steps and cancel flag
+    pub(crate) fn step(&self) -> result::Result<(), Error> {
+        let steps = self.shared.counters.steps.get() + 1;
+        self.shared.counters.steps.set(steps);
+
+        let options = self.options;
+        if matches!(options.max_depth, Some(max) if self.depth > max) {
+            Err(Error::limit(
+                self,
+                ErrKind::DepthLimit,
+                "maximum depth reached",
+            ))
+        } else if matches!(options.max_steps, Some(max) if steps > max) {
+            Err(Error::limit(
+                self,
+                ErrKind::StepLimit,
+                "maximum steps reached",
+            ))
+        } else if steps % CANCEL_CHECK_STEPS == 1
+            && matches!(options.cancel, Some(ref c) if c.load(Ordering::Relaxed))
+        {
+            Err(Error::limit(self, ErrKind::Cancelled, "parsing cancelled"))
+        } else {
+            Ok(())
+        }
+    }
+    /// Called when an option on an *or* fails
+    pub(crate) fn backtrack(&self) -> result::Result<(), Error> {
+        let backtracks = self.shared.counters.backtracks.get() + 1;
+        self.shared.counters.backtracks.set(backtracks);
+        match self.options.max_backtracks {
+            Some(max) if backtracks > max => Err(Error::limit(
+                self,
+                ErrKind::BacktrackLimit,
+                "maximum backtracks reached",
+            )),
+            _ => Ok(()),
+        }
+    }
+    /// Register new nodes on the AST
+    pub(crate) fn add_nodes(mut self, nodes: usize) -> result::Result<Self, Error> {
+        self.count_nodes(nodes)?;
+        Ok(self)
+    }
+    /// Register new nodes on the AST, in place
+    pub(crate) fn count_nodes(&mut self, nodes: usize) -> result::Result<(), Error> {
+        self.nodes += nodes;
+        match self.options.max_nodes {
+            Some(max) if self.nodes > max => Err(Error::limit(
+                self,
+                ErrKind::NodeLimit,
+                "maximum AST nodes reached",
+            )),
+            _ => Ok(()),
+        }
+    }
+    /// Error with all elements expected on the farthest possition
+    pub(crate) fn farthest_error(&self) -> Option<Error> {
+        Error::from_farthest(&self.text2parse, &self.shared.farthest.borrow())
+    }
 }
 
-pub(crate) type Result<'a> = result::Result<(Status<'a>, ast::Node), Error>;
+pub(crate) type Result<'a> = result::Result<(Status<'a>, ast::borrowed::Node<'a>), Error>;
 
 //-----------------------------------------------------------------------
 //-----------------------------------------------------------------------
@@ -160,19 +937,63 @@ mod test;
 //-----------------------------------------------------------------------
 impl Error {
     pub(crate) fn from_status(status: &Status, descr: &str, prior: ErrPriority) -> Self {
+        let (line_before, line_after) = Self::line_context(&status.text2parse, &status.pos);
         Error {
             pos: status.pos.clone(),
             descr: descr.to_owned(),
-            line_before: status.text2parse[status.pos.start_line..status.pos.n].to_string(),
-            line_after: status
-                .it_parsing
-                .clone()
-                .take_while(|&ch| ch != '\n' && ch != '\r')
-                .collect(),
+            line_before,
+            line_after,
             // errors: vec![],
-            parsing_rules: status.walking_rules.clone(),
+            parsing_rules: vec![],
             priority: prior,
+            expected: vec![],
+            kind: ErrKind::Syntax,
+        }
+    }
+
+    pub(crate) fn limit(status: &Status, kind: ErrKind, descr: &str) -> Self {
+        Error {
+            kind,
+            ..Self::from_status(status, descr, ErrPriority::Critical)
+        }
+    }
+
+    pub(crate) fn from_farthest(
+        text2parse: &dyn input::Input,
+        farthest: &Farthest,
+    ) -> Option<Self> {
+        let pos = &farthest.pos;
+        let descr = match farthest.expected.len() {
+            0 => return None,
+            1 => format!("expected {}", farthest.expected[0]),
+            _ => format!("expected one of {}", farthest.expected.join(", ")),
+        };
+        let (line_before, line_after) = Self::line_context(text2parse, pos);
+        Some(Error {
+            pos: pos.clone(),
+            descr,
+            line_before,
+            line_after,
+            parsing_rules: vec![],
+            priority: ErrPriority::Normal,
+            expected: farthest.expected.clone(),
+            kind: ErrKind::Syntax,
+        })
+    }
+
+    //  Line content before and after the possition
+    pub(crate) fn line_context(text2parse: &dyn input::Input, pos: &Possition) -> (String, String) {
+        let mut end = pos.byte;
+        while let Some((ch, len)) = text2parse.char_at(end) {
+            if ch == '\n' || ch == '\r' {
+                break;
+            }
+            end += len;
         }
+        (
+            text2parse.slice(pos.start_line, pos.byte).into_owned(),
+            text2parse.slice(pos.byte, end).into_owned(),
+        )
     }
 
     pub(crate) fn from_status_normal(status: &Status, descr: &str) -> Self {
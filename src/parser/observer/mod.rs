@@ -0,0 +1,252 @@
+//! Hooks to follow the parsing
+//!
+//! The parser will call a ```ParseObserver``` entering and leaving
+//! every rule, and when an *or* backtracks
+//!
+//! Two observers are provided
+//!
+//! * ```TextTrace``` An indented text with the rules called
+//! * ```FailureStack``` The stack of rules at the farthest failure
+
+use crate::parser::{Error, Possition};
+
+#[cfg(test)]
+mod test;
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  T Y P E S
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+/// Callbacks called during the parsing
+///
+/// All of them are optional (by default they do nothing)
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::{parse_with_observer, parser::{Options, observer::ParseObserver, Possition}};
+///
+/// #[derive(Default)]
+/// struct CountRules(usize);
+///
+/// impl ParseObserver for CountRules {
+///     fn enter_rule(&mut self, _rule_name: &str, _pos: &Possition) {
+///         self.0 += 1;
+///     }
+/// }
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  rep!(ref_rule!("a"), 1),
+///        "a"      =>  lit!("a")
+///     };
+///
+///     let mut count = CountRules::default();
+///     assert!(parse_with_observer("aa", &rules, &Options::new(), &mut count).is_ok());
+///     //  main, a, a and the last failing a
+///     assert_eq!(count.0, 4);
+/// }
+/// ```
+pub trait ParseObserver {
+    /// Starting to parse a rule
+    fn enter_rule(&mut self, _rule_name: &str, _pos: &Possition) {}
+
+    /// The rule has been parsed from ```start``` to ```end```
+    fn rule_ok(&mut self, _rule_name: &str, _start: &Possition, _end: &Possition) {}
+
+    /// The rule failed
+    fn rule_err(&mut self, _rule_name: &str, _start: &Possition, _error: &Error) {}
+
+    /// An option on an *or* failed at ```from```. Next one
+    /// will be tried from ```to```
+    fn backtrack(&mut self, _from: &Possition, _to: &Possition) {}
+}
+
+impl<T: ParseObserver + ?Sized> ParseObserver for &mut T {
+    fn enter_rule(&mut self, rule_name: &str, pos: &Possition) {
+        (**self).enter_rule(rule_name, pos)
+    }
+    fn rule_ok(&mut self, rule_name: &str, start: &Possition, end: &Possition) {
+        (**self).rule_ok(rule_name, start, end)
+    }
+    fn rule_err(&mut self, rule_name: &str, start: &Possition, error: &Error) {
+        (**self).rule_err(rule_name, start, error)
+    }
+    fn backtrack(&mut self, from: &Possition, to: &Possition) {
+        (**self).backtrack(from, to)
+    }
+}
+
+/// Two observers at the same time
+impl<A: ParseObserver, B: ParseObserver> ParseObserver for (A, B) {
+    fn enter_rule(&mut self, rule_name: &str, pos: &Possition) {
+        self.0.enter_rule(rule_name, pos);
+        self.1.enter_rule(rule_name, pos);
+    }
+    fn rule_ok(&mut self, rule_name: &str, start: &Possition, end: &Possition) {
+        self.0.rule_ok(rule_name, start, end);
+        self.1.rule_ok(rule_name, start, end);
+    }
+    fn rule_err(&mut self, rule_name: &str, start: &Possition, error: &Error) {
+        self.0.rule_err(rule_name, start, error);
+        self.1.rule_err(rule_name, start, error);
+    }
+    fn backtrack(&mut self, from: &Possition, to: &Possition) {
+        self.0.backtrack(from, to);
+        self.1.backtrack(from, to);
+    }
+}
+
+/// Indented text with the rules called, results and backtracks
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::{parse_with_observer, parser::{Options, observer::TextTrace}};
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  or!(lit!("b"), ref_rule!("a")),
+///        "a"      =>  lit!("a")
+///     };
+///
+///     let mut trace = TextTrace::new();
+///     assert!(parse_with_observer("a", &rules, &Options::new(), &mut trace).is_ok());
+///     assert_eq!(
+///         trace.text(),
+///         "main 1:1\n  backtrack 1:2 -> 1:1\n  a 1:1\n  a ok 1:2\nmain ok 1:2\n"
+///     );
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct TextTrace {
+    text: String,
+    depth: usize,
+}
+
+/// Stack of rules at the farthest failure
+///
+/// Outer rule first
+///
+/// ```
+/// #[macro_use]  extern crate dynparser;
+/// use dynparser::{parse_with_observer, parser::{Options, observer::FailureStack}};
+///
+/// fn main() {
+///     let rules = rules!{
+///        "main"   =>  rep!(ref_rule!("stmt"), 1),
+///        "stmt"   =>  and!(ref_rule!("id"), lit!(";")),
+///        "id"     =>  lit!("a")
+///     };
+///
+///     let mut failure = FailureStack::new();
+///     assert!(parse_with_observer("a;b", &rules, &Options::new(), &mut failure).is_err());
+///     assert_eq!(failure.rules(), &["main", "stmt", "id"]);
+///     assert_eq!(failure.pos().unwrap().n, 3);
+/// }
+/// ```
+#[derive(Debug, Default)]
+pub struct FailureStack {
+    stack: Vec<String>,
+    failure: Option<(Possition, Vec<String>)>,
+}
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  A P I
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+impl TextTrace {
+    /// Create an empty trace
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The trace generated
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+
+    fn add_line(&mut self, line: &str) {
+        for _ in 0..self.depth {
+            self.text.push_str("  ");
+        }
+        self.text.push_str(line);
+        self.text.push('\n');
+    }
+}
+
+impl ParseObserver for TextTrace {
+    fn enter_rule(&mut self, rule_name: &str, pos: &Possition) {
+        self.add_line(&format!("{} {}", rule_name, row_col(pos)));
+        self.depth += 1;
+    }
+
+    fn rule_ok(&mut self, rule_name: &str, _start: &Possition, end: &Possition) {
+        self.depth = self.depth.saturating_sub(1);
+        self.add_line(&format!("{} ok {}", rule_name, row_col(end)));
+    }
+
+    fn rule_err(&mut self, rule_name: &str, _start: &Possition, error: &Error) {
+        self.depth = self.depth.saturating_sub(1);
+        self.add_line(&format!("{} err {}", rule_name, error));
+    }
+
+    fn backtrack(&mut self, from: &Possition, to: &Possition) {
+        self.add_line(&format!("backtrack {} -> {}", row_col(from), row_col(to)));
+    }
+}
+
+impl FailureStack {
+    /// Create an empty failure stack
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Rules followed till the farthest failure
+    pub fn rules(&self) -> &[String] {
+        match self.failure {
+            Some((_, ref rules)) => rules,
+            None => &[],
+        }
+    }
+
+    /// Possition of the farthest failure
+    pub fn pos(&self) -> Option<&Possition> {
+        self.failure.as_ref().map(|(pos, _)| pos)
+    }
+}
+
+impl ParseObserver for FailureStack {
+    fn enter_rule(&mut self, rule_name: &str, _pos: &Possition) {
+        self.stack.push(rule_name.to_string());
+    }
+
+    fn rule_ok(&mut self, _rule_name: &str, _start: &Possition, _end: &Possition) {
+        self.stack.pop();
+    }
+
+    //  Inner rules fail first. On same possition, the deeper stack is kept
+    fn rule_err(&mut self, _rule_name: &str, _start: &Possition, error: &Error) {
+        let farther = match self.failure {
+            Some((ref pos, _)) => error.pos.n > pos.n,
+            None => true,
+        };
+        if farther {
+            self.failure = Some((error.pos.clone(), self.stack.clone()));
+        }
+        self.stack.pop();
+    }
+}
+
+//-----------------------------------------------------------------------
+//  SUPPORT
+
+fn row_col(pos: &Possition) -> String {
+    format!("{}:{}", pos.row + 1, pos.col + 1)
+}
@@ -0,0 +1,123 @@
+//-----------------------------------------------------------------------
+//
+//  mod parser::observer  TEST
+//
+//-----------------------------------------------------------------------
+
+use super::{FailureStack, ParseObserver, TextTrace};
+use crate::parser::{Error, Options, Possition};
+use crate::{parse_debug, parse_recovering_with_options, parse_with_observer, rules_from_peg};
+
+#[derive(Default)]
+struct Events(Vec<String>);
+
+impl ParseObserver for Events {
+    fn enter_rule(&mut self, rule_name: &str, pos: &Possition) {
+        self.0.push(format!("enter {} {}", rule_name, pos.n));
+    }
+    fn rule_ok(&mut self, rule_name: &str, start: &Possition, end: &Possition) {
+        self.0
+            .push(format!("ok {} {}-{}", rule_name, start.n, end.n));
+    }
+    fn rule_err(&mut self, rule_name: &str, start: &Possition, error: &Error) {
+        self.0
+            .push(format!("err {} {}-{}", rule_name, start.n, error.pos.n));
+    }
+    fn backtrack(&mut self, from: &Possition, to: &Possition) {
+        self.0.push(format!("backtrack {}-{}", from.n, to.n));
+    }
+}
+
+#[test]
+fn observer_events() {
+    let rules = rules! {
+       "main"   =>  or!(and!(ref_rule!("a"), lit!("b")), and!(ref_rule!("a"), lit!("c"))),
+       "a"      =>  lit!("a")
+    };
+
+    let mut events = Events::default();
+    assert!(parse_with_observer("ac", &rules, &Options::new(), &mut events).is_ok());
+    assert_eq!(
+        events.0,
+        vec![
+            "enter main 0",
+            "enter a 0",
+            "ok a 0-1",
+            "backtrack 2-0",
+            "enter a 0",
+            "ok a 0-1",
+            "ok main 0-2",
+        ]
+    );
+
+    //  memoized rules are also notified
+    let mut events = Events::default();
+    let options = Options::new().packrat(true);
+    assert!(parse_with_observer("ac", &rules, &options, &mut events).is_ok());
+    assert_eq!(events.0.len(), 7);
+}
+
+#[test]
+fn observer_text_trace() {
+    let rules = rules! {
+       "main"   =>  rep!(ref_rule!("a"), 1),
+       "a"      =>  lit!("a")
+    };
+
+    let mut trace = TextTrace::new();
+    assert!(parse_with_observer("a\na", &rules, &Options::new(), &mut trace).is_err());
+    assert_eq!(
+        trace.text(),
+        "main 1:1\n  a 1:1\n  a ok 1:2\n  a 1:2\n  a err expected literal: <a> at 2:1\nmain ok 1:2\n"
+    );
+}
+
+#[test]
+fn observer_failure_stack() {
+    let rules = rules! {
+       "main"   =>  rep!(ref_rule!("stmt"), 1),
+       "stmt"   =>  or!(and!(ref_rule!("id"), lit!(";")), ref_rule!("num")),
+       "id"     =>  lit!("a"),
+       "num"    =>  lit!("1")
+    };
+
+    let mut failure = FailureStack::new();
+    let mut trace = TextTrace::new();
+    let mut both = (&mut failure, &mut trace);
+    assert!(parse_with_observer("a;a", &rules, &Options::new(), &mut both).is_err());
+    assert_eq!(failure.rules(), &["main", "stmt", "num"]);
+    assert_eq!(failure.pos().unwrap().n, 3);
+    assert!(!trace.text().is_empty());
+
+    let err = parse_debug("a;1x", &rules).err().unwrap();
+    assert_eq!(err.parsing_rules, vec!["main", "stmt", "id"]);
+}
+
+#[test]
+fn observer_failure_stack_per_diagnostic() {
+    let rules = rules_from_peg(
+        r#"
+        main    =   (stmt ' '*)*
+        stmt    =   recover(assign, ';') ';'
+        assign  =   id '=' num
+        id      =   [a-z]+
+        num     =   [0-9]+
+        "#,
+    )
+    .unwrap();
+
+    let options = Options::new().trace_rules(true);
+    let (_, errors) = parse_recovering_with_options("a=1; b=x; 2=3; c=4", &rules, &options);
+    let rules = errors
+        .iter()
+        .map(|e| e.parsing_rules.clone())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        rules,
+        vec![
+            vec!["main", "stmt", "assign", "num"],
+            vec!["main", "stmt", "assign", "id"],
+            vec!["main", "stmt"],
+        ]
+    );
+}
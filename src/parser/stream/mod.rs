@@ -0,0 +1,213 @@
+//! Parse an input received by chunks (logs, network...)
+//!
+//! The input is a sequence of the start rule (```main``` by default).
+//! Every time a chunk is pushed, the complete elements are returned
+//!
+//! An element is complete when the parser didn't look at the end of the
+//! input received (lookahead included), therefore more input
+//! will not modify it. If not, the parser waits for the next chunk
+//!
+//! Only the input after the last element returned is kept in memory
+//!
+//! No parsing state is kept between chunks. An element not complete is
+//! parsed again from its start on every push, so an element received on
+//! ```k``` chunks costs ```O(k * len)```. Push bigger chunks for long
+//! elements
+//!
+//! The spans are on the full input. The ```line_before``` on errors, is
+//! limited to the input after the last element returned, and the
+//! ```line_after``` to the input received
+
+use crate::ast::spanned::Node;
+use crate::parser::{
+    expression, expression::SetOfRules, ErrKind, Error, Options, Possition, Status,
+};
+use std::result;
+
+#[cfg(test)]
+mod test;
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  T Y P E S
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+/// Push parser. It receives the input by chunks
+///
+/// ```
+/// use dynparser::{ast::spanned::Node, parser::{stream::StreamParser, Options}, rules_from_peg};
+///
+/// let rules = rules_from_peg(
+///     r#"
+///
+///     main    =   [a-z]+ '=' [0-9]+ _eol
+///     _eol    =   "\n"
+///
+///     "#,
+/// )
+/// .unwrap();
+///
+/// let mut stream = StreamParser::new(&rules, &Options::new());
+/// assert_eq!(stream.push("a=1\nb=").unwrap().len(), 1);
+/// assert_eq!(stream.push("2").unwrap().len(), 0);
+/// let nodes = stream.push("2\nc=3\n").unwrap();
+///
+/// assert_eq!(nodes.len(), 2);
+/// match nodes[0] {
+///     Node::Rule((_, _, ref span)) => {
+///         assert_eq!(span.start.n, 4);
+///         assert_eq!(span.start.row, 1);
+///     }
+///     _ => panic!("expected rule"),
+/// }
+/// assert!(stream.finish().unwrap().is_empty());
+/// ```
+pub struct StreamParser<'r> {
+    rules: &'r SetOfRules,
+    options: Options,
+    /// Input after the last element returned. Next one starts here
+    buffer: String,
+    /// Chars on buffer
+    chars: usize,
+    /// Possition of the buffer on the full input
+    base: Possition,
+    finished: bool,
+    error: Option<Error>,
+}
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  A P I
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+impl<'r> StreamParser<'r> {
+    /// Nothing received
+    pub fn new(rules: &'r SetOfRules, options: &Options) -> Self {
+        StreamParser {
+            rules,
+            options: options.clone(),
+            buffer: String::new(),
+            chars: 0,
+            base: Possition::init(),
+            finished: false,
+            error: None,
+        }
+    }
+
+    /// Add a chunk of input, and get the elements completed
+    ///
+    /// If an error is found after some elements, the elements are
+    /// returned and the error will be returned on next call (and
+    /// the following ones)
+    pub fn push(&mut self, chunk: &str) -> result::Result<Vec<Node>, Error> {
+        self.buffer.push_str(chunk);
+        self.chars += chunk.chars().count();
+        self.parse_ready()
+    }
+
+    /// No more input. Get the pending elements
+    ///
+    /// It will fail if the input received is not complete
+    pub fn finish(mut self) -> result::Result<Vec<Node>, Error> {
+        self.finished = true;
+        self.parse_ready()
+    }
+
+    /// Input received, not parsed yet
+    pub fn pending(&self) -> &str {
+        &self.buffer
+    }
+
+    fn parse_ready(&mut self) -> result::Result<Vec<Node>, Error> {
+        if let Some(ref e) = self.error {
+            return Err(e.clone());
+        }
+        let mut nodes = vec![];
+        while !self.buffer.is_empty() {
+            match self.parse_next() {
+                Ok(Some(node)) => nodes.push(node),
+                Ok(None) => break,
+                Err(e) => {
+                    self.error = Some(e.clone());
+                    break;
+                }
+            }
+        }
+        match self.error {
+            Some(ref e) if nodes.is_empty() => Err(e.clone()),
+            _ => Ok(nodes),
+        }
+    }
+
+    //  None if more input is needed
+    fn parse_next(&mut self) -> result::Result<Option<Node>, Error> {
+        let status = Status::init_options(self.buffer.as_str(), self.rules, &self.options);
+        let shared = status.shared.clone();
+        let result = expression::parse(status);
+        let reach = shared.counters.reach.get();
+        drop(shared);
+
+        //  "\r" could be followed by "\n" on next chunk
+        let limit = if self.buffer.ends_with('\r') {
+            self.chars - 1
+        } else {
+            self.chars
+        };
+        let complete = match result {
+            Err(ref e) if e.kind != ErrKind::Syntax => true,
+            _ => self.finished || reach <= limit,
+        };
+        if !complete {
+            return Ok(None);
+        }
+
+        let (st, node) = result.map_err(|e| self.shift_error(e))?;
+        if let Some(e) = st.diagnostics.first() {
+            return Err(self.shift_error(e.clone()));
+        }
+        if st.pos.n == 0 {
+            return Err(self.shift_error(crate::not_consumed_error(&st)));
+        }
+        let node = node.into_spanned().map_possitions(&|pos| self.shift(pos));
+        let end = st.pos.clone();
+        drop(st);
+        self.drop_parsed(end);
+        Ok(Some(node))
+    }
+
+    //  Remove from buffer the input before pos
+    fn drop_parsed(&mut self, pos: Possition) {
+        self.base = self.shift(&pos);
+        self.buffer.replace_range(..pos.byte, "");
+        self.chars -= pos.n;
+    }
+
+    //  From buffer to full input. The buffer could start in the middle
+    //  of a line
+    fn shift(&self, pos: &Possition) -> Possition {
+        let (col, start_line) = match pos.row {
+            0 => (self.base.col + pos.col, self.base.start_line),
+            _ => (pos.col, self.base.byte + pos.start_line),
+        };
+        Possition {
+            n: self.base.n + pos.n,
+            byte: self.base.byte + pos.byte,
+            row: self.base.row + pos.row,
+            col,
+            start_line,
+        }
+    }
+
+    fn shift_error(&self, error: Error) -> Error {
+        Error {
+            pos: self.shift(&error.pos),
+            ..error
+        }
+    }
+}
@@ -0,0 +1,123 @@
+//-----------------------------------------------------------------------
+//
+//  mod parser::stream  TEST
+//
+//-----------------------------------------------------------------------
+
+use super::StreamParser;
+use crate::ast::spanned::Node;
+use crate::parser::{expression::SetOfRules, Options};
+use crate::{parse_spanned, rules_from_peg};
+
+fn rules_lines() -> SetOfRules {
+    rules_from_peg(
+        r#"
+        main    =   item*
+        item    =   id _ '=' _ num _eol
+        id      =   [a-z]+
+        num     =   [0-9]+
+        _       =   ' '*
+        _eol    =   "\r\n" / "\n"
+        "#,
+    )
+    .unwrap()
+}
+
+//  Nodes of every element, pushing the text on chunks of size
+fn parse_by_chunks(text: &str, size: usize, rules: &SetOfRules, options: &Options) -> Vec<Node> {
+    let mut stream = StreamParser::new(rules, options);
+    let chars = text.chars().collect::<Vec<_>>();
+    let mut nodes = vec![];
+    for chunk in chars.chunks(size) {
+        nodes.extend(stream.push(&chunk.iter().collect::<String>()).unwrap());
+    }
+    nodes.extend(stream.finish().unwrap());
+    nodes
+}
+
+fn items(node: Node) -> Vec<Node> {
+    match node {
+        Node::Rule((_, nodes, _)) => nodes,
+        _ => panic!("expected rule"),
+    }
+}
+
+#[test]
+fn test_stream_same_as_full() {
+    let rules = rules_lines();
+    let options = Options::new().start_rule("item");
+    let text = "a = 1\nbb = 22\r\nc=3\r\nd = 4\n";
+
+    let full = items(parse_spanned(text, &rules, &Options::new()).unwrap());
+    for size in 1..8 {
+        assert_eq!(parse_by_chunks(text, size, &rules, &options), full);
+    }
+}
+
+#[test]
+fn test_stream_waits_lookahead() {
+    let rules = rules_lines();
+    let options = Options::new().start_rule("item");
+    let mut stream = StreamParser::new(&rules, &options);
+
+    assert!(stream.push("a = 1").unwrap().is_empty());
+    assert!(stream.push("2").unwrap().is_empty());
+    //  could be "\r\n"
+    assert!(stream.push("\r").unwrap().is_empty());
+    assert_eq!(stream.push("\nb = 3").unwrap().len(), 1);
+    assert_eq!(stream.pending(), "b = 3");
+    assert!(stream.push("\n").unwrap().len() == 1);
+    assert!(stream.finish().unwrap().is_empty());
+}
+
+#[test]
+fn test_stream_errors() {
+    let rules = rules_lines();
+    let options = Options::new().start_rule("item");
+
+    let mut stream = StreamParser::new(&rules, &options);
+    assert_eq!(stream.push("a = 1\nb = 2\nc - 3\n").unwrap().len(), 2);
+    let error = stream.push("d = 4\n").err().unwrap();
+    assert_eq!(error.pos.n, 14);
+    assert_eq!(error.pos.row, 2);
+    assert_eq!(error.pos.col, 2);
+    assert_eq!(error.line_before, "c ");
+    assert!(stream.push("").is_err());
+
+    //  incomplete input
+    let mut stream = StreamParser::new(&rules, &options);
+    assert_eq!(stream.push("a = 1\nb = ").unwrap().len(), 1);
+    let error = stream.finish().err().unwrap();
+    assert_eq!(error.pos.n, 10);
+    assert_eq!(error.pos.row, 1);
+}
+
+#[test]
+fn test_stream_long_element_on_small_chunks() {
+    let rules = rules_from_peg(
+        r#"
+        main    =   item*
+        item    =   [a-z]+ ';'
+        "#,
+    )
+    .unwrap();
+    let options = Options::new().start_rule("item");
+
+    //  just one line, trimmed after every element
+    let text = format!("{};b;{};c;", "a".repeat(1000), "x".repeat(300));
+    let full = items(parse_spanned(&text, &rules, &Options::new()).unwrap());
+    for size in &[1, 7, 64] {
+        assert_eq!(parse_by_chunks(&text, *size, &rules, &options), full);
+    }
+
+    let mut stream = StreamParser::new(&rules, &options);
+    for _ in 0..1000 {
+        assert!(stream.push("ab;").unwrap().len() == 1);
+        assert_eq!(stream.pending(), "");
+    }
+    assert!(stream.push("a").unwrap().is_empty());
+    let error = stream.push("-").err().unwrap();
+    assert_eq!(error.pos.n, 3001);
+    assert_eq!(error.pos.col, 3001);
+    assert_eq!(error.pos.row, 0);
+}
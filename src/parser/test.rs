@@ -71,3 +71,493 @@ fn test_parse_expr_repeat_ok() {
     //     assert_eq!(result.status.pos.row, 0);
     // }
 }
+
+#[test]
+fn test_parse_packrat_same_ast() {
+    use crate::parser::Options;
+
+    let rules = rules! {
+        "main" => or![and![ref_rule!("a"), lit!("b")], and![ref_rule!("a"), lit!("c")]],
+        "a"    => rep![or![lit!("x"), ref_rule!("y")], 1],
+        "y"    => lit!("y")
+    };
+    let options = Options::new().packrat(true);
+
+    let (_, ast) = parse(Status::init("xyxyc", &rules)).ok().unwrap();
+    let (status, ast_packrat) = parse(Status::init_options("xyxyc", &rules, &options))
+        .ok()
+        .unwrap();
+    assert_eq!(status.pos.n, 5);
+    assert_eq!(ast, ast_packrat);
+}
+
+#[test]
+fn test_parse_packrat_memoized() {
+    use crate::parser::Options;
+
+    let rules = rules! {
+        "main" => or![and![ref_rule!("a"), lit!("b")], and![ref_rule!("a"), lit!("c")]],
+        "a"    => lit!("a")
+    };
+
+    let options = Options::new().packrat(true);
+    let status = Status::init_options("ac", &rules, &options);
+    let shared = status.shared.clone();
+    parse(status).ok().unwrap();
+    assert!(shared.memo.borrow().contains_key(&("a", 0)));
+    assert!(shared.memo.borrow().contains_key(&("main", 0)));
+
+    let options = Options::new().packrat(true).no_memo("a");
+    let status = Status::init_options("ac", &rules, &options);
+    let shared = status.shared.clone();
+    parse(status).ok().unwrap();
+    assert!(!shared.memo.borrow().contains_key(&("a", 0)));
+    assert!(shared.memo.borrow().contains_key(&("main", 0)));
+}
+
+#[test]
+fn test_parse_packrat_exponential_backtracking() {
+    use crate::parser::Options;
+
+    //  without packrat, each level multiplies by 3 the work
+    let level = |n: usize, next: &str| {
+        (
+            format!("e{}", n),
+            or![
+                and![ref_rule!(next.to_string()), lit!("+")],
+                and![ref_rule!(next.to_string()), lit!("-")],
+                ref_rule!(next.to_string())
+            ],
+        )
+    };
+    let rules = (0..25).fold(
+        rules! {"main" => ref_rule!("e0"), "e25" => lit!("x")},
+        |rules, n| {
+            let (name, expr) = level(n, &format!("e{}", n + 1));
+            rules.add(&name, expr)
+        },
+    );
+
+    let options = Options::new().packrat(true);
+    let (status, _) = parse(Status::init_options("x", &rules, &options))
+        .ok()
+        .unwrap();
+    assert_eq!(status.pos.n, 1);
+}
+
+#[test]
+fn test_parse_packrat_same_errors() {
+    use crate::parser::{Engine, Options};
+    use crate::{parse_recovering_with_options, rules_from_peg};
+
+    //  the rules are memoized first inside a not (quiet)
+    let rules = rules_from_peg(
+        r#"
+        main    =   (!a 'x')  /  a  /  !expr 'y'  /  expr ';'
+        a       =   'a' 'b'
+        expr    =   expr '+' num  /  num
+        num     =   [0-9]+
+        "#,
+    )
+    .unwrap();
+
+    for text in &["ac", "a", "1+", "1+2", "1+2:", "z"] {
+        let mut errors = vec![];
+        for &engine in &[Engine::Tree, Engine::Vm] {
+            for &packrat in &[false, true] {
+                let options = Options::new().engine(engine).packrat(packrat);
+                let (_, found) = parse_recovering_with_options(text, &rules, &options);
+                errors.push(
+                    found
+                        .iter()
+                        .map(|e| (e.pos.n, e.descr.clone(), e.expected.clone()))
+                        .collect::<Vec<_>>(),
+                );
+            }
+        }
+        assert!(!errors[0].is_empty(), "on {:?}", text);
+        for other in &errors[1..] {
+            assert_eq!(&errors[0], other, "on {:?}", text);
+        }
+    }
+}
+
+#[test]
+fn test_parse_direct_left_recursion() {
+    use crate::ast::Node;
+
+    let rules = rules! {
+        "main" => ref_rule!("expr"),
+        "expr" => or![and![ref_rule!("expr"), lit!("-"), ref_rule!("num")], ref_rule!("num")],
+        "num"  => ematch!(chlist "123", from2 vec![])
+    };
+
+    let (status, ast) = parse(Status::init("3-2-1", &rules)).ok().unwrap();
+    assert_eq!(status.pos.n, 5);
+
+    let num = |n: &str| Node::Rule(("num".to_string(), vec![Node::Val(n.to_string())]));
+    let expr = |nodes| Node::Rule(("expr".to_string(), nodes));
+    let minus = || Node::Val("-".to_string());
+    //  ((3-2)-1)
+    let expected = Node::Rule((
+        "main".to_string(),
+        vec![expr(vec![
+            expr(vec![expr(vec![num("3")]), minus(), num("2")]),
+            minus(),
+            num("1"),
+        ])],
+    ));
+    assert_eq!(ast.into_node(), expected);
+}
+
+#[test]
+fn test_parse_indirect_left_recursion() {
+    let rules = rules! {
+        "main" => ref_rule!("a"),
+        "a"    => or![and![ref_rule!("b"), lit!("a")], lit!("x")],
+        "b"    => or![and![ref_rule!("a"), lit!("b")], lit!("y")]
+    };
+
+    for input in &["x", "ya", "xba", "yababa", "xbababa"] {
+        let (status, _) = parse(Status::init(input, &rules)).ok().unwrap();
+        assert_eq!(status.pos.n, input.len());
+    }
+}
+
+#[test]
+fn test_parse_interlocking_left_recursion() {
+    use crate::parser::Options;
+
+    //  two cycles: a -> b -> a  and  b -> b
+    let rules = rules! {
+        "main" => ref_rule!("a"),
+        "a"    => or![and![ref_rule!("b"), lit!("x")], lit!("a")],
+        "b"    => or![
+                    and![ref_rule!("a"), lit!("y")],
+                    and![ref_rule!("b"), lit!("z")],
+                    lit!("b")
+                ]
+    };
+
+    let options = Options::new().packrat(true);
+    for input in &["a", "bx", "ayx", "ayxyx", "bzx", "bzzxyx"] {
+        let (status, _) = parse(Status::init(input, &rules)).ok().unwrap();
+        assert_eq!(status.pos.n, input.len(), "on {:?}", input);
+        let (status, _) = parse(Status::init_options(input, &rules, &options))
+            .ok()
+            .unwrap();
+        assert_eq!(status.pos.n, input.len(), "packrat on {:?}", input);
+    }
+}
+
+#[test]
+fn test_analysis_done_once() {
+    use std::sync::Arc;
+
+    let rules = rules! {
+        "main" => or![and![ref_rule!("main"), lit!("a")], lit!("a")]
+    };
+
+    let left_rec = {
+        let first = Status::init("aa", &rules);
+        let second = Status::init("aaa", &rules);
+        assert!(Arc::ptr_eq(&first.shared.left_rec, &second.shared.left_rec));
+        assert!(Arc::ptr_eq(&first.shared.first, &second.shared.first));
+        first.shared.left_rec.clone()
+    };
+
+    //  added rules are analyzed again
+    let rules = rules.add("b", lit!("b"));
+    let third = Status::init("aa", &rules);
+    assert!(!Arc::ptr_eq(&left_rec, &third.shared.left_rec));
+    assert!(third.shared.left_rec.is_leader("main"));
+}
+
+#[test]
+fn test_analysis_done_again_on_changes() {
+    use crate::parse;
+
+    let mut rules = rules! {
+        "main" => or![ref_rule!("a"), lit!("x")],
+        "a" => lit!("a")
+    };
+    assert!(parse("b", &rules).is_err());
+
+    //  the first set of "a" changes
+    rules.insert("a", lit!("b"));
+    assert!(parse("b", &rules).is_ok());
+    assert!(parse("a", &rules).is_err());
+
+    assert!(rules.remove("a").is_some());
+    assert!(parse("b", &rules).is_err());
+    assert!(!rules.rules().contains_key("a"));
+}
+
+#[test]
+fn test_parse_left_recursion_packrat() {
+    use crate::parser::Options;
+
+    let rules = rules! {
+        "main" => and![ref_rule!("expr"), lit!(";")],
+        "expr" => or![
+                    and![ref_rule!("expr"), lit!("+"), ref_rule!("term")],
+                    ref_rule!("term")
+                ],
+        "term" => or![
+                    and![ref_rule!("term"), lit!("*"), ref_rule!("num")],
+                    ref_rule!("num")
+                ],
+        "num"  => rep![ematch!(chlist "", from '0', to '9'), 1]
+    };
+
+    let options = Options::new().packrat(true);
+    let (_, ast) = parse(Status::init("1+22*3+4;", &rules)).ok().unwrap();
+    let (status, ast_packrat) = parse(Status::init_options("1+22*3+4;", &rules, &options))
+        .ok()
+        .unwrap();
+    assert_eq!(status.pos.n, 9);
+    assert_eq!(ast, ast_packrat);
+
+    assert!(parse(Status::init("1+;", &rules)).is_err());
+}
+
+#[test]
+fn test_parse_start_rule() {
+    use crate::parser::Options;
+
+    let rules = rules! {
+        "main" => rep![ref_rule!("stmt"), 1],
+        "stmt" => and![lit!("a"), lit!(";")]
+    };
+
+    let options = Options::new().start_rule("stmt");
+    let (status, ast) = parse(Status::init_options("a;a;", &rules, &options))
+        .ok()
+        .unwrap();
+    assert_eq!(status.pos.n, 2);
+    assert_eq!(status.consumed(), 2);
+    assert_eq!(
+        ast.into_node(),
+        crate::ast::Node::Rule((
+            "stmt".to_string(),
+            vec![
+                crate::ast::Node::Val("a".to_string()),
+                crate::ast::Node::Val(";".to_string())
+            ]
+        ))
+    );
+
+    let options = Options::new().start_rule("missing");
+    assert!(parse(Status::init_options("a;", &rules, &options)).is_err());
+}
+
+#[test]
+fn test_parse_spans() {
+    use crate::ast::borrowed::Node;
+
+    let rules = rules! {
+        "main" => and![lit!("a\n"), ref_rule!("b"), dot!()],
+        "b"    => rep![lit!("b"), 1]
+    };
+
+    let (_, ast) = parse(Status::init("a\nbbbc", &rules)).ok().unwrap();
+    let (nodes, span) = match ast {
+        Node::Rule((_, nodes, span)) => (nodes, span),
+        _ => panic!("expected rule"),
+    };
+    assert_eq!(span.start.byte, 0);
+    assert_eq!(span.end.byte, 6);
+
+    let span_b = nodes[1].span().unwrap();
+    assert_eq!((span_b.start.byte, span_b.end.byte), (2, 5));
+    assert_eq!((span_b.start.row, span_b.start.col), (1, 0));
+    assert_eq!((span_b.end.row, span_b.end.col), (1, 3));
+
+    let span_c = nodes[2].span().unwrap();
+    assert_eq!((span_c.start.byte, span_c.end.byte), (5, 6));
+}
+
+#[test]
+fn test_spans_compact_prune_flatten() {
+    use crate::ast::spanned::{flat, Node};
+
+    let rules = rules! {
+        "main" => and![ref_rule!("_"), ref_rule!("word"), ref_rule!("_")],
+        "word" => rep![ematch!(chlist "", from 'a', to 'z'), 1],
+        "_"    => rep![lit!(" "), 0]
+    };
+
+    let (_, ast) = parse(Status::init("  abc ", &rules)).ok().unwrap();
+    let ast = ast
+        .into_spanned()
+        .compact()
+        .prune(&["_"])
+        .pass_through_except(&["main"]);
+
+    let val_span = match ast {
+        Node::Rule((_, ref nodes, _)) => match nodes[..] {
+            [Node::Val((ref v, ref span))] => {
+                assert_eq!(v, "abc");
+                span.clone()
+            }
+            _ => panic!("expected one val"),
+        },
+        _ => panic!("expected rule"),
+    };
+    assert_eq!((val_span.start.byte, val_span.end.byte), (2, 5));
+
+    let flat = ast.flatten();
+    assert_eq!(flat.len(), 3);
+    match (&flat[0], &flat[2]) {
+        (flat::Node::BeginRule((_, sb)), flat::Node::EndRule((_, se))) => {
+            assert_eq!((sb.start.byte, sb.end.byte), (0, 6));
+            assert_eq!(sb, se);
+        }
+        _ => panic!("expected begin and end rules"),
+    }
+    assert_eq!(flat[1].span(), Some(&val_span));
+}
+
+#[test]
+fn test_parse_limits() {
+    use crate::parser::{ErrKind, ErrPriority, Options};
+    use crate::{parse_recovering_with_options, parse_with_options};
+
+    let rules = rules! {
+        "main"  => rep![ref_rule!("item"), 0],
+        "item"  => or![and![lit!("("), ref_rule!("main"), lit!(")")], lit!("a")]
+    };
+
+    let deep = format!("{}a{}", "(".repeat(5000), ")".repeat(5000));
+    let err = parse_with_options(&deep, &rules, &Options::new().max_depth(100))
+        .err()
+        .unwrap();
+    assert_eq!(err.kind, ErrKind::DepthLimit);
+    assert_eq!(err.priority, ErrPriority::Critical);
+
+    let options = Options::new().max_depth(200);
+    assert!(parse_with_options("(a)(a)a", &rules, &options).is_ok());
+
+    let options = Options::new().max_steps(10);
+    let err = parse_with_options("aaaaaaaaaa", &rules, &options)
+        .err()
+        .unwrap();
+    assert_eq!(err.kind, ErrKind::StepLimit);
+
+    //  alternatives not starting with next char are not tried
+    let backtracking = rules! {
+        "main"  => rep![ref_rule!("item"), 0],
+        "item"  => or![and![lit!("a"), lit!("b")], lit!("a")]
+    };
+    let options = Options::new().max_backtracks(4);
+    let err = parse_with_options("aaaaa", &backtracking, &options)
+        .err()
+        .unwrap();
+    assert_eq!(err.kind, ErrKind::BacktrackLimit);
+    assert!(parse_with_options("aaaa", &backtracking, &options).is_ok());
+
+    //  main, 3 items and 3 'a'
+    let options = Options::new().max_nodes(7);
+    assert!(parse_with_options("aaa", &rules, &options).is_ok());
+    let err = parse_with_options("aaaa", &rules, &options).err().unwrap();
+    assert_eq!(err.kind, ErrKind::NodeLimit);
+    //  nodes discarded on backtracking are not counted
+    //  main, 2 items, 2 '(', 2 main, 2 items, 2 'a' and 2 ')'
+    let options = Options::new().max_nodes(13);
+    assert!(parse_with_options("(a)a(a)", &rules, &options).is_err());
+    assert!(parse_with_options("(a)(a)", &rules, &options).is_ok());
+    let options = options.packrat(true);
+    assert!(parse_with_options("(a)(a)", &rules, &options).is_ok());
+    let backtracking =
+        rules! {"main" => or![and![lit!("a"), lit!("b")], and![lit!("a"), lit!("c")]]};
+    assert!(parse_with_options("ac", &backtracking, &Options::new().max_nodes(3)).is_ok());
+
+    //  limits are not swallowed by not! or recover!
+    let rules = rules! {
+        "main"  => and![not!(ref_rule!("deep")), recover!(ref_rule!("deep"), lit!(";")), lit!(";")],
+        "deep"  => rep![lit!("a"), 0]
+    };
+    let options = Options::new().max_steps(5);
+    let err = parse_with_options("aaaa;", &rules, &options).err().unwrap();
+    assert_eq!(err.kind, ErrKind::StepLimit);
+    let (ast, errors) = parse_recovering_with_options("aaaa;", &rules, &options);
+    assert!(ast.is_none());
+    assert_eq!(errors[0].kind, ErrKind::StepLimit);
+}
+
+#[test]
+fn test_max_depth_on_small_stack() {
+    use crate::parser::{observer::FailureStack, ErrKind, Options};
+    use crate::{parse_recovering_with_options, parse_with_observer, parse_with_options};
+
+    //  the limit documented for a thread with 2 MB
+    let limited = || {
+        let rules = rules! {
+            "main"  => rep![ref_rule!("item"), 0],
+            "item"  => or![and![lit!("("), ref_rule!("main"), lit!(")")], lit!("a")]
+        };
+        let deep = format!("{}a{}", "(".repeat(5000), ")".repeat(5000));
+        let options = Options::new().max_depth(150);
+
+        let err = parse_with_options(&deep, &rules, &options).err().unwrap();
+        assert_eq!(err.kind, ErrKind::DepthLimit);
+        let options = options.packrat(true);
+        let err = parse_with_options(&deep, &rules, &options).err().unwrap();
+        assert_eq!(err.kind, ErrKind::DepthLimit);
+        let (_, errors) = parse_recovering_with_options(&deep, &rules, &options);
+        assert_eq!(errors[0].kind, ErrKind::DepthLimit);
+        let err = parse_with_observer(&deep, &rules, &options, &mut FailureStack::default())
+            .err()
+            .unwrap();
+        assert_eq!(err.kind, ErrKind::DepthLimit);
+    };
+
+    std::thread::Builder::new()
+        .stack_size(2 * 1024 * 1024)
+        .spawn(limited)
+        .unwrap()
+        .join()
+        .unwrap();
+}
+#[test]
+fn test_parse_borrowed() {
+    use crate::ast::borrowed::Node;
+    use crate::parser::{input::Segments, Options};
+    use crate::{parse_borrowed, parse_input_spanned, parse_spanned};
+    use std::borrow::Cow;
+
+    let rules = rules! {
+        "main"  => rep![or![ref_rule!("word"), lit!(" ")], 0],
+        "word"  => rep![ematch!(chlist "ñ", from 'a', to 'z'), 1]
+    };
+    let text = "año ab c";
+
+    let ast = parse_borrowed(text, &rules).unwrap();
+    let all_borrowed = |nodes: &[Node]| {
+        nodes.iter().all(|n| match n {
+            Node::Val((Cow::Borrowed(_), _)) => true,
+            Node::Rule((name, nodes, _)) => {
+                *name == "word"
+                    && nodes
+                        .iter()
+                        .all(|n| matches!(n, Node::Val((Cow::Borrowed(_), _))))
+            }
+            _ => false,
+        })
+    };
+    match ast {
+        Node::Rule((_, ref nodes, _)) => assert!(all_borrowed(nodes)),
+        _ => panic!("expected rule"),
+    }
+
+    let spanned = parse_spanned(text, &rules, &Options::new()).unwrap();
+    assert_eq!(ast.clone().into_spanned(), spanned);
+    assert_eq!(ast.into_node(), spanned.clone().into_node());
+
+    //  same AST parsing other inputs
+    let input = Segments::new(&["añ", "o ab c"]);
+    assert_eq!(
+        parse_input_spanned(input, &rules, &Options::new()).unwrap(),
+        spanned
+    );
+}
@@ -0,0 +1,1042 @@
+//! Rules compiled to a flat list of instructions, run with an explicit
+//! stack (as LPeg does)
+//!
+//! Every expression starts with a ```Step``` (limits and depth), and an
+//! atom is matched by one instruction. An *or* pushes a choice on the
+//! stack, and the alternative matching commits to it. A rule is a call,
+//! and the rule ends with a return
+//!
+//! A failure pops the stack till an entry handling it (choice, repeat,
+//! not...), restoring the status saved there. Nothing is cloned but
+//! the possitions and the counters
+//!
+//! ```text
+//! main    =   'a'  /  'b' main
+//!
+//! 0   halt                6   step 1
+//! 1   step 0              7   step 2
+//! 2   choice 3, 6         8   atom 'b'
+//! 3   step 1              9   step 2
+//! 4   atom 'a'            10  call main 3
+//! 5   commit 12           11  commit 12
+//!                         12  return
+//! ```
+//!
+//! The rule parsed is called returning to the halt. The program is
+//! compiled on the first parse, and kept with the rules (or the grammar)
+//!
+//! The result (and the errors) are the same as with the tree engine.
+//! The nesting is counted as on it, to apply ```Options::max_depth```
+//!
+//! The rules leading a left recursion are parsed by the tree engine
+
+use crate::ast::{borrowed::Node, spanned::Span};
+use crate::parser::{
+    analysis,
+    atom::{self, Atom, Native},
+    expression::{self, Expression, NRep, Rule, Rules},
+    input::Input,
+    Engine, ErrKind, ErrPriority, Error, Farthest, Mark, MemoEntry, Memoized, Possition, Result,
+    Status,
+};
+use std::collections::HashMap;
+use std::rc::Rc;
+use std::result;
+use std::sync::OnceLock;
+
+#[cfg(test)]
+mod test;
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  T Y P E S
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+#[derive(Debug)]
+enum Instr {
+    /// Start of an expression, with the nesting on the rule
+    Step(usize),
+    Atom(Atom),
+    /// Start of an element checked by a predicate
+    Mark,
+    /// End of the element checked
+    Matched,
+    /// A predicate, checking the text matched by the previous element
+    Predicate(Atom),
+    /// Rule, and nesting of the call on current rule
+    Call(usize, usize),
+    Return,
+    MissingRule(String),
+    /// Alternatives of an *or* (on ```Program::choices```)
+    Choice(usize),
+    /// An alternative matched. Jump to the end of the *or*
+    Commit(usize),
+    /// Negation, jumping to the end when the expression fails
+    Not(usize),
+    /// The negated expression matched
+    FailNot,
+    Peek,
+    PeekEnd,
+    /// Minimum, maximum and end
+    Repeat(usize, Option<usize>, usize),
+    /// Start of the expression repeated
+    RepeatNext(usize),
+    /// An element after a cut (on ```Program::cuts```)
+    Cut(usize),
+    CutEnd,
+    /// Where to skip the input on errors
+    Recover(usize),
+    /// End of the recover
+    RecoverEnd(usize),
+    /// Where to emit the error when the input is finished
+    Sync(usize),
+    /// Where to emit the error
+    SyncFound(usize),
+    RecoverEmit,
+    Halt,
+}
+
+/// It's kept with the rules, so it doesn't borrow them. The atoms are
+/// copied, and other expressions are found by their ```Path```
+#[derive(Debug)]
+pub(crate) struct Program {
+    code: Vec<Instr>,
+    /// Rules and their start
+    rules: Vec<(String, usize)>,
+    /// Rules by name
+    ids: HashMap<String, usize>,
+    choices: Vec<Choice>,
+    /// Elements after a cut
+    cuts: Vec<Path>,
+}
+
+/// An *or*, the key of its first sets, and the start of every
+/// alternative
+#[derive(Debug)]
+struct Choice {
+    path: Path,
+    first: usize,
+    alternatives: Vec<usize>,
+}
+
+/// Where an expression is on the rules. The rule, and the element
+/// taken on every level
+#[derive(Debug, Clone)]
+struct Path {
+    rule: usize,
+    steps: Vec<usize>,
+}
+
+/// Program of a ```SetOfRules``` (or a grammar), compiled on first
+/// parse and kept with it (till the rules change)
+#[derive(Debug, Default)]
+pub(crate) struct Bytecode(OnceLock<Program>);
+
+/// Status to restore on failures
+#[derive(Debug, Clone)]
+struct Saved {
+    pos: Possition,
+    captures: usize,
+    nodes: usize,
+    diagnostics: usize,
+    potential_error: Option<Rc<Error>>,
+    quiet: bool,
+}
+
+#[derive(Debug)]
+enum Frame {
+    Call {
+        rule: usize,
+        ret: usize,
+        /// Depth of the caller
+        base: usize,
+        start: Possition,
+        captures: usize,
+        label: bool,
+        /// If it's memoized, with the failures registered out of it
+        memo: Option<(Mark, Farthest)>,
+    },
+    Choice {
+        saved: Saved,
+        choice: usize,
+        index: usize,
+        error: Option<Box<Error>>,
+        /// Next char, if the first sets are used
+        next: Option<Option<char>>,
+    },
+    Not {
+        saved: Saved,
+        end: usize,
+    },
+    Peek {
+        saved: Saved,
+    },
+    Repeat {
+        saved: Saved,
+        count: usize,
+        min: usize,
+        max: Option<usize>,
+        end: usize,
+    },
+    Cut {
+        pos: Possition,
+        cut: usize,
+    },
+    Recover {
+        saved: Saved,
+        outer: Farthest,
+        skip: usize,
+    },
+    /// Start of the element a predicate checks
+    Mark {
+        byte: usize,
+    },
+    /// Skipping the input, with the error to emit
+    Skip {
+        error: Box<Error>,
+        start: Possition,
+    },
+    Sync {
+        saved: Saved,
+        body: usize,
+        emit: usize,
+    },
+}
+
+struct Vm<'a> {
+    program: &'a Program,
+    status: Status<'a>,
+    potential_error: Option<Rc<Error>>,
+    /// Nodes of the elements parsed, waiting for their rule
+    captures: Vec<Node<'a>>,
+    stack: Vec<Frame>,
+    /// Depth of the rule on parsing
+    base: usize,
+    /// Start of the element matched, for the predicate after it
+    matched: usize,
+}
+
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+//
+//  A P I
+//
+//-----------------------------------------------------------------------
+//-----------------------------------------------------------------------
+
+/// Observers and incremental parsing run on the tree engine
+pub(crate) fn runs(status: &Status) -> bool {
+    status.options.engine == Engine::Vm
+        && status.shared.observer.is_none()
+        && status.shared.reuse.is_none()
+}
+
+pub(crate) fn parse_rule<'a>(status: Status<'a>, rule: Rule<'a>) -> Result<'a> {
+    let program = status.rules.program();
+    let start = program.ids.get(rule.name).cloned().unwrap_or_default();
+    let mut status = status;
+    let potential_error = status.potential_error.take();
+    Vm {
+        program,
+        status,
+        potential_error,
+        captures: vec![],
+        stack: vec![],
+        base: 0,
+        matched: 0,
+    }
+    .run(start)
+}
+
+impl Bytecode {
+    pub(crate) fn get(&self, rules: Rules) -> &Program {
+        self.0.get_or_init(|| compile(rules))
+    }
+}
+
+//-----------------------------------------------------------------------
+//  SUPPORT
+
+//-----------------------------------------------------------------------
+//  C O M P I L E
+
+struct Compiler<'a> {
+    rules: Rules<'a>,
+    ids: HashMap<String, usize>,
+    code: Vec<Instr>,
+    choices: Vec<Choice>,
+    cuts: Vec<Path>,
+    /// Where the expression compiled is
+    path: Path,
+}
+
+//  The first instruction is the halt the rules return to
+fn compile(rules: Rules) -> Program {
+    let all = rules.all();
+    let ids = all
+        .iter()
+        .enumerate()
+        .map(|(id, rule)| (rule.name.to_string(), id))
+        .collect::<HashMap<_, _>>();
+
+    let mut compiler = Compiler {
+        rules,
+        ids,
+        code: vec![Instr::Halt],
+        choices: vec![],
+        cuts: vec![],
+        path: Path {
+            rule: 0,
+            steps: vec![],
+        },
+    };
+    let rules = all
+        .into_iter()
+        .enumerate()
+        .map(|(id, rule)| {
+            let at = compiler.code.len();
+            compiler.path = Path {
+                rule: id,
+                steps: vec![],
+            };
+            compiler.expression(rule.expression, 0);
+            compiler.code.push(Instr::Return);
+            (rule.name.to_string(), at)
+        })
+        .collect();
+
+    Program {
+        code: compiler.code,
+        rules,
+        ids: compiler.ids,
+        choices: compiler.choices,
+        cuts: compiler.cuts,
+    }
+}
+
+impl<'a> Compiler<'a> {
+    fn push(&mut self, instr: Instr) -> usize {
+        self.code.push(instr);
+        self.code.len() - 1
+    }
+
+    fn expression(&mut self, expression: &'a Expression, nesting: usize) {
+        self.push(Instr::Step(nesting));
+        let nested = nesting + 1;
+        match expression {
+            Expression::Simple(atom) => {
+                self.push(Instr::Atom(atom.clone()));
+            }
+            Expression::And(mexpr) => {
+                let mut cut = false;
+                for (i, e) in mexpr.0.iter().enumerate() {
+                    let is_cut = matches!(e, Expression::Cut);
+                    cut = cut || is_cut;
+                    let checked = matches!(mexpr.0.get(i + 1), Some(e) if is_predicate(e));
+                    if checked {
+                        self.push(Instr::Mark);
+                    }
+                    if cut && !is_cut {
+                        let cut = self.cut(i);
+                        self.push(Instr::Cut(cut));
+                        self.element(i, e, nested);
+                        self.push(Instr::CutEnd);
+                    } else {
+                        self.element(i, e, nested);
+                    }
+                    if checked {
+                        self.push(Instr::Matched);
+                    }
+                }
+            }
+            Expression::Or(mexpr) => {
+                let choice = self.choices.len();
+                self.choices.push(Choice {
+                    path: self.path.clone(),
+                    first: analysis::key(mexpr),
+                    alternatives: vec![],
+                });
+                self.push(Instr::Choice(choice));
+                let mut alternatives = vec![];
+                let mut commits = vec![];
+                for (i, e) in mexpr.0.iter().enumerate() {
+                    alternatives.push(self.code.len());
+                    self.nested(i, e, nested);
+                    commits.push(self.push(Instr::Commit(0)));
+                }
+                let end = self.code.len();
+                for commit in commits {
+                    self.code[commit] = Instr::Commit(end);
+                }
+                self.choices[choice].alternatives = alternatives;
+            }
+            Expression::Not(e) => {
+                let not = self.push(Instr::Not(0));
+                self.nested(0, e, nested);
+                self.push(Instr::FailNot);
+                self.code[not] = Instr::Not(self.code.len());
+            }
+            Expression::Peek(e) => {
+                self.push(Instr::Peek);
+                self.nested(0, e, nested);
+                self.push(Instr::PeekEnd);
+            }
+            //  x{0} is not even tried
+            Expression::Repeat(rep) if matches!(rep.max, Some(NRep(0))) => (),
+            Expression::Repeat(rep) => {
+                let max = rep.max.as_ref().map(|max| max.0);
+                let repeat = self.push(Instr::Repeat(rep.min.0, max, 0));
+                self.nested(0, &rep.expression, nested);
+                self.push(Instr::RepeatNext(repeat + 1));
+                self.code[repeat] = Instr::Repeat(rep.min.0, max, self.code.len());
+            }
+            Expression::RuleName(name) => self.call(name, name, nested),
+            Expression::RuleRef(rule_ref) => match self.rules.at(rule_ref.index()) {
+                Some(rule) => self.call(rule.name, rule.name, nested),
+                None => self.call("", &format!("#{}", rule_ref.index()), nested),
+            },
+            Expression::Cut => (),
+            Expression::Recover(e, sync) => {
+                let recover = self.push(Instr::Recover(0));
+                self.nested(0, e, nested);
+                let end = self.push(Instr::RecoverEnd(0));
+                let skip = self.push(Instr::Sync(0));
+                self.nested(1, sync, nested);
+                let found = self.push(Instr::SyncFound(0));
+                let emit = self.push(Instr::RecoverEmit);
+                self.code[recover] = Instr::Recover(skip);
+                self.code[end] = Instr::RecoverEnd(emit + 1);
+                self.code[skip] = Instr::Sync(emit);
+                self.code[found] = Instr::SyncFound(emit);
+            }
+        }
+    }
+
+    //  The element ```step``` of the expression compiled
+    fn nested(&mut self, step: usize, expression: &'a Expression, nesting: usize) {
+        self.path.steps.push(step);
+        self.expression(expression, nesting);
+        self.path.steps.pop();
+    }
+
+    //  A predicate after another element checks the text it matched
+    fn element(&mut self, step: usize, expression: &'a Expression, nesting: usize) {
+        match expression {
+            Expression::Simple(atom) if step > 0 && is_predicate(expression) => {
+                self.push(Instr::Step(nesting));
+                self.push(Instr::Predicate(atom.clone()));
+            }
+            _ => self.nested(step, expression, nesting),
+        }
+    }
+
+    //  The expected error is built from the element when it fails
+    fn cut(&mut self, step: usize) -> usize {
+        let mut path = self.path.clone();
+        path.steps.push(step);
+        self.cuts.push(path);
+        self.cuts.len() - 1
+    }
+
+    //  Missing rules fail when they are called
+    fn call(&mut self, name: &str, descr: &str, nesting: usize) {
+        match self.ids.get(name) {
+            Some(&id) => self.push(Instr::Call(id, nesting)),
+            None => self.push(Instr::MissingRule(descr.to_string())),
+        };
+    }
+}
+
+fn is_predicate(expression: &Expression) -> bool {
+    matches!(
+        expression,
+        Expression::Simple(Atom::Native(Native::Predicate(_)))
+    )
+}
+
+//-----------------------------------------------------------------------
+//  R U N
+
+impl<'a> Vm<'a> {
+    fn run(mut self, start: usize) -> Result<'a> {
+        let code = &self.program.code;
+        let mut next = self.call(start, 0, 0);
+        loop {
+            let pc = match next {
+                Ok(next) => next,
+                Err(e) => self.fail(e)?,
+            };
+            next = match code[pc] {
+                Instr::Halt => return self.finish(),
+                ref instr => self.exec(instr, pc),
+            };
+        }
+    }
+
+    //  Next instruction, or the error
+    fn exec(&mut self, instr: &'a Instr, pc: usize) -> result::Result<usize, Error> {
+        match *instr {
+            Instr::Step(nesting) => {
+                self.status.depth = self.base + nesting;
+                self.status.step()?;
+            }
+            Instr::Atom(ref atom) => {
+                let node = atom::parse_in_place(&mut self.status, atom)?;
+                if let Some(error) = self.status.potential_error.take() {
+                    //  skip until, as the repetition it replaces
+                    self.potential_error = Some(error);
+                }
+                self.capture(node)?;
+            }
+            Instr::Mark => {
+                let byte = self.status.pos.byte;
+                self.stack.push(Frame::Mark { byte });
+            }
+            Instr::Matched => {
+                if let Some(Frame::Mark { byte }) = self.stack.pop() {
+                    self.matched = byte;
+                }
+            }
+            Instr::Predicate(ref atom) => {
+                let node = atom::parse_after(&mut self.status, atom, self.matched)?;
+                self.capture(node)?;
+            }
+            Instr::Call(rule, nesting) => return self.call(rule, nesting, pc + 1),
+            Instr::Return => return self.ret(),
+            Instr::MissingRule(ref name) => {
+                return Err(expression::missing_rule(&self.status, name))
+            }
+            Instr::Choice(choice) => {
+                let next = match self
+                    .status
+                    .shared
+                    .first
+                    .at(self.program.choices[choice].first)
+                {
+                    Some(_) if !self.status.quiet => Some(
+                        self.status
+                            .text2parse
+                            .char_at(self.status.pos.byte)
+                            .map(|(ch, _)| ch),
+                    ),
+                    _ => None,
+                };
+                return self.try_alternatives(choice, 0, None, next);
+            }
+            Instr::Commit(end) => {
+                self.stack.pop();
+                return Ok(end);
+            }
+            Instr::Not(end) => {
+                let saved = self.save();
+                self.stack.push(Frame::Not { saved, end });
+                self.status.quiet = true;
+            }
+            Instr::FailNot => {
+                if let Some(Frame::Not { saved, .. }) = self.stack.pop() {
+                    self.restore(&saved);
+                }
+                return Err(Error::from_status_normal(&self.status, "not"));
+            }
+            Instr::Peek => {
+                let saved = self.save();
+                self.stack.push(Frame::Peek { saved });
+            }
+            Instr::PeekEnd => {
+                if let Some(Frame::Peek { saved }) = self.stack.pop() {
+                    self.restore(&saved);
+                }
+            }
+            Instr::Repeat(min, max, end) => {
+                let saved = self.save();
+                self.stack.push(Frame::Repeat {
+                    saved,
+                    count: 0,
+                    min,
+                    max,
+                    end,
+                });
+            }
+            Instr::RepeatNext(body) => return Ok(self.repeat_next(body, pc + 1)),
+            Instr::Cut(cut) => {
+                let pos = self.status.pos.clone();
+                self.stack.push(Frame::Cut { pos, cut });
+            }
+            Instr::CutEnd => {
+                self.stack.pop();
+            }
+            Instr::Recover(skip) => {
+                let saved = self.save();
+                let outer = self.status.shared.farthest.replace(Farthest::new());
+                self.stack.push(Frame::Recover { saved, outer, skip });
+            }
+            Instr::RecoverEnd(end) => {
+                if let Some(Frame::Recover { outer, .. }) = self.stack.pop() {
+                    let inner = self.status.shared.farthest.replace(outer);
+                    self.status.shared.farthest.borrow_mut().merge(inner);
+                }
+                return Ok(end);
+            }
+            Instr::Sync(emit) => {
+                let saved = self.save();
+                self.stack.push(Frame::Sync {
+                    saved,
+                    body: pc + 1,
+                    emit,
+                });
+                self.status.quiet = true;
+            }
+            Instr::SyncFound(emit) => {
+                if let Some(Frame::Sync { saved, .. }) = self.stack.pop() {
+                    self.restore(&saved);
+                }
+                return Ok(emit);
+            }
+            Instr::RecoverEmit => {
+                if let Some(Frame::Skip { error, start }) = self.stack.pop() {
+                    self.status.count_nodes(1)?;
+                    let span = Span::new(start, self.status.pos.clone());
+                    self.captures.push(Node::Error((error.descr.clone(), span)));
+                    self.status.diagnostics.push(*error);
+                }
+            }
+            Instr::Halt => (),
+        }
+        Ok(pc + 1)
+    }
+
+    //  Some atoms give no node
+    fn capture(&mut self, node: Option<Node<'a>>) -> result::Result<(), Error> {
+        if let Some(node) = node {
+            self.status.count_nodes(1)?;
+            self.captures.push(node);
+        }
+        Ok(())
+    }
+
+    //  The nodes of the rule are on the captures
+    fn finish(mut self) -> Result<'a> {
+        let node = match self.captures.pop() {
+            Some(node) => node,
+            None => return Err(self.logic_error("no node parsed")),
+        };
+        self.status.depth = 0;
+        self.status.potential_error = self.potential_error;
+        Ok((self.status, node))
+    }
+
+    //-------------------------------------------------------------------
+    //  Pop the stack till an entry handling the error. The next
+    //  instruction, or the error if there is no one
+    fn fail(&mut self, error: Error) -> result::Result<usize, Error> {
+        let mut error = error;
+        while let Some(frame) = self.stack.pop() {
+            match frame {
+                Frame::Call {
+                    rule,
+                    base,
+                    start,
+                    label,
+                    memo,
+                    ..
+                } => {
+                    self.base = base;
+                    let name = self.program.rules[rule].0.as_str();
+                    if let Some((mark, outer)) = memo {
+                        let entry = self.memo_entry(&mark, outer, Memoized::Err(error.clone()));
+                        self.status
+                            .shared
+                            .memo
+                            .borrow_mut()
+                            .insert((name, start.n), entry);
+                    }
+                    self.rule_failed(name, &start, label, &error);
+                }
+                Frame::Choice {
+                    saved,
+                    choice,
+                    index,
+                    error: deepest,
+                    next,
+                } => {
+                    if error.priority == ErrPriority::Critical {
+                        continue;
+                    }
+                    self.restore(&saved);
+                    if index + 1 < self.program.choices[choice].alternatives.len() {
+                        if let Err(e) = self.status.backtrack() {
+                            error = e;
+                            continue;
+                        }
+                    }
+                    let deepest = expression::deep_err(deepest.map(|e| *e), error);
+                    match self.try_alternatives(choice, index + 1, deepest, next) {
+                        Ok(pc) => return Ok(pc),
+                        Err(e) => error = e,
+                    }
+                }
+                Frame::Not { saved, end } => {
+                    if error.kind == ErrKind::Syntax {
+                        self.restore(&saved);
+                        return Ok(end);
+                    }
+                }
+                Frame::Peek { .. } | Frame::Mark { .. } | Frame::Skip { .. } => (),
+                Frame::Repeat {
+                    saved,
+                    count,
+                    min,
+                    end,
+                    ..
+                } => {
+                    if count >= min && error.priority == ErrPriority::Normal {
+                        self.restore(&saved);
+                        self.potential_error = Some(Rc::new(error));
+                        return Ok(end);
+                    }
+                }
+                Frame::Cut { pos, cut } => {
+                    if error.priority == ErrPriority::Normal {
+                        self.status.pos = pos;
+                        let descr = self
+                            .expression(&self.program.cuts[cut])
+                            .map(|e| expression::expected_descr(e, &self.status.rules))
+                            .unwrap_or_default();
+                        error = Error::from_status(
+                            &self.status,
+                            &format!("expected {}", descr),
+                            ErrPriority::Critical,
+                        );
+                    }
+                }
+                Frame::Recover { saved, outer, skip } => {
+                    let inner = self.status.shared.farthest.replace(outer);
+                    if error.kind == ErrKind::Syntax {
+                        let error = match error.priority {
+                            ErrPriority::Normal => {
+                                Error::from_farthest(&self.status.text2parse, &inner)
+                                    .unwrap_or(error)
+                            }
+                            ErrPriority::Critical => error,
+                        };
+                        self.restore(&saved);
+                        self.stack.push(Frame::Skip {
+                            error: Box::new(error),
+                            start: saved.pos,
+                        });
+                        return Ok(skip);
+                    }
+                }
+                //  The sync is tried on next char
+                Frame::Sync { saved, body, emit } => {
+                    if error.kind == ErrKind::Syntax {
+                        self.restore(&saved);
+                        if self.status.next_char().is_none() {
+                            return Ok(emit);
+                        }
+                        let saved = self.save();
+                        self.stack.push(Frame::Sync { saved, body, emit });
+                        self.status.quiet = true;
+                        return Ok(body);
+                    }
+                }
+            }
+        }
+        Err(error)
+    }
+
+    //-------------------------------------------------------------------
+    //  The alternatives that can't start with next char are not tried
+    //  Their failures are registered, to get the same errors
+    fn try_alternatives(
+        &mut self,
+        choice: usize,
+        index: usize,
+        deepest: Option<Error>,
+        next: Option<Option<char>>,
+    ) -> result::Result<usize, Error> {
+        let program = self.program;
+        let choice_at = &program.choices[choice];
+        let first = self.status.shared.first.clone();
+        let firsts = match next {
+            Some(_) => first.at(choice_at.first),
+            None => None,
+        };
+        let rejected = |index: usize| match (firsts, next) {
+            (Some(firsts), Some(next)) => {
+                matches!(firsts[index], Some(ref first) if first.rejects(next))
+            }
+            _ => false,
+        };
+
+        let mut deepest = deepest;
+        let alternatives = &choice_at.alternatives;
+        for (index, &alternative) in alternatives.iter().enumerate().skip(index) {
+            if rejected(index) {
+                let e = match self.expression(&choice_at.path) {
+                    Some(Expression::Or(mexpr)) => {
+                        expression::rejected_error(&self.status, &mexpr.0[index])
+                    }
+                    _ => self.logic_error("choice not on the rules"),
+                };
+                deepest = expression::deep_err(deepest, e);
+            } else {
+                let saved = self.save();
+                self.stack.push(Frame::Choice {
+                    saved,
+                    choice,
+                    index,
+                    error: deepest.map(Box::new),
+                    next,
+                });
+                return Ok(alternative);
+            }
+        }
+        Err(deepest.unwrap_or_else(|| expression::or_without_errors(&self.status)))
+    }
+
+    //  Repeat again, or continue after the maximum
+    fn repeat_next(&mut self, body: usize, end: usize) -> usize {
+        let saved = self.save();
+        match self.stack.last_mut() {
+            Some(Frame::Repeat {
+                saved: last,
+                count,
+                max,
+                ..
+            }) => {
+                if matches!(max, Some(max) if *count + 1 == *max) {
+                    self.stack.pop();
+                    end
+                } else {
+                    *count += 1;
+                    *last = saved;
+                    body
+                }
+            }
+            _ => end,
+        }
+    }
+
+    //-------------------------------------------------------------------
+    //  Labelled rules are quiet inside, and they are reported by name
+    fn call(&mut self, id: usize, nesting: usize, ret: usize) -> result::Result<usize, Error> {
+        let program = self.program;
+        let (ref name, at) = program.rules[id];
+        let name = name.as_str();
+        let start = self.status.pos.clone();
+        let label = !self.status.quiet && self.status.options.is_label(name);
+        if label {
+            self.status.quiet = true;
+        }
+
+        let left_rec = self.status.shared.left_rec.clone();
+        let memo = self.status.options.memoize(name) && !left_rec.is_involved(name);
+        let result = if left_rec.is_leader(name) {
+            Some(self.parse_by_tree(name, nesting))
+        } else if memo {
+            let cached = self
+                .status
+                .shared
+                .memo
+                .borrow()
+                .get(&(name, start.n), self.status.quiet)
+                .cloned();
+            cached.map(|entry| self.apply_memo_entry(entry))
+        } else {
+            None
+        };
+
+        match result {
+            Some(Ok(node)) => {
+                self.rule_parsed(label, node);
+                Ok(ret)
+            }
+            Some(Err(e)) => {
+                self.rule_failed(name, &start, label, &e);
+                Err(e)
+            }
+            None => {
+                let mark = if memo {
+                    let outer = self.status.shared.farthest.replace(Farthest::new());
+                    Some((self.status.mark(), outer))
+                } else {
+                    None
+                };
+                self.stack.push(Frame::Call {
+                    rule: id,
+                    ret,
+                    base: self.base,
+                    start,
+                    captures: self.captures.len(),
+                    label,
+                    memo: mark,
+                });
+                self.base += nesting;
+                Ok(at)
+            }
+        }
+    }
+
+    //  The frame is popped after counting the node. If it's
+    //  over the limit, the rule fails
+    fn ret(&mut self) -> result::Result<usize, Error> {
+        self.status.count_nodes(1)?;
+        let (rule, ret, base, start, captures, label, memo) = match self.stack.pop() {
+            Some(Frame::Call {
+                rule,
+                ret,
+                base,
+                start,
+                captures,
+                label,
+                memo,
+            }) => (rule, ret, base, start, captures, label, memo),
+            _ => return Err(self.logic_error("return without call")),
+        };
+        self.base = base;
+        let name = self.program.rules[rule].0.as_str();
+        let nodes = self.captures.split_off(captures);
+        let span = Span::new(start.clone(), self.status.pos.clone());
+        let node = Node::Rule((name, nodes, span));
+
+        if let Some((mark, outer)) = memo {
+            let result = Memoized::Ok {
+                pos: self.status.pos.clone(),
+                potential_error: self.potential_error.clone(),
+                diagnostics: self.status.diagnostics[mark.diagnostics..].to_vec(),
+                nodes: self.status.nodes - mark.nodes,
+                node: node.clone(),
+            };
+            let entry = self.memo_entry(&mark, outer, result);
+            self.status
+                .shared
+                .memo
+                .borrow_mut()
+                .insert((name, start.n), entry);
+        }
+        self.rule_parsed(label, node);
+        Ok(ret)
+    }
+
+    fn rule_parsed(&mut self, label: bool, node: Node<'a>) {
+        if label {
+            self.status.quiet = false;
+        }
+        self.captures.push(node);
+    }
+
+    fn rule_failed(&self, name: &str, start: &Possition, label: bool, error: &Error) {
+        if label && error.priority == ErrPriority::Normal {
+            self.status
+                .shared
+                .farthest
+                .borrow_mut()
+                .add(start, || name.to_string());
+        }
+    }
+
+    //  The failures registered by the rule are added to the outer ones,
+    //  and stored with the result
+    fn memo_entry(&self, mark: &Mark, outer: Farthest, result: Memoized<'a>) -> MemoEntry<'a> {
+        let farthest = self.status.shared.farthest.replace(outer);
+        self.status
+            .shared
+            .farthest
+            .borrow_mut()
+            .merge(farthest.clone());
+        MemoEntry {
+            quiet: mark.quiet,
+            farthest,
+            result,
+        }
+    }
+
+    fn apply_memo_entry(&mut self, entry: MemoEntry<'a>) -> result::Result<Node<'a>, Error> {
+        if !self.status.quiet {
+            self.status
+                .shared
+                .farthest
+                .borrow_mut()
+                .merge(entry.farthest);
+        }
+        match entry.result {
+            Memoized::Ok {
+                pos,
+                potential_error,
+                diagnostics,
+                nodes,
+                node,
+            } => {
+                self.status.pos = pos;
+                self.potential_error = potential_error;
+                self.status.diagnostics.extend(diagnostics);
+                self.status.count_nodes(nodes)?;
+                Ok(node)
+            }
+            Memoized::Err(e) => Err(e),
+        }
+    }
+
+    //  Left recursion
+    fn parse_by_tree(&mut self, name: &str, nesting: usize) -> result::Result<Node<'a>, Error> {
+        let rule = match self.status.rules.get(name) {
+            Some(rule) => rule,
+            None => return Err(self.logic_error("rule not on the rules")),
+        };
+        let mut status = self.status.clone();
+        status.potential_error = self.potential_error.clone();
+        status.depth = self.base + nesting;
+        let (mut status, node) = expression::parse_rule_dispatch(status, rule)?;
+        self.potential_error = status.potential_error.take();
+        self.status = status;
+        Ok(node)
+    }
+
+    //-------------------------------------------------------------------
+    fn save(&self) -> Saved {
+        Saved {
+            pos: self.status.pos.clone(),
+            captures: self.captures.len(),
+            nodes: self.status.nodes,
+            diagnostics: self.status.diagnostics.len(),
+            potential_error: self.potential_error.clone(),
+            quiet: self.status.quiet,
+        }
+    }
+
+    fn restore(&mut self, saved: &Saved) {
+        self.status.pos = saved.pos.clone();
+        self.captures.truncate(saved.captures);
+        self.status.nodes = saved.nodes;
+        self.status.diagnostics.truncate(saved.diagnostics);
+        self.potential_error = saved.potential_error.clone();
+        self.status.quiet = saved.quiet;
+    }
+
+    //  The rules compiled are the ones parsed, so it's found
+    fn expression(&self, path: &Path) -> Option<&'a Expression> {
+        let rule = self.status.rules.get(&self.program.rules[path.rule].0)?;
+        path.steps
+            .iter()
+            .try_fold(rule.expression, |expression, &step| match expression {
+                Expression::And(mexpr) | Expression::Or(mexpr) => mexpr.0.get(step),
+                Expression::Not(e) | Expression::Peek(e) => Some(&**e),
+                Expression::Repeat(rep) => Some(&*rep.expression),
+                Expression::Recover(e, _) if step == 0 => Some(&**e),
+                Expression::Recover(_, sync) => Some(&**sync),
+                _ => None,
+            })
+    }
+
+    fn logic_error(&self, descr: &str) -> Error {
+        Error::from_status(
+            &self.status,
+            &format!("LOGIC ERROR!!! {}", descr),
+            ErrPriority::Critical,
+        )
+    }
+}
@@ -0,0 +1,197 @@
+//-----------------------------------------------------------------------
+//
+//  mod parser::vm  TEST
+//
+//-----------------------------------------------------------------------
+
+use crate::parser::{expression::SetOfRules, grammar::Grammar, Engine, ErrKind, Error, Options};
+use crate::{parse_borrowed_with_options, parse_recovering_spanned, rules_from_peg};
+
+fn rules_calc() -> SetOfRules {
+    rules_from_peg(
+        r#"
+        main    =   (stmt _)*
+        stmt    =   recover(id _ '=' _ ^ expr, ';') ';'  /  !'x' expr ';'
+        expr    =   expr _ ('+' / '-') _ term  /  term
+        term    =   '(' _ ^ expr _ ')'  /  num  /  id  /  &'#' [#] [a-z]{1,3}
+        num     =   [0-9]+
+        id      =   [a-z]+
+        _       =   ' '*
+        "#,
+    )
+    .unwrap()
+}
+
+fn check_errors(vm: &[Error], tree: &[Error], text: &str) {
+    assert_eq!(vm.len(), tree.len(), "on {:?}", text);
+    for (vm, tree) in vm.iter().zip(tree) {
+        assert_eq!(vm.descr, tree.descr, "on {:?}", text);
+        assert_eq!(vm.pos, tree.pos, "on {:?}", text);
+        assert_eq!(vm.expected, tree.expected, "on {:?}", text);
+        assert_eq!(vm.priority, tree.priority, "on {:?}", text);
+        assert_eq!(vm.kind, tree.kind, "on {:?}", text);
+    }
+}
+
+fn check_same(text: &str, rules: &SetOfRules, options: Options) {
+    let (vm_ast, vm_errors) =
+        parse_recovering_spanned(text, rules, &options.clone().engine(Engine::Vm));
+    let (tree_ast, tree_errors) =
+        parse_recovering_spanned(text, rules, &options.engine(Engine::Tree));
+    assert_eq!(vm_ast, tree_ast, "on {:?}", text);
+    check_errors(&vm_errors, &tree_errors, text);
+}
+
+const TEXTS: &[&str] = &[
+    "a = 1; b = (a + 2) - c;",
+    "1 + 2; #ab;",
+    "a = ; b = 2;",
+    "a = (1 + ; b = 3",
+    "x;",
+    "(1 + 2",
+    "a = #abcd;",
+    "",
+];
+
+#[test]
+fn test_vm_same_as_tree() {
+    let rules = rules_calc();
+    for text in TEXTS {
+        check_same(text, &rules, Options::new());
+        check_same(text, &rules, Options::new().packrat(true).no_memo("_"));
+        check_same(text, &rules, Options::new().label("expr").label("id"));
+    }
+    check_same("(a+1)", &rules, Options::new().start_rule("term"));
+    check_same("a", &rules, Options::new().start_rule("missing"));
+}
+
+#[test]
+fn test_vm_same_limits() {
+    let rules = rules_calc();
+    let text = "a = (((1 + 2) - 3) + b); c = ((2));";
+    for options in vec![
+        Options::new().max_depth(12),
+        Options::new().max_steps(40),
+        Options::new().max_backtracks(3),
+        Options::new().max_nodes(20),
+    ] {
+        check_same(text, &rules, options);
+    }
+}
+
+#[test]
+fn test_vm_compiled_grammar() {
+    let grammar = Grammar::compile(rules_calc()).unwrap();
+    for text in TEXTS {
+        let vm = grammar.parse_spanned(text, &Options::new().engine(Engine::Vm));
+        let tree = grammar.parse_spanned(text, &Options::new().engine(Engine::Tree));
+        match (vm, tree) {
+            (Ok(vm), Ok(tree)) => assert_eq!(vm, tree, "on {:?}", text),
+            (Err(vm), Err(tree)) => check_errors(&[vm], &[tree], text),
+            (vm, tree) => panic!("on {:?} {:?} vs {:?}", text, vm, tree),
+        }
+    }
+}
+
+#[test]
+fn test_vm_deep_nesting() {
+    let rules = rules! {
+        "main"  =>  or!(and!(lit!("("), ref_rule!("main"), lit!(")")), lit!("x"))
+    };
+    let deep = format!("{}x{}", "(".repeat(2000), ")".repeat(2000));
+
+    let options = Options::new().engine(Engine::Vm);
+    assert!(parse_borrowed_with_options(&deep, &rules, &options).is_ok());
+
+    let options = options.max_depth(1000);
+    let err = parse_borrowed_with_options(&deep, &rules, &options)
+        .err()
+        .unwrap();
+    assert_eq!(err.kind, ErrKind::DepthLimit);
+}
+
+#[test]
+fn test_vm_skip_until() {
+    let rules = rules_from_peg(
+        r#"
+        main    =   (comment / id / ' ')*
+        comment =   '/*' (!'*/' .)* ^ '*/'
+        id      =   [a-z]+
+        "#,
+    )
+    .unwrap();
+    for text in &["a /* b * c */ d", "a /**/", "a /* b", "/* a */ 1"] {
+        check_same(text, &rules, Options::new());
+    }
+}
+
+#[test]
+fn test_vm_natives() {
+    let rules = rules_from_peg(
+        r#"
+        main    =   (stmt ' '?)*
+        stmt    =   &?never 'x'  /  kw ^ &?is_kw ':' @num  /  [a-z]+ &?short
+        kw      =   [a-z]+
+        "#,
+    )
+    .unwrap()
+    .matcher("num", |rest| {
+        Some(
+            rest.find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len()),
+        )
+        .filter(|&l| l > 0)
+    })
+    .predicate("never", |_| false)
+    .predicate("is_kw", |kw| kw == "let")
+    .predicate("short", |id| id.len() < 4);
+
+    for text in &["let:12 abc", "let:x", "abcd", "x let:1", "lex:1"] {
+        check_same(text, &rules, Options::new());
+    }
+}
+
+#[test]
+fn test_vm_compiled_once() {
+    use crate::parser::expression::Rules;
+    use std::ptr;
+
+    let rules = rules_calc();
+    let options = Options::new().engine(Engine::Vm);
+    let program = Rules::Set(&rules).program();
+    for text in TEXTS {
+        parse_recovering_spanned(text, &rules, &options);
+        assert!(ptr::eq(program, Rules::Set(&rules).program()));
+    }
+
+    //  added rules are compiled again
+    let rules = rules.add("extra", lit!("x"));
+    let (ast, errors) = parse_recovering_spanned("a = 1;", &rules, &options);
+    assert!(ast.is_some() && errors.is_empty());
+
+    let grammar = Grammar::compile(rules).unwrap();
+    let program = Rules::Grammar(&grammar).program();
+    for text in TEXTS {
+        let _ = grammar.parse_spanned(text, &options);
+        assert!(ptr::eq(program, Rules::Grammar(&grammar).program()));
+    }
+}
+
+#[test]
+fn test_vm_compiled_again_on_changes() {
+    let mut rules = rules_calc();
+    let vm = Options::new().engine(Engine::Vm);
+    assert!(parse_recovering_spanned("a = 1.5;", &rules, &vm).1.len() == 1);
+
+    rules.insert("num", rep!(ematch!(chlist ".", from '0', to '9'), 1));
+    let (ast, errors) = parse_recovering_spanned("a = 1.5;", &rules, &vm);
+    assert!(ast.is_some() && errors.is_empty());
+    for text in TEXTS.iter().chain(&["x = 1.5 + 2.;"]) {
+        check_same(text, &rules, Options::new());
+    }
+
+    rules.remove("num");
+    for text in TEXTS {
+        check_same(text, &rules, Options::new());
+    }
+}
@@ -41,7 +41,7 @@
 
 use crate::parser::{
     atom,
-    atom::Atom,
+    atom::{Atom, Native},
     expression::{self, Expression},
 };
 
@@ -67,8 +67,13 @@ fn expr2code(expr: &Expression) -> String {
         Expression::And(mexpr) => format!("and!({})", mexpr2code(mexpr)),
         Expression::Or(mexpr) => format!("or!({})", mexpr2code(mexpr)),
         Expression::Not(e) => format!("not!({})", expr2code(e)),
+        Expression::Peek(e) => format!("peek!({})", expr2code(e)),
         Expression::Repeat(rep) => repeat2code(rep),
         Expression::RuleName(rname) => format!(r##"ref_rule!(r#"{}"#)"##, rname),
+        //  just on a compiled grammar
+        Expression::RuleRef(_) => unreachable!("resolved reference on a set of rules"),
+        Expression::Cut => "cut!()".to_string(),
+        Expression::Recover(e, sync) => format!("recover!({}, {})", expr2code(e), expr2code(sync)),
     }
 }
 
@@ -92,10 +97,30 @@ fn atom2code(atom: &Atom) -> String {
 
     match atom {
         Atom::Literal(s) => format!(r#"lit!("{}")"#, replace_esc(s.to_string())),
+        Atom::ILiteral(s) => format!(r#"ilit!("{}")"#, replace_esc(s.to_string())),
         Atom::Error(s) => format!(r#"error!("{}")"#, replace_esc(s.to_string())),
         Atom::Match(mrules) => match_rules2code(mrules),
         Atom::Dot => "dot!()".to_string(),
         Atom::EOF => "eof!()".to_string(),
+        Atom::Native(Native::Matcher(s)) => format!(r#"matcher!("{}")"#, s),
+        Atom::Native(Native::Predicate(s)) => format!(r#"predicate!("{}")"#, s),
+        Atom::SkipUntil(s) => format!(r#"skip_until!("{}")"#, replace_esc(s.to_string())),
+        Atom::Trie(trie) => {
+            let literals = trie
+                .literals()
+                .iter()
+                .map(|l| format!(r#""{}""#, replace_esc(l.to_string())))
+                .collect::<Vec<_>>();
+            if trie.is_longest() {
+                format!("longest!({})", literals.join(", "))
+            } else {
+                let literals = literals
+                    .iter()
+                    .map(|l| format!("lit!({})", l))
+                    .collect::<Vec<_>>();
+                format!("or!({})", literals.join(", "))
+            }
+        }
     }
 }
 
@@ -103,17 +128,42 @@ fn match_rules2code(mrules: &atom::MatchRules) -> String {
     fn bounds2code(acc: String, bounds: &[(char, char)]) -> String {
         match bounds.split_first() {
             Some(((f, t), rest)) => {
-                format!(", from '{}', to '{}' {}", f, t, bounds2code(acc, rest))
+                format!(", from {:?}, to {:?} {}", f, t, bounds2code(acc, rest))
             }
             None => acc,
         }
     }
 
-    format!(
-        r##"ematch!(chlist r#"{}"#  {})"##,
-        &mrules.0,
-        bounds2code(String::new(), &mrules.1)
-    )
+    let negated = if mrules.2 { "not " } else { "" };
+    if !mrules.3.is_empty() {
+        let ranges = mrules
+            .1
+            .iter()
+            .fold(String::new(), |acc, (f, t)| match acc.len() {
+                0 => format!("({:?}, {:?})", f, t),
+                _ => format!("{}, ({:?}, {:?})", acc, f, t),
+            });
+        let props = mrules
+            .3
+            .iter()
+            .fold(String::new(), |acc, p| match acc.len() {
+                0 => format!("{:?}", p.name()),
+                _ => format!("{}, {:?}", acc, p.name()),
+            });
+        format!(
+            "ematch!({}chlist {:?}, from2 vec![{}], props {})",
+            negated, &mrules.0, ranges, props
+        )
+    } else if mrules.1.is_empty() {
+        format!("ematch!({}chlist {:?}, from2 vec![])", negated, &mrules.0)
+    } else {
+        format!(
+            "ematch!({}chlist {:?}  {})",
+            negated,
+            &mrules.0,
+            bounds2code(String::new(), &mrules.1)
+        )
+    }
 }
 
 fn repeat2code(rep: &expression::RepInfo) -> String {
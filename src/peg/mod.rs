@@ -10,7 +10,8 @@ use crate::ast::{self, flat};
 use crate::parse;
 use crate::parser::{
     self,
-    expression::{self, Expression},
+    atom::{Atom, MatchRules, Property},
+    expression::{self, Expression, RepInfo},
 };
 use idata::{self, cont::IVec};
 use std::{self, result};
@@ -530,8 +531,11 @@ fn consume_rep_or_neg(
     nodes: &[flat::Node],
     context: Context,
 ) -> result::Result<(Expression, &[flat::Node], Context), Error> {
-    // rep_or_neg      =   atom_or_par ("*" / "+" / "?")?
+    // rep_or_neg      =   atom_or_par ("*" / "+" / "?" / rep_range)?
     //                 /   "!" atom_or_par
+    //                 /   predicate
+    //                 /   "&" atom_or_par
+    //                 /   "^"
 
     fn process_repetition_indicator(
         expr: Expression,
@@ -539,7 +543,7 @@ fn consume_rep_or_neg(
     ) -> result::Result<Expression, Error> {
         match rsymbol {
             "+" => Ok(rep!(expr, 1)),
-            "*" => Ok(rep!(expr, 0)),
+            "*" => Ok(skip_until_or_rep(expr)),
             "?" => Ok(rep!(expr, 0, 1)),
             unknown => Err(error_peg_s(&format!(
                 "repetition symbol unknown {}",
@@ -548,6 +552,18 @@ fn consume_rep_or_neg(
         }
     }
 
+    //  (!'literal' .)*  is scanned with a substring search
+    fn skip_until_or_rep(expr: Expression) -> Expression {
+        if let Expression::And(expression::MultiExpr(v)) = &expr {
+            if let [Expression::Not(not), Expression::Simple(Atom::Dot)] = v.as_slice() {
+                if let Expression::Simple(Atom::Literal(l)) = &**not {
+                    return Expression::Simple(Atom::SkipUntil(l.clone()));
+                }
+            }
+        }
+        rep!(expr, 0)
+    }
+
     let atom_and_rep = |nodes, context| {
         let (expr, nodes, context) = consume_atom_or_par(nodes, context)?;
         let next_node = flat::peek_first_node(nodes)?;
@@ -557,6 +573,14 @@ fn consume_rep_or_neg(
                 let (sep, nodes) = flat::consume_val(nodes)?;
                 Ok((process_repetition_indicator(expr, sep)?, nodes, context))
             }
+            flat::Node::BeginRule(name) if name == "rep_range" => {
+                let ((min, max), nodes, context) = consume_rep_range(nodes, context)?;
+                Ok((
+                    Expression::Repeat(RepInfo::new(Box::new(expr), min, max)),
+                    nodes,
+                    context,
+                ))
+            }
             _ => Ok((expr, nodes, context)),
         }
     };
@@ -566,6 +590,12 @@ fn consume_rep_or_neg(
             let (expr, nodes, context) = consume_atom_or_par(nodes, context)?;
             Ok((not!(expr), nodes, context))
         };
+    let peek_and_atom =
+        |nodes, context| -> result::Result<(Expression, &[flat::Node], Context), Error> {
+            let nodes = flat::consume_this_value(r#"&"#, nodes)?;
+            let (expr, nodes, context) = consume_atom_or_par(nodes, context)?;
+            Ok((peek!(expr), nodes, context))
+        };
     //  --------------------------
 
     consuming_rule(
@@ -573,23 +603,58 @@ fn consume_rep_or_neg(
         nodes,
         context,
         |nodes, context| match flat::peek_first_node(nodes)? {
-            flat::Node::Val(v) => {
-                if v == "!" {
-                    neg_and_atom(nodes, context)
-                } else {
-                    Err(error_peg_s(&format!("expected '!', received {}", v)))
-                }
-            }
+            flat::Node::Val(v) => match v.as_ref() {
+                "!" => neg_and_atom(nodes, context),
+                "&" => peek_and_atom(nodes, context),
+                "^" => Ok((cut!(), flat::consume_this_value("^", nodes)?, context)),
+                _ => Err(error_peg_s(&format!(
+                    "expected '!', '&' or '^', received {}",
+                    v
+                ))),
+            },
+            flat::Node::BeginRule(name) if name == "predicate" => consume_predicate(nodes, context),
             _ => atom_and_rep(nodes, context),
         },
     )
 }
 
+type MinMax = (usize, Option<usize>);
+fn consume_rep_range(
+    nodes: &[flat::Node],
+    context: Context,
+) -> result::Result<(MinMax, &[flat::Node], Context), Error> {
+    // rep_range       =   "{"  ( [0-9]+ ("," [0-9]*)?  /  "," [0-9]+ )  "}"
+
+    consuming_rule("rep_range", nodes, context, |nodes, context| {
+        let (val, nodes) = flat::consume_val(nodes)?;
+        let parse_n = |s: &str| {
+            s.parse::<usize>()
+                .map_err(|_| error_peg_s(&format!("invalid repetition number {}", val)))
+        };
+
+        let range = val.trim_start_matches('{').trim_end_matches('}');
+        let (min, max) = match range.find(',') {
+            None => (parse_n(range)?, Some(parse_n(range)?)),
+            Some(0) => (0, Some(parse_n(&range[1..])?)),
+            Some(p) if p == range.len() - 1 => (parse_n(&range[..p])?, None),
+            Some(p) => (parse_n(&range[..p])?, Some(parse_n(&range[p + 1..])?)),
+        };
+
+        match max {
+            Some(max) if min > max => Err(error_peg_s(&format!(
+                "invalid repetition {}, min > max",
+                val
+            ))),
+            _ => Ok(((min, max), nodes, context)),
+        }
+    })
+}
+
 fn consume_atom_or_par(
     nodes: &[flat::Node],
     context: Context,
 ) -> result::Result<(Expression, &[flat::Node], Context), Error> {
-    // atom_or_par     =   (atom / parenth)
+    // atom_or_par     =   (recover / atom / parenth)
 
     consuming_rule("atom_or_par", nodes, context, |nodes, context| {
         let next_node = flat::peek_first_node(nodes)?;
@@ -597,6 +662,7 @@ fn consume_atom_or_par(
 
         let (expr, nodes, context) = push_err!(&format!("n:{}", node_name), {
             match &node_name as &str {
+                "recover" => consume_recover(nodes, context),
                 "atom" => consume_atom(nodes, context),
                 "parenth" => consume_parenth(nodes, context),
                 unknown => Err(error_peg_s(&format!("unknown {}", unknown))),
@@ -611,10 +677,11 @@ fn consume_atom(
     nodes: &[flat::Node],
     context: Context,
 ) -> result::Result<(Expression, &[flat::Node], Context), Error> {
-    // atom            =   literal
+    // atom            =   literal  nocase?
     //                 /   match
-    //                 /   dot
+    //                 /   matcher
     //                 /   rule_name
+    //                 /   dot
 
     consuming_rule("atom", nodes, context, |nodes, context| {
         let next_node = flat::peek_first_node(nodes)?;
@@ -622,10 +689,14 @@ fn consume_atom(
 
         let (expr, nodes, context) = push_err!(&format!("n:{}", node_name), {
             match &node_name as &str {
-                "literal" => consume_literal_expr(nodes, context),
+                "literal" => {
+                    let (expr, nodes, context) = consume_literal_expr(nodes, context)?;
+                    consume_opt_nocase(expr, nodes, context)
+                }
                 "rule_name" => consume_rule_ref(nodes, context),
                 "dot" => consume_dot(nodes, context),
                 "match" => consume_match(nodes, context),
+                "matcher" => consume_matcher(nodes, context),
                 unknown => Err(error_peg_s(&format!("unknown {}", unknown))),
             }
         })?;
@@ -638,28 +709,87 @@ fn consume_parenth(
     nodes: &[flat::Node],
     context: Context,
 ) -> result::Result<(Expression, &[flat::Node], Context), Error> {
-    //  parenth         =   "("  _  expr  _  ")"
+    //  parenth         =   "("  _  expr  _  ")"  nocase?
 
     consuming_rule("parenth", nodes, context, |nodes, context| {
         let nodes = flat::consume_this_value(r#"("#, nodes)?;
         let (expr, nodes, context) = consume_peg_expr(nodes, context)?;
         let nodes = flat::consume_this_value(r#")"#, nodes)?;
-        Ok((expr, nodes, context))
+        consume_opt_nocase(expr, nodes, context)
     })
 }
 
+fn consume_recover(
+    nodes: &[flat::Node],
+    context: Context,
+) -> result::Result<(Expression, &[flat::Node], Context), Error> {
+    //  recover         =   'recover' _  '('  _  expr  _  ','  _  expr  _  ')'
+
+    consuming_rule("recover", nodes, context, |nodes, context| {
+        let nodes = flat::consume_this_value("recover", nodes)?;
+        let nodes = flat::consume_this_value("(", nodes)?;
+        let (expr, nodes, context) = consume_peg_expr(nodes, context)?;
+        let nodes = flat::consume_this_value(",", nodes)?;
+        let (sync, nodes, context) = consume_peg_expr(nodes, context)?;
+        let nodes = flat::consume_this_value(")", nodes)?;
+        Ok((recover!(expr, sync), nodes, context))
+    })
+}
+
+fn consume_opt_nocase(
+    expr: Expression,
+    nodes: &[flat::Node],
+    context: Context,
+) -> result::Result<(Expression, &[flat::Node], Context), Error> {
+    // nocase          =   "i"
+
+    match flat::peek_first_node(nodes).and_then(flat::get_nodename) {
+        Ok("nocase") => consuming_rule("nocase", nodes, context, |nodes, context| {
+            let nodes = flat::consume_this_value("i", nodes)?;
+            Ok((expr.nocase(), nodes, context))
+        }),
+        _ => Ok((expr, nodes, context)),
+    }
+}
+
 fn consume_literal_string(
     nodes: &[flat::Node],
     context: Context,
 ) -> result::Result<(String, &[flat::Node], Context), Error> {
-    // literal         =  lit_noesc  /  lit_esc
+    // literal         =  lit_bytes  /  lit_noesc  /  lit_esc
 
     consuming_rule("literal", nodes, context, |nodes, context| {
         let next_node_name = flat::get_nodename(flat::peek_first_node(nodes)?)?;
         match next_node_name {
+            "lit_bytes" => consume_literal_bytes(nodes, context),
+            "lit_noesc" => consume_literal_no_esc(nodes, context),
+            "lit_esc" => consume_literal_esc(nodes, context),
+            _ => Err(error_peg_s(&format!("unexpected node {}", next_node_name))),
+        }
+    })
+}
+
+fn consume_literal_bytes(
+    nodes: &[flat::Node],
+    context: Context,
+) -> result::Result<(String, &[flat::Node], Context), Error> {
+    // lit_bytes       =   (lit_noesc  /  lit_esc)  'b'
+
+    consuming_rule("lit_bytes", nodes, context, |nodes, context| {
+        let next_node_name = flat::get_nodename(flat::peek_first_node(nodes)?)?;
+        let (val, nodes, context) = match next_node_name {
             "lit_noesc" => consume_literal_no_esc(nodes, context),
             "lit_esc" => consume_literal_esc(nodes, context),
             _ => Err(error_peg_s(&format!("unexpected node {}", next_node_name))),
+        }?;
+        let nodes = flat::consume_this_value("b", nodes)?;
+        //  a byte is a char from \x00 to \xff
+        match val.chars().find(|&ch| ch > '\u{ff}') {
+            Some(ch) => Err(error_peg_s(&format!(
+                "char out of byte range on \"{}\"b: {}",
+                val, ch
+            ))),
+            None => Ok((val, nodes, context)),
         }
     })
 }
@@ -763,18 +893,22 @@ fn consume_hex_char(
     nodes: &[flat::Node],
     context: Context,
 ) -> result::Result<(String, &[flat::Node], Context), Error> {
-    // hex_char        =   '\0x' [0-9A-F] [0-9A-F]
-
-    use std::u8;
+    // hex_char        =   '\0x' [0-9A-Fa-f] [0-9A-Fa-f]
+    //                 /   '\x' [0-9A-Fa-f] [0-9A-Fa-f]
+    //                 /   '\u{' [0-9A-Fa-f]+ '}'
 
     consuming_rule("hex_char", nodes, context, |nodes, context| {
         let (val, nodes) = flat::consume_val(nodes)?;
-        let val = &val[3..];
-
-        let ch = match u8::from_str_radix(val, 16) {
-            Ok(v) => Ok(v as char),
-            _ => Err(error_peg_s(&format!("error parsing hex {}", &val[2..]))),
-        }?;
+        let hex = val
+            .trim_start_matches(r#"\0x"#)
+            .trim_start_matches(r#"\x"#)
+            .trim_start_matches(r#"\u{"#)
+            .trim_end_matches('}');
+
+        let ch = u32::from_str_radix(hex, 16)
+            .ok()
+            .and_then(std::char::from_u32)
+            .ok_or_else(|| error_peg_s(&format!("error parsing hex {}", val)))?;
         Ok((ch.to_string(), nodes, context))
     })
 }
@@ -835,6 +969,32 @@ fn consume_dot(
     })
 }
 
+fn consume_matcher(
+    nodes: &[flat::Node],
+    context: Context,
+) -> result::Result<(Expression, &[flat::Node], Context), Error> {
+    //  matcher         =   "@"  symbol
+
+    consuming_rule("matcher", nodes, context, |nodes, context| {
+        let nodes = flat::consume_this_value("@", nodes)?;
+        let (name, nodes, context) = consume_symbol(nodes, context)?;
+        Ok((matcher!(name), nodes, context))
+    })
+}
+
+fn consume_predicate(
+    nodes: &[flat::Node],
+    context: Context,
+) -> result::Result<(Expression, &[flat::Node], Context), Error> {
+    //  predicate       =   "&?"  symbol
+
+    consuming_rule("predicate", nodes, context, |nodes, context| {
+        let nodes = flat::consume_this_value("&?", nodes)?;
+        let (name, nodes, context) = consume_symbol(nodes, context)?;
+        Ok((predicate!(name), nodes, context))
+    })
+}
+
 fn consume_rule_ref(
     nodes: &[flat::Node],
     context: Context,
@@ -850,84 +1010,172 @@ fn consume_match(
     nodes: &[flat::Node],
     context: Context,
 ) -> result::Result<(Expression, &[flat::Node], Context), Error> {
-    // match           =   "["
+    // match           =   "["  "^"?
     //                         (
     //                             (mchars  mbetween*)
     //                             / mbetween+
     //                         )
     //                     "]"
 
-    type VecChCh = Vec<(char, char)>;
     consuming_rule("match", nodes, context, |nodes, context| {
         fn rec_consume_mbetween(
-            acc: Vec<(char, char)>,
+            mut acc: MatchRules,
             nodes: &[flat::Node],
             context: Context,
-        ) -> result::Result<(VecChCh, &[flat::Node], Context), Error> {
+        ) -> result::Result<(MatchRules, &[flat::Node], Context), Error> {
             let next_node = flat::peek_first_node(nodes)?;
             let node_name = flat::get_nodename(next_node);
             match node_name {
                 Ok("mbetween") => {
                     let ((from, to), nodes, context) = consume_mbetween(nodes, context)?;
-                    rec_consume_mbetween(acc.ipush((from, to)), nodes, context)
+                    acc.1.push((from, to));
+                    rec_consume_mbetween(acc, nodes, context)
                 }
                 _ => Ok((acc, nodes, context)),
             }
         }
         //  --------------------------
 
-        let nodes = flat::consume_this_value("[", nodes)?;
-
-        let (omchars, nodes, context) = match flat::get_nodename(flat::peek_first_node(nodes)?)? {
-            "mchars" => {
-                let (mchars, nodes, context) = consume_mchars(nodes, context)?;
-                (Some(mchars), nodes, context)
+        //  "[" and "^" are compacted in the same node
+        let (negated, nodes) = match flat::consume_val(nodes)? {
+            ("[", nodes) => (false, nodes),
+            ("[^", nodes) => (true, nodes),
+            (v, _) => {
+                return Err(error_peg_s(&format!(
+                    "expected '[' or '[^', received {}",
+                    v
+                )))
             }
-            _ => (None, nodes, context),
         };
 
-        let (vchars, nodes, context) = rec_consume_mbetween(vec![], nodes, context)?;
+        let (mrules, nodes, context) = match flat::get_nodename(flat::peek_first_node(nodes)?)? {
+            "mchars" => consume_mchars(nodes, context)?,
+            _ => (MatchRules::init("", vec![]), nodes, context),
+        };
 
-        let (expr, nodes) = match (omchars, vchars.is_empty()) {
-            (Some(chars), true) => Ok((ematch!(chlist chars, from2 vec![]), nodes)),
-            (Some(chars), false) => Ok((ematch!(chlist chars, from2 vchars), nodes)),
-            (None, false) => Ok((ematch!(chlist "", from2 vchars), nodes)),
-            _ => Err(error_peg_s("Invalid match combination")),
-        }?;
+        let (mrules, nodes, context) = rec_consume_mbetween(mrules, nodes, context)?;
+
+        if mrules.0.is_empty() && mrules.1.is_empty() && mrules.3.is_empty() {
+            return Err(error_peg_s("Invalid match combination"));
+        }
 
         let nodes = flat::consume_this_value("]", nodes)?;
 
-        Ok((expr, nodes, context))
+        let mrules = if negated { mrules.negate() } else { mrules };
+        Ok((Expression::Simple(Atom::Match(mrules)), nodes, context))
     })
 }
 
+type CharChar = (char, char);
+
 fn consume_mchars(
     nodes: &[flat::Node],
     context: Context,
-) -> result::Result<(&str, &[flat::Node], Context), Error> {
-    // mchars          =   (!"]" !(. "-") .)+
+) -> result::Result<(MatchRules, &[flat::Node], Context), Error> {
+    // mchars          =   (!(mchar "-")  (mclass / mchar))+
+
+    fn rec_consume_mchars(
+        mut acc: MatchRules,
+        nodes: &[flat::Node],
+        context: Context,
+    ) -> result::Result<(MatchRules, &[flat::Node], Context), Error> {
+        let next_node = flat::peek_first_node(nodes)?;
+        match flat::get_nodename(next_node) {
+            Ok("mclass") => {
+                let (mrules, nodes, context) = consume_mclass(nodes, context)?;
+                acc.0.push_str(&mrules.0);
+                acc.1.extend(mrules.1);
+                acc.3.extend(mrules.3);
+                rec_consume_mchars(acc, nodes, context)
+            }
+            Ok("mchar") => {
+                let (ch, nodes, context) = consume_mchar(nodes, context)?;
+                acc.0.push(ch);
+                rec_consume_mchars(acc, nodes, context)
+            }
+            _ => Ok((acc, nodes, context)),
+        }
+    }
 
     consuming_rule("mchars", nodes, context, |nodes, context| {
-        let (val, nodes) = flat::consume_val(nodes)?;
-        Ok((val, nodes, context))
+        rec_consume_mchars(MatchRules::init("", vec![]), nodes, context)
     })
 }
 
-type CharChar = (char, char);
 fn consume_mbetween(
     nodes: &[flat::Node],
     context: Context,
 ) -> result::Result<(CharChar, &[flat::Node], Context), Error> {
-    // mbetween        =   (.  "-"  .)
+    // mbetween        =   mchar  "-"  mchar
 
     consuming_rule("mbetween", nodes, context, |nodes, context| {
-        let (from_to, nodes) = flat::consume_val(nodes)?;
-
-        let (from, chars) = idata::consume_char(from_to.chars())
-            .ok_or_else(|| error_peg_s("expected from char"))?;
-        let (_, chars) =
-            idata::consume_char(chars).ok_or_else(|| error_peg_s("expected '-' char"))?;
-        let (to, _) = idata::consume_char(chars).ok_or_else(|| error_peg_s("expected to char"))?;
+        let (from, nodes, context) = consume_mchar(nodes, context)?;
+        let nodes = flat::consume_this_value("-", nodes)?;
+        let (to, nodes, context) = consume_mchar(nodes, context)?;
         Ok(((from, to), nodes, context))
     })
 }
+
+fn consume_mchar(
+    nodes: &[flat::Node],
+    context: Context,
+) -> result::Result<(char, &[flat::Node], Context), Error> {
+    // mchar           =   mesc  /  !"]" .
+
+    consuming_rule("mchar", nodes, context, |nodes, context| {
+        let (val, nodes, context) = match flat::get_nodename(flat::peek_first_node(nodes)?) {
+            Ok("mesc") => consume_mesc(nodes, context)?,
+            _ => {
+                let (val, nodes) = flat::consume_val(nodes)?;
+                (val.to_string(), nodes, context)
+            }
+        };
+
+        let mut chars = val.chars();
+        match (chars.next(), chars.next()) {
+            (Some(ch), None) => Ok((ch, nodes, context)),
+            _ => Err(error_peg_s(&format!("expected one char, received {}", val))),
+        }
+    })
+}
+
+fn consume_mesc(
+    nodes: &[flat::Node],
+    context: Context,
+) -> result::Result<(String, &[flat::Node], Context), Error> {
+    // mesc            =   esc_char  /  hex_char  /  "\]"  /  "\["  /  "\^"  /  "\-"
+
+    consuming_rule(
+        "mesc",
+        nodes,
+        context,
+        |nodes, context| match flat::get_nodename(flat::peek_first_node(nodes)?) {
+            Ok("esc_char") => consume_esc_char(nodes, context),
+            Ok("hex_char") => consume_hex_char(nodes, context),
+            _ => {
+                let (val, nodes) = flat::consume_val(nodes)?;
+                Ok((val.trim_start_matches('\\').to_string(), nodes, context))
+            }
+        },
+    )
+}
+
+fn consume_mclass(
+    nodes: &[flat::Node],
+    context: Context,
+) -> result::Result<(MatchRules, &[flat::Node], Context), Error> {
+    // mclass          =   "\d"  /  "\w"  /  "\s"
+    //                 /   "\p{"  [_A-Za-z]+  "}"
+
+    consuming_rule("mclass", nodes, context, |nodes, context| {
+        let (val, nodes) = flat::consume_val(nodes)?;
+        let mrules = if val.starts_with(r#"\p{"#) {
+            Property::by_name(&val[3..val.len() - 1])
+                .map(|p| MatchRules::init("", vec![]).add_property(p))
+        } else {
+            val.chars().last().and_then(MatchRules::shorthand)
+        }
+        .ok_or_else(|| error_peg_s(&format!("unknown class {}", val)))?;
+        Ok((mrules, nodes, context))
+    })
+}
@@ -48,21 +48,36 @@ fn text_peg2code() -> &'static str {
                     /   rep_or_neg  ( _1 _ !(rule_name _ ('=' / '{')) and )*
     _1              =   (' ' / eol)     //  this is the and separator
 
-    rep_or_neg      =   atom_or_par ('*' / '+' / '?')?
+    rep_or_neg      =   atom_or_par ('*' / '+' / '?' / rep_range)?
                     /   '!' atom_or_par
+                    /   predicate
+                    /   '&' atom_or_par
+                    /   '^'                     //  cut
 
-    atom_or_par     =   (atom / parenth)
+    predicate       =   '&?' symbol             //  written in rust
+
+    rep_range       =   '{'  ( [0-9]+ (',' [0-9]*)?  /  ',' [0-9]+ )  '}'
+
+    atom_or_par     =   (recover / atom / parenth)
+
+    recover         =   'recover' _  '('  _  expr  _  ','  _  expr  _  ')'
 
     parenth         =   '('  _  expr  _  (  ')'
                                          /  error("unbalanced parethesis: missing ')'") 
-                                         )
+                                         )  nocase?
 
-    atom            =   literal
+    atom            =   literal  nocase?
                     /   match
+                    /   matcher
                     /   rule_name
                     /   dot             //  as rule_name can start with a '.', dot has to be after rule_name
 
-    literal         =  lit_noesc  /  lit_esc
+    matcher         =   '@' symbol              //  written in rust
+
+    literal         =  lit_bytes  /  lit_noesc  /  lit_esc
+    nocase          =   'i'
+
+    lit_bytes       =   (lit_noesc  /  lit_esc)  'b'    //  a prefix would collide with symbols (b')
 
     lit_noesc       =   _'   (  !_' .  )*   _'
     _'              =   "'"
@@ -81,20 +96,26 @@ fn text_peg2code() -> &'static str {
                     /   '\\'
                     /   '\"'
 
-    hex_char        =   '\0x' [0-9A-F] [0-9A-F]
+    hex_char        =   '\0x' [0-9A-Fa-f] [0-9A-Fa-f]
+                    /   '\x' [0-9A-Fa-f] [0-9A-Fa-f]
+                    /   '\u{' [0-9A-Fa-f]+ '}'
 
     eol             =   ("\r\n"  /  "\n"  /  "\r")
     _eol            =   (' ' / comment)*  eol
 
-    match           =   '['
+    match           =   '['  '^'?
                             (
                                 (mchars  mbetween*)
                                 / mbetween+
                             )
                         ']'
 
-    mchars          =   (!']' !(. '-') .)+
-    mbetween        =   (.  '-'  .)
+    mchars          =   (!(mchar '-')  (mclass / mchar))+
+    mbetween        =   mchar  '-'  mchar
+    mchar           =   mesc  /  !']' .
+    mesc            =   esc_char  /  hex_char  /  '\]'  /  '\['  /  '\^'  /  '\-'
+    mclass          =   '\d'  /  '\w'  /  '\s'
+                    /   '\p{'  [_A-Za-z]+  '}'
 
     dot             =   '.'
 
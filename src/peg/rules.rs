@@ -4,37 +4,46 @@ pub(crate) fn parse_peg() -> parser::expression::SetOfRules {
   rules!(
          r#"dot"# => lit!(".")
        , r#"module"# => and!(ref_rule!(r#"_"#), ref_rule!(r#"mod_name"#), ref_rule!(r#"_"#), lit!("{"), ref_rule!(r#"_"#), ref_rule!(r#"grammar"#), ref_rule!(r#"_"#), lit!("}"), ref_rule!(r#"_eol"#), ref_rule!(r#"_"#))
-       , r#"mline_comment"# => and!(lit!("/*"), rep!(and!(not!(lit!("*/")), dot!()), 0), lit!("*/"))
+       , r#"mline_comment"# => and!(lit!("/*"), skip_until!("*/"), lit!("*/"))
        , r#"_1"# => or!(lit!(" "), ref_rule!(r#"eol"#))
        , r#"comment"# => or!(ref_rule!(r#"line_comment"#), ref_rule!(r#"mline_comment"#))
        , r#"or"# => and!(ref_rule!(r#"and"#), rep!(and!(ref_rule!(r#"_"#), lit!("/"), ref_rule!(r#"_"#), ref_rule!(r#"or"#)), 0, 1))
-       , r#"literal"# => or!(ref_rule!(r#"lit_noesc"#), ref_rule!(r#"lit_esc"#))
+       , r#"literal"# => or!(ref_rule!(r#"lit_bytes"#), ref_rule!(r#"lit_noesc"#), ref_rule!(r#"lit_esc"#))
        , r#"mod_name"# => ref_rule!(r#"symbol"#)
        , r#"esc_char"# => or!(lit!("\\r"), lit!("\\n"), lit!("\\t"), lit!("\\\\"), lit!("\\\""))
-       , r#"atom"# => or!(ref_rule!(r#"literal"#), ref_rule!(r#"match"#), ref_rule!(r#"rule_name"#), ref_rule!(r#"dot"#))
-       , r#"hex_char"# => and!(lit!("\\0x"), ematch!(chlist r#""#  , from '0', to '9' , from 'A', to 'F' ), ematch!(chlist r#""#  , from '0', to '9' , from 'A', to 'F' ))
-       , r#"parenth"# => and!(lit!("("), ref_rule!(r#"_"#), ref_rule!(r#"expr"#), ref_rule!(r#"_"#), or!(lit!(")"), error!("unbalanced parethesis: missing ')'")))
+       , r#"atom"# => or!(and!(ref_rule!(r#"literal"#), rep!(ref_rule!(r#"nocase"#), 0, 1)), ref_rule!(r#"match"#), ref_rule!(r#"matcher"#), ref_rule!(r#"rule_name"#), ref_rule!(r#"dot"#))
+       , r#"hex_char"# => or!(and!(lit!("\\0x"), ematch!(chlist ""  , from '0', to '9' , from 'A', to 'F' , from 'a', to 'f' ), ematch!(chlist ""  , from '0', to '9' , from 'A', to 'F' , from 'a', to 'f' )), and!(lit!("\\x"), ematch!(chlist ""  , from '0', to '9' , from 'A', to 'F' , from 'a', to 'f' ), ematch!(chlist ""  , from '0', to '9' , from 'A', to 'F' , from 'a', to 'f' )), and!(lit!("\\u{"), rep!(ematch!(chlist ""  , from '0', to '9' , from 'A', to 'F' , from 'a', to 'f' ), 1), lit!("}")))
+       , r#"parenth"# => and!(lit!("("), ref_rule!(r#"_"#), ref_rule!(r#"expr"#), ref_rule!(r#"_"#), or!(lit!(")"), error!("unbalanced parethesis: missing ')'")), rep!(ref_rule!(r#"nocase"#), 0, 1))
        , r#"and"# => or!(ref_rule!(r#"error"#), and!(ref_rule!(r#"rep_or_neg"#), rep!(and!(ref_rule!(r#"_1"#), ref_rule!(r#"_"#), not!(and!(ref_rule!(r#"rule_name"#), ref_rule!(r#"_"#), or!(lit!("="), lit!("{")))), ref_rule!(r#"and"#)), 0)))
        , r#"rule"# => and!(ref_rule!(r#"_"#), ref_rule!(r#"rule_name"#), ref_rule!(r#"_"#), lit!("="), ref_rule!(r#"_"#), ref_rule!(r#"expr"#), ref_rule!(r#"_eol"#), ref_rule!(r#"_"#))
        , r#"grammar"# => rep!(or!(ref_rule!(r#"rule"#), ref_rule!(r#"module"#)), 1)
-       , r#"match"# => and!(lit!("["), or!(and!(ref_rule!(r#"mchars"#), rep!(ref_rule!(r#"mbetween"#), 0)), rep!(ref_rule!(r#"mbetween"#), 1)), lit!("]"))
-       , r#"rep_or_neg"# => or!(and!(ref_rule!(r#"atom_or_par"#), rep!(or!(lit!("*"), lit!("+"), lit!("?")), 0, 1)), and!(lit!("!"), ref_rule!(r#"atom_or_par"#)))
+       , r#"match"# => and!(lit!("["), rep!(lit!("^"), 0, 1), or!(and!(ref_rule!(r#"mchars"#), rep!(ref_rule!(r#"mbetween"#), 0)), rep!(ref_rule!(r#"mbetween"#), 1)), lit!("]"))
+       , r#"rep_or_neg"# => or!(and!(ref_rule!(r#"atom_or_par"#), rep!(or!(lit!("*"), lit!("+"), lit!("?"), ref_rule!(r#"rep_range"#)), 0, 1)), and!(lit!("!"), ref_rule!(r#"atom_or_par"#)), ref_rule!(r#"predicate"#), and!(lit!("&"), ref_rule!(r#"atom_or_par"#)), lit!("^"))
        , r#"_eol"# => and!(rep!(or!(lit!(" "), ref_rule!(r#"comment"#)), 0), ref_rule!(r#"eol"#))
        , r#"line_comment"# => and!(lit!("//"), rep!(and!(not!(ref_rule!(r#"eol"#)), dot!()), 0), ref_rule!(r#"eol"#))
        , r#"lit_noesc"# => and!(ref_rule!(r#"_'"#), rep!(and!(not!(ref_rule!(r#"_'"#)), dot!()), 0), ref_rule!(r#"_'"#))
        , r#"rule_name"# => and!(rep!(lit!("."), 0, 1), ref_rule!(r#"symbol"#), rep!(and!(lit!("."), ref_rule!(r#"symbol"#)), 0))
        , r#"_""# => lit!("\"")
-       , r#"mchars"# => rep!(and!(not!(lit!("]")), not!(and!(dot!(), lit!("-"))), dot!()), 1)
+       , r#"mchars"# => rep!(and!(not!(and!(ref_rule!(r#"mchar"#), lit!("-"))), or!(ref_rule!(r#"mclass"#), ref_rule!(r#"mchar"#))), 1)
        , r#"expr"# => ref_rule!(r#"or"#)
-       , r#"symbol"# => and!(ematch!(chlist r#"_"#  , from 'a', to 'z' , from 'A', to 'Z' , from '0', to '9' ), rep!(ematch!(chlist r#"_'""#  , from 'a', to 'z' , from 'A', to 'Z' , from '0', to '9' ), 0))
+       , r#"symbol"# => and!(ematch!(chlist "_"  , from 'a', to 'z' , from 'A', to 'Z' , from '0', to '9' ), rep!(ematch!(chlist "_'\""  , from 'a', to 'z' , from 'A', to 'Z' , from '0', to '9' ), 0))
        , r#"error"# => and!(lit!("error"), ref_rule!(r#"_"#), lit!("("), ref_rule!(r#"_"#), ref_rule!(r#"literal"#), ref_rule!(r#"_"#), lit!(")"))
        , r#"_'"# => lit!("'")
        , r#"eol"# => or!(lit!("\r\n"), lit!("\n"), lit!("\r"))
        , r#"lit_esc"# => and!(ref_rule!(r#"_""#), rep!(or!(ref_rule!(r#"esc_char"#), ref_rule!(r#"hex_char"#), and!(not!(ref_rule!(r#"_""#)), dot!())), 0), ref_rule!(r#"_""#))
-       , r#"mbetween"# => and!(dot!(), lit!("-"), dot!())
+       , r#"mbetween"# => and!(ref_rule!(r#"mchar"#), lit!("-"), ref_rule!(r#"mchar"#))
        , r#"_"# => rep!(or!(lit!(" "), ref_rule!(r#"eol"#), ref_rule!(r#"comment"#)), 0)
        , r#"main"# => ref_rule!(r#"grammar"#)
-       , r#"atom_or_par"# => or!(ref_rule!(r#"atom"#), ref_rule!(r#"parenth"#))
+       , r#"atom_or_par"# => or!(ref_rule!(r#"recover"#), ref_rule!(r#"atom"#), ref_rule!(r#"parenth"#))
+       , r#"nocase"# => lit!("i")
+       , r#"matcher"# => and!(lit!("@"), ref_rule!(r#"symbol"#))
+       , r#"predicate"# => and!(lit!("&?"), ref_rule!(r#"symbol"#))
+       , r#"mchar"# => or!(ref_rule!(r#"mesc"#), and!(not!(lit!("]")), dot!()))
+       , r#"mclass"# => or!(lit!("\\d"), lit!("\\w"), lit!("\\s"), and!(lit!("\\p{"), rep!(ematch!(chlist "_"  , from 'A', to 'Z' , from 'a', to 'z' ), 1), lit!("}")))
+       , r#"mesc"# => or!(ref_rule!(r#"esc_char"#), ref_rule!(r#"hex_char"#), lit!("\\]"), lit!("\\["), lit!("\\^"), lit!("\\-"))
+       , r#"rep_range"# => and!(lit!("{"), or!(and!(rep!(ematch!(chlist ""  , from '0', to '9' ), 1), rep!(and!(lit!(","), rep!(ematch!(chlist ""  , from '0', to '9' ), 0)), 0, 1)), and!(lit!(","), rep!(ematch!(chlist ""  , from '0', to '9' ), 1))), lit!("}"))
+       , r#"recover"# => and!(lit!("recover"), ref_rule!(r#"_"#), lit!("("), ref_rule!(r#"_"#), ref_rule!(r#"expr"#), ref_rule!(r#"_"#), lit!(","), ref_rule!(r#"_"#), ref_rule!(r#"expr"#), ref_rule!(r#"_"#), lit!(")"))
+       , r#"lit_bytes"# => and!(or!(ref_rule!(r#"lit_noesc"#), ref_rule!(r#"lit_esc"#)), lit!("b"))
 
   )
 }
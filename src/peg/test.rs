@@ -347,3 +347,510 @@ fn test_match() {
     assert!(parse("Z", &rules).is_err());
     assert!(parse("ABZ", &rules).is_err());
 }
+
+#[test]
+fn parse_left_recursion() {
+    let peg = r#"
+
+    main    =   expr
+
+    expr    =   expr  '+'  num
+            /   expr  '-'  num
+            /   num
+
+    num     =   [0-9]+
+
+    "#;
+
+    let rules = peg::rules_from_peg(peg).unwrap();
+
+    assert!(parse("1", &rules).is_ok());
+    assert!(parse("1+2-3+44", &rules).is_ok());
+    assert!(parse("1+", &rules).is_err());
+    assert!(parse("+1", &rules).is_err());
+}
+
+#[test]
+fn parse_rule_and_prefix() {
+    use crate::{parse_prefix, parse_rule};
+
+    let peg = r#"
+
+    main    =   stmt+
+    stmt    =   word  ';'
+    word    =   [a-z]+
+
+    "#;
+
+    let rules = peg::rules_from_peg(peg).unwrap();
+
+    assert!(parse_rule("abc;", &rules, "stmt").is_ok());
+    assert!(parse_rule("abc;abc;", &rules, "stmt").is_err());
+    assert!(parse_rule("abc", &rules, "word").is_ok());
+
+    let (_, consumed) = parse_prefix("abc; rest", &rules, "stmt").unwrap();
+    assert_eq!(consumed, "abc;".len());
+    assert!(parse_prefix("; rest", &rules, "stmt").is_err());
+}
+
+#[test]
+fn parse_non_ascii_errors() {
+    use crate::parse_prefix;
+
+    let peg = r#"
+
+    main    =   (word / eol)+ '.'
+    word    =   'año'  /  '€'
+    eol     =   "\r\n"  /  "\n"  /  "\r"
+
+    "#;
+
+    let rules = peg::rules_from_peg(peg).unwrap();
+
+    assert!(parse("año€año.", &rules).is_ok());
+
+    let err = parse("año€x.", &rules).unwrap_err();
+    assert_eq!(err.pos.n, 4);
+    assert_eq!(err.pos.byte, "año€".len());
+    assert_eq!(err.line_before, "año€");
+    assert_eq!(err.line_after, "x.");
+
+    let err = parse("año\r\naño\n€\r€x", &rules).unwrap_err();
+    assert_eq!(err.pos.row, 3);
+    assert_eq!(err.line_before, "€");
+
+    let (_, consumed) = parse_prefix("año€. rest", &rules, "main").unwrap();
+    assert_eq!(consumed, "año€.".len());
+}
+
+#[test]
+fn parse_positive_lookahead() {
+    let peg = r#"
+
+    main    =   &"he"  word  (&(" " "w") " " word)?
+    word    =   [a-z]+
+
+    "#;
+
+    let rules = peg::rules_from_peg(peg).unwrap();
+
+    assert!(parse("hello", &rules).is_ok());
+    assert!(parse("hello world", &rules).is_ok());
+    assert!(parse("hello mars", &rules).is_err());
+    assert!(parse("bye", &rules).is_err());
+}
+
+#[test]
+fn gcode_positive_lookahead() {
+    let rules = peg::rules_from_peg("main = &'a' .\n").unwrap();
+
+    assert!(peg::gcode::rust_from_rules(&rules).contains(r#"peek!(lit!("a"))"#));
+}
+
+#[test]
+fn parse_case_insensitive_literals() {
+    let peg = r#"
+
+    main    =   'select'i  ' '  ("from" ' ' 'where')i  ' '  'X'
+
+    "#;
+
+    let rules = peg::rules_from_peg(peg).unwrap();
+
+    assert!(parse("select from where X", &rules).is_ok());
+    assert!(parse("SELECT From WHERE X", &rules).is_ok());
+    assert!(parse("select from where x", &rules).is_err());
+
+    let rules = peg::rules_from_peg("main = 'Select'i\n").unwrap();
+    assert_eq!(
+        parse("SELEC", &rules).err().unwrap().descr,
+        "expected 'Select'i"
+    );
+    assert!(peg::gcode::rust_from_rules(&rules).contains(r#"ilit!("Select")"#));
+}
+
+#[test]
+fn parse_negated_match() {
+    let peg = r#"
+
+    main    =   '"'  [^"\\]*  '"'
+
+    "#;
+
+    let rules = peg::rules_from_peg(peg).unwrap();
+
+    assert!(parse(r#""hello""#, &rules).is_ok());
+    assert!(parse(r#""""#, &rules).is_ok());
+    assert!(parse(r#""hel"lo""#, &rules).is_err());
+    assert!(parse(r#""hel\lo""#, &rules).is_err());
+    assert!(parse(r#""hello"#, &rules).is_err());
+}
+
+#[test]
+fn parse_match_escapes() {
+    let peg = r#"
+
+    main    =   [\]\-\n\t\x41\u{1F600}]+  [\[-\^]
+
+    "#;
+
+    let rules = peg::rules_from_peg(peg).unwrap();
+
+    assert!(parse("]-\n\tA😀\\", &rules).is_ok());
+    assert!(parse("]]\u{5c}", &rules).is_ok());
+    assert!(parse("B\\", &rules).is_err());
+    assert!(parse("]_", &rules).is_err());
+}
+
+#[test]
+fn parse_match_shorthand_classes() {
+    let peg = r#"
+
+    main    =   [\w]+  [\s]+  [\d.]+  [^\s]
+
+    "#;
+
+    let rules = peg::rules_from_peg(peg).unwrap();
+
+    assert!(parse("var_1 \t 3.14!", &rules).is_ok());
+    assert!(parse("var-1 3.14!", &rules).is_err());
+    assert!(parse("var_1 3,14!", &rules).is_err());
+    assert!(parse("var_1 3.14 ", &rules).is_err());
+}
+
+#[test]
+fn parse_lit_hex_and_unicode_escapes() {
+    let peg = r#"
+
+    main    =   "\0x41\0x6a\x42\u{1F600}\u{e9}"
+
+    "#;
+
+    let rules = peg::rules_from_peg(peg).unwrap();
+
+    assert!(parse("AjB😀é", &rules).is_ok());
+    assert!(peg::rules_from_peg("main = \"\\u{110000}\"\n").is_err());
+}
+
+#[test]
+fn gcode_match_escapes() {
+    let rules = peg::rules_from_peg("main = [^\\]\"]  [\\da-z]\n").unwrap();
+    let code = peg::gcode::rust_from_rules(&rules);
+
+    assert!(code.contains(r#"ematch!(not chlist "]\"", from2 vec![])"#));
+    assert!(code.contains(r#"ematch!(chlist ""  , from '0', to '9' , from 'a', to 'z' )"#));
+}
+
+#[test]
+fn parse_match_unicode_properties() {
+    let peg = r#"
+
+    main    =   id  ([\p{White_Space}]+  id)*
+    id      =   [\p{XID_Start}_]  [\p{XID_Continue}]*  [\p{Nd}]?
+
+    "#;
+
+    let rules = peg::rules_from_peg(peg).unwrap();
+
+    assert!(parse("año _ñu1\u{a0}Ωmega٣", &rules).is_ok());
+    assert!(parse("1año", &rules).is_err());
+    assert!(parse("año-1", &rules).is_err());
+
+    let rules = peg::rules_from_peg("main = [^\\p{L}]+\n").unwrap();
+    assert!(parse("1+ 2", &rules).is_ok());
+    assert!(parse("1+é", &rules).is_err());
+    assert!(peg::gcode::rust_from_rules(&rules)
+        .contains(r#"ematch!(not chlist "", from2 vec![], props "L")"#));
+
+    assert!(peg::rules_from_peg("main = [\\p{Unknown}]\n").is_err());
+}
+
+#[test]
+fn parse_bounded_repetition() {
+    let peg = r#"
+
+    main    =   'a'{3}  'b'{2,}  'c'{,2}  'd'{1,2}  ('e' 'f'){2}
+
+    "#;
+
+    let rules = peg::rules_from_peg(peg).unwrap();
+
+    assert!(parse("aaabbdefef", &rules).is_ok());
+    assert!(parse("aaabbbbbccddefef", &rules).is_ok());
+    assert!(parse("aabbdefef", &rules).is_err());
+    assert!(parse("aaabdefef", &rules).is_err());
+    assert!(parse("aaabbcccdefef", &rules).is_err());
+    assert!(parse("aaabbddddefef", &rules).is_err());
+    assert!(parse("aaabbdef", &rules).is_err());
+
+    let code = peg::gcode::rust_from_rules(&rules);
+    assert!(code.contains(r#"rep!(lit!("a"), 3, 3)"#));
+    assert!(code.contains(r#"rep!(lit!("b"), 2)"#));
+    assert!(code.contains(r#"rep!(lit!("c"), 0, 2)"#));
+    assert!(code.contains(r#"rep!(lit!("d"), 1, 2)"#));
+}
+
+#[test]
+fn parse_zero_repetition() {
+    use crate::{parse_with_options, parser::Engine, parser::Options};
+
+    for rep in &["{0}", "{,0}", "{0,0}"] {
+        let rules = peg::rules_from_peg(&format!("main = 'a'{} 'b'\n", rep)).unwrap();
+        for engine in &[Engine::Tree, Engine::Vm] {
+            let options = Options::new().engine(*engine);
+            assert!(parse_with_options("b", &rules, &options).is_ok());
+            assert!(parse_with_options("ab", &rules, &options).is_err());
+            assert!(parse_with_options("aab", &rules, &options).is_err());
+        }
+    }
+}
+
+#[test]
+fn invalid_bounded_repetition() {
+    assert!(peg::rules_from_peg("main = 'a'{3,2}\n").is_err());
+    assert!(peg::rules_from_peg("main = 'a'{,}\n").is_err());
+    assert!(peg::rules_from_peg("main = 'a'{}\n").is_err());
+    assert!(peg::rules_from_peg("main = 'a'{2,2}\n").is_ok());
+}
+
+#[test]
+fn parse_cut() {
+    let peg = r#"
+
+    main    =   stmt+
+    stmt    =   'if' ^ ' ' cond ' then;'
+            /   'i' [a-z]* ';'
+    cond    =   'true' / 'false'
+
+    "#;
+
+    let rules = peg::rules_from_peg(peg).unwrap();
+
+    assert!(parse("if true then;", &rules).is_ok());
+    assert!(parse("ifx;", &rules).is_err());
+    assert!(parse("ix;if false then;", &rules).is_ok());
+
+    let err = parse("ix;if maybe then;", &rules).err().unwrap();
+    assert_eq!(err.descr, "expected cond");
+    assert_eq!(err.priority, crate::parser::ErrPriority::Critical);
+    assert_eq!(err.pos.n, 6);
+
+    let err = parse("if true else;", &rules).err().unwrap();
+    assert_eq!(err.descr, "expected ' then;'");
+    assert_eq!(err.pos.n, 7);
+
+    assert!(peg::gcode::rust_from_rules(&rules).contains("cut!()"));
+}
+
+#[test]
+fn parse_farthest_expected() {
+    use crate::{parse_with_options, parser::Options};
+
+    let peg = r#"
+
+    main    =   (call _eol)+
+    call    =   id '(' (id (',' id)*)? ')'
+    id      =   [a-z]+ !'x'
+    _eol    =   "\n"
+
+    "#;
+
+    let rules = peg::rules_from_peg(peg).unwrap();
+    let text = "f(a)\ng()\nh(a,b,cd e)\n";
+
+    let err = parse(text, &rules).err().unwrap();
+    assert_eq!(err.descr, "expected one of [a-z], ',', ')'");
+    assert_eq!(err.expected, vec!["[a-z]", "','", "')'"]);
+    assert_eq!((err.pos.row, err.pos.col), (2, 8));
+    assert_eq!(err.line_before, "h(a,b,cd");
+    assert_eq!(err.line_after, " e)");
+
+    let options = Options::new().label("id");
+    let err = parse_with_options(text, &rules, &options).err().unwrap();
+    assert_eq!(err.to_string(), "expected one of ',', ')' at 3:9");
+
+    let err = parse_with_options("f(a)\nf(,)\n", &rules, &options)
+        .err()
+        .unwrap();
+    assert_eq!(err.to_string(), "expected one of id, ')' at 2:3");
+
+    let err = parse("f(a)\n)", &rules).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "expected one of [a-z], end of input at 2:1"
+    );
+}
+
+#[test]
+fn parse_recover() {
+    use crate::{ast, parse_recovering, parse_recovering_spanned, parser::Options};
+
+    let peg = r#"
+
+    main    =   (stmt _eol)*
+    stmt    =   recover(assign, _eol)
+    assign  =   id '=' [0-9]+
+    id      =   [a-z]+
+    _eol    =   "\n"
+
+    "#;
+
+    let rules = peg::rules_from_peg(peg).unwrap();
+
+    assert!(parse("a=1\nb=2\n", &rules).is_ok());
+
+    let text = "a=1\nb=\n=3\nc=4\n";
+    let err = parse(text, &rules).err().unwrap();
+    assert_eq!(err.to_string(), "expected [0-9] at 2:3");
+
+    let (ast, errors) = parse_recovering(text, &rules);
+    let errors = errors.iter().map(|e| e.to_string()).collect::<Vec<_>>();
+    assert_eq!(
+        errors,
+        vec!["expected [0-9] at 2:3", "expected [a-z] at 3:1"]
+    );
+    let ast = ast.unwrap().flatten();
+    assert!(ast.contains(&ast::flat::Node::Error("expected [a-z]".to_string())));
+
+    let (ast, _) = parse_recovering_spanned(text, &rules, &Options::new().packrat(true));
+    let errors = ast
+        .unwrap()
+        .flatten()
+        .into_iter()
+        .filter_map(|n| match n {
+            ast::spanned::flat::Node::Error((_, span)) => Some((span.start.n, span.end.n)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(errors, vec![(4, 6), (7, 9)]);
+
+    let (ast, errors) = parse_recovering("a=1\nb=2", &rules);
+    assert!(ast.is_some());
+    assert_eq!(errors.len(), 1);
+
+    assert!(peg::gcode::rust_from_rules(&rules).contains("recover!("));
+}
+
+#[test]
+fn parse_skip_until() {
+    use crate::ast::flat;
+
+    let peg = r#"
+
+    main    =   (comment / 'x')*
+    comment =   '/*' (!'*/' .)* '*/'
+
+    "#;
+
+    let rules = peg::rules_from_peg(peg).unwrap();
+    assert!(peg::gcode::rust_from_rules(&rules).contains(r#"skip_until!("*/")"#));
+
+    let ast = parse("x/* a * b /*/x/**/", &rules).unwrap().flatten();
+    let vals = ast
+        .iter()
+        .filter_map(|n| match n {
+            flat::Node::Val(v) => Some(v.as_str()),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    //  nothing skipped, no value (as the repetition)
+    assert_eq!(vals, vec!["x", "/*", " a * b /", "*/", "x", "/*", "*/"]);
+
+    //  same errors as the repetition
+    let rep_rules = rules! {
+        "main"      =>  rep!(or!(ref_rule!("comment"), lit!("x")), 0),
+        "comment"   =>  and!(lit!("/*"), rep!(and!(not!(lit!("*/")), dot!()), 0), lit!("*/"))
+    };
+    for text in &["x/* unclosed *", "x/* ok */ x", "/*"] {
+        let err = parse(text, &rules).err().unwrap();
+        let rep_err = parse(text, &rep_rules).err().unwrap();
+        assert_eq!(err.to_string(), rep_err.to_string(), "on {:?}", text);
+        assert_eq!(err.expected, rep_err.expected, "on {:?}", text);
+    }
+
+    let quoted_rep = rules! {
+        "main"  =>  and!(lit!("\""), rep!(and!(not!(lit!("\"")), dot!()), 0), lit!("\""))
+    };
+    let quoted = peg::rules_from_peg(
+        r#"
+        main    =   '"' (!'"' .)* '"'
+        "#,
+    )
+    .unwrap();
+    for text in &["\"\"", "\"a\""] {
+        assert_eq!(
+            parse(text, &quoted).unwrap(),
+            parse(text, &quoted_rep).unwrap(),
+            "on {:?}",
+            text
+        );
+    }
+}
+
+#[test]
+fn parse_natives() {
+    use crate::parser::ErrPriority;
+
+    let peg = r#"
+
+    main    =   (decl ';')+
+    decl    =   id &?is_not_reserved ':' @u16
+    id      =   [a-z]+
+
+    "#;
+
+    let rules = peg::rules_from_peg(peg)
+        .unwrap()
+        .matcher("u16", |rest| {
+            let len = rest
+                .find(|c: char| !c.is_ascii_digit())
+                .unwrap_or(rest.len());
+            rest[..len].parse::<u16>().ok().map(|_| len)
+        })
+        .predicate("is_not_reserved", |id| id != "if" && id != "else");
+
+    let code = peg::gcode::rust_from_rules(&rules);
+    assert!(code.contains(r#"matcher!("u16")"#));
+    assert!(code.contains(r#"predicate!("is_not_reserved")"#));
+
+    //  the predicate adds no node
+    let ast = parse("a:1;iff:65535;", &rules).unwrap().flatten();
+    assert!(!ast
+        .iter()
+        .any(|n| matches!(n, crate::ast::flat::Node::Val(v) if v.is_empty())));
+
+    let err = parse("a:1;if:2;", &rules).err().unwrap();
+    assert_eq!(
+        err.to_string(),
+        "expected one of [a-z], &?is_not_reserved at 1:7"
+    );
+    let err = parse("a:65536;", &rules).err().unwrap();
+    assert_eq!(err.to_string(), "expected @u16 at 1:3");
+
+    let err = parse("a:1;", &peg::rules_from_peg(peg).unwrap())
+        .err()
+        .unwrap();
+    assert_eq!(err.descr, "Missing predicate: is_not_reserved");
+    assert_eq!(err.priority, ErrPriority::Critical);
+
+    let rules = rules! { "main" => matcher!("bad") }.matcher("bad", |_| Some(1));
+    let err = parse("ñ", &rules).err().unwrap();
+    assert_eq!(err.descr, "matcher bad returned an invalid length 1");
+}
+
+#[test]
+fn parse_names_with_quotes_and_bytes() {
+    let peg = r#"
+
+    main    =   a' b' c  'x' b
+    a'      =   'a'
+    b'      =   'b'
+    c       =   "c"b
+    b       =   'y'
+
+    "#;
+
+    let rules = peg::rules_from_peg(peg).unwrap();
+    assert!(parse("abcxy", &rules).is_ok());
+    assert!(parse("abcx", &rules).is_err());
+}